@@ -0,0 +1,89 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use glam::Vec2;
+use minesweeper_roguelike_frfr::analysis::{bbbv, zini};
+use minesweeper_roguelike_frfr::config::GameConfig;
+use minesweeper_roguelike_frfr::game_objects::create_minefield;
+use minesweeper_roguelike_frfr::solver::solve;
+use minesweeper_roguelike_frfr::GameState;
+
+const SIDE: u32 = 1000;
+const SEED: u64 = 33;
+
+fn config(mines: u32) -> GameConfig {
+    GameConfig {
+        width: SIDE,
+        length: SIDE,
+        mines,
+        ..GameConfig::default()
+    }
+}
+
+// a safe tile with no mines around it, so opening it floods
+fn first_zero(game_state: &GameState) -> usize {
+    (0..game_state.board().len())
+        .find(|index| {
+            !game_state.board()[*index].has_mine() && game_state.adjacent_mines(*index) == 0
+        })
+        .expect("board has no openings")
+}
+
+fn deal(c: &mut Criterion) {
+    let expert_density = config(SIDE * SIDE / 5);
+    c.bench_function("deal 1000x1000", |b| {
+        b.iter(|| create_minefield(black_box(&expert_density), None, SEED))
+    });
+}
+
+fn flood_fill(c: &mut Criterion) {
+    // sparse mines make one opening that covers most of the board
+    let sparse = config(SIDE);
+    let game_state = create_minefield(&sparse, None, SEED);
+    let start = first_zero(&game_state);
+    c.bench_function("open 1000x1000 sparse", |b| {
+        b.iter_batched(
+            || create_minefield(&sparse, None, SEED),
+            |mut game_state| game_state.open(start).len(),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn lookups(c: &mut Criterion) {
+    let game_state = create_minefield(&config(SIDE * SIDE / 5), None, SEED);
+    c.bench_function("tile_at x1000", |b| {
+        b.iter(|| {
+            (0..1000)
+                .filter_map(|i| {
+                    let t = i as f32 / 1000.0;
+                    game_state.tile_at(black_box(Vec2::new(t, 1.0 - t)))
+                })
+                .count()
+        })
+    });
+    c.bench_function("visible_ranges zoomed in", |b| {
+        b.iter(|| game_state.visible_ranges(black_box(Vec2::new(0.4, 0.4)), Vec2::new(0.45, 0.45)))
+    });
+    c.bench_function("visible_ranges whole board", |b| {
+        b.iter(|| game_state.visible_ranges(black_box(Vec2::new(-0.2, -0.2)), Vec2::new(1.2, 1.2)))
+    });
+}
+
+fn analysis(c: &mut Criterion) {
+    let game_state = create_minefield(&config(SIDE * SIDE / 5), None, SEED);
+    c.bench_function("bbbv 1000x1000", |b| {
+        b.iter(|| bbbv(black_box(&game_state)))
+    });
+    // these take around a second each, a few samples are enough
+    let mut slow = c.benchmark_group("slow analysis");
+    slow.sample_size(10);
+    slow.bench_function("zini 1000x1000", |b| {
+        b.iter(|| zini(black_box(&game_state)))
+    });
+    slow.bench_function("solve 1000x1000", |b| {
+        b.iter(|| solve(black_box(&game_state), None))
+    });
+    slow.finish();
+}
+
+criterion_group!(benches, deal, flood_fill, lookups, analysis);
+criterion_main!(benches);
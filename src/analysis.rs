@@ -0,0 +1,447 @@
+use crate::solver::{solve, Rating, Solution};
+use crate::{GamePhase, GameState};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::time::Duration;
+
+// the results screen rates boards up to 100x100, ZiNi and the solver take seconds
+// on the biggest ones and would freeze the frame the game ends on
+const RATED_TILES: usize = 100 * 100;
+
+/// 3BV of a board: the fewest left clicks that clear it without flags or chords.
+/// `solved` only counts the parts the player has already revealed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bbbv {
+    pub total: u32,
+    pub solved: u32,
+}
+
+pub fn bbbv(game_state: &GameState) -> Bbbv {
+    let board = game_state.board();
+    let layout = Layout::new(game_state);
+    let mut result = Bbbv {
+        total: layout.openings,
+        solved: 0,
+    };
+    // an opening is solved once any of its zeros is revealed
+    let mut solved = vec![false; layout.openings as usize];
+    for (index, opening) in layout.opening.iter().enumerate() {
+        if let Some(opening) = opening {
+            if layout.is_zero[index] && board[index].clicked {
+                solved[*opening as usize] = true;
+            }
+        }
+    }
+    result.solved = solved.iter().filter(|solved| **solved).count() as u32;
+
+    // numbers that no opening reveals need a click each
+    for (index, tile) in board.iter().enumerate() {
+        if layout.is_island_number(index) {
+            result.total += 1;
+            if tile.clicked {
+                result.solved += 1;
+            }
+        }
+    }
+    result
+}
+
+// every zero and the numbers around it, labelled by which opening they belong to
+struct Layout {
+    is_zero: Vec<bool>,
+    is_mine: Vec<bool>,
+    // numbers around an opening are in it too, a number between two openings is in
+    // whichever was found first
+    opening: Vec<Option<u32>>,
+    openings: u32,
+}
+
+impl Layout {
+    fn new(game_state: &GameState) -> Layout {
+        let board = game_state.board();
+        let is_mine: Vec<bool> = board.iter().map(|tile| tile.has_mine()).collect();
+        let is_zero: Vec<bool> = (0..board.len())
+            .map(|index| !is_mine[index] && game_state.adjacent_mines(index) == 0)
+            .collect();
+        let mut opening = vec![None; board.len()];
+        let mut openings = 0;
+        // every opening is one click, found with a flood fill over zeros
+        for start in 0..board.len() {
+            if !is_zero[start] || opening[start].is_some() {
+                continue;
+            }
+            let mut stack = vec![start];
+            opening[start] = Some(openings);
+            while let Some(index) = stack.pop() {
+                if !is_zero[index] {
+                    continue;
+                }
+                for neighbor in game_state.neighbors(board[index].board_position()) {
+                    if opening[neighbor].is_none() {
+                        opening[neighbor] = Some(openings);
+                        stack.push(neighbor);
+                    }
+                }
+            }
+            openings += 1;
+        }
+        Self {
+            is_zero,
+            is_mine,
+            opening,
+            openings,
+        }
+    }
+
+    // a safe number no opening reveals
+    fn is_island_number(&self, index: usize) -> bool {
+        !self.is_mine[index] && self.opening[index].is_none()
+    }
+}
+
+/// Openings on a board, the zero areas one click floods open.
+pub fn openings(game_state: &GameState) -> u32 {
+    Layout::new(game_state).openings
+}
+
+/// Groups of touching numbers that no opening reveals, each needs clicking by hand.
+pub fn islands(game_state: &GameState) -> u32 {
+    let board = game_state.board();
+    let layout = Layout::new(game_state);
+    let mut seen = vec![false; board.len()];
+    let mut islands = 0;
+    for start in 0..board.len() {
+        if !layout.is_island_number(start) || seen[start] {
+            continue;
+        }
+        seen[start] = true;
+        let mut stack = vec![start];
+        while let Some(index) = stack.pop() {
+            for neighbor in game_state.neighbors(board[index].board_position()) {
+                if layout.is_island_number(neighbor) && !seen[neighbor] {
+                    seen[neighbor] = true;
+                    stack.push(neighbor);
+                }
+            }
+        }
+        islands += 1;
+    }
+    islands
+}
+
+/// ZiNi: clicks to clear a board when flags and chords are allowed, found greedily
+/// like the usual definition. Each step opens, flags around and chords the number
+/// that saves the most clicks, then whatever is left is clicked one by one.
+pub fn zini(game_state: &GameState) -> u32 {
+    Zini::new(game_state).run()
+}
+
+struct Zini<'a> {
+    game_state: &'a GameState,
+    layout: Layout,
+    numbers: Vec<u8>,
+    opened: Vec<bool>,
+    flagged: Vec<bool>,
+    opening_solved: Vec<bool>,
+    clicks: u32,
+}
+
+impl<'a> Zini<'a> {
+    fn new(game_state: &'a GameState) -> Zini<'a> {
+        let layout = Layout::new(game_state);
+        let len = game_state.board().len();
+        Self {
+            game_state,
+            numbers: (0..len)
+                .map(|index| game_state.adjacent_mines(index))
+                .collect(),
+            opened: vec![false; len],
+            flagged: vec![false; len],
+            opening_solved: vec![false; layout.openings as usize],
+            layout,
+            clicks: 0,
+        }
+    }
+
+    fn neighbors(&self, index: usize) -> Vec<usize> {
+        self.game_state
+            .neighbors(self.game_state.board()[index].board_position())
+    }
+
+    fn run(mut self) -> u32 {
+        let mut heap: BinaryHeap<(i64, Reverse<usize>)> = (0..self.opened.len())
+            .filter_map(|index| Some((self.premium(index)?, Reverse(index))))
+            .collect();
+        while let Some((premium, Reverse(index))) = heap.pop() {
+            // an older entry, the cell was pushed again when it changed
+            if self.premium(index) != Some(premium) {
+                continue;
+            }
+            if premium <= 0 {
+                break;
+            }
+            let changed = self.chord(index);
+            let mut affected: Vec<usize> = changed
+                .iter()
+                .flat_map(|cell| self.neighbors(*cell).into_iter().chain([*cell]))
+                .collect();
+            affected.sort_unstable();
+            affected.dedup();
+            for cell in affected {
+                if let Some(premium) = self.premium(cell) {
+                    heap.push((premium, Reverse(cell)));
+                }
+            }
+        }
+        // what the chords didn't reach takes a click each
+        let openings_left = self
+            .opening_solved
+            .iter()
+            .filter(|solved| !**solved)
+            .count();
+        let islands_left = (0..self.opened.len())
+            .filter(|index| self.layout.is_island_number(*index) && !self.opened[*index])
+            .count();
+        self.clicks + (openings_left + islands_left) as u32
+    }
+
+    // clicks a chord on this number would save, None for tiles that can't be chorded
+    fn premium(&self, index: usize) -> Option<i64> {
+        if self.layout.is_mine[index] || self.numbers[index] == 0 {
+            return None;
+        }
+        let mut cost = 1;
+        let mut gain = 0;
+        if !self.opened[index] {
+            cost += 1;
+            if self.layout.is_island_number(index) {
+                gain += 1;
+            }
+        }
+        let mut openings = Vec::new();
+        for neighbor in self.neighbors(index) {
+            if self.layout.is_mine[neighbor] {
+                if !self.flagged[neighbor] {
+                    cost += 1;
+                }
+            } else if !self.opened[neighbor] {
+                if self.layout.is_zero[neighbor] {
+                    if let Some(opening) = self.layout.opening[neighbor] {
+                        if !self.opening_solved[opening as usize] {
+                            openings.push(opening);
+                        }
+                    }
+                } else if self.layout.is_island_number(neighbor) {
+                    gain += 1;
+                }
+            }
+        }
+        openings.sort_unstable();
+        openings.dedup();
+        Some(gain + openings.len() as i64 - cost)
+    }
+
+    // opens the number, flags its mines and chords it, returns every tile that changed
+    fn chord(&mut self, index: usize) -> Vec<usize> {
+        let mut changed = Vec::new();
+        if !self.opened[index] {
+            self.clicks += 1;
+            self.open(index, &mut changed);
+        }
+        for neighbor in self.neighbors(index) {
+            if self.layout.is_mine[neighbor] {
+                if !self.flagged[neighbor] {
+                    self.clicks += 1;
+                    self.flagged[neighbor] = true;
+                    changed.push(neighbor);
+                }
+            } else {
+                self.open(neighbor, &mut changed);
+            }
+        }
+        self.clicks += 1;
+        changed
+    }
+
+    fn open(&mut self, index: usize, changed: &mut Vec<usize>) {
+        let mut stack = vec![index];
+        while let Some(index) = stack.pop() {
+            if self.opened[index] {
+                continue;
+            }
+            self.opened[index] = true;
+            changed.push(index);
+            if self.layout.is_zero[index] {
+                if let Some(opening) = self.layout.opening[index] {
+                    self.opening_solved[opening as usize] = true;
+                }
+                stack.extend(self.neighbors(index));
+            }
+        }
+    }
+}
+
+/// The standard metrics of a board's mine layout, whatever has been played on it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BoardAnalysis {
+    pub bbbv: u32,
+    pub openings: u32,
+    pub islands: u32,
+    pub zini: u32,
+    pub solution: Solution,
+}
+
+impl BoardAnalysis {
+    pub fn new(game_state: &GameState) -> BoardAnalysis {
+        Self {
+            bbbv: bbbv(game_state).total,
+            openings: openings(game_state),
+            islands: islands(game_state),
+            zini: zini(game_state),
+            solution: solve(game_state, None),
+        }
+    }
+
+    pub fn rating(&self) -> Rating {
+        self.solution.rating()
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        vec![
+            format!(
+                "OPENINGS: {}  ISLANDS: {}  ZINI: {}",
+                self.openings, self.islands, self.zini
+            ),
+            format!(
+                "DIFFICULTY: {} ({} GUESSES)",
+                self.rating().label(),
+                self.solution.guesses
+            ),
+        ]
+    }
+}
+
+/// Summary of a finished (or abandoned) game for the results screen.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameStats {
+    pub outcome: GamePhase,
+    pub time: Duration,
+    pub bbbv: Bbbv,
+    pub clicks: u32,
+    pub flags: u32,
+    pub seed: u64,
+    // None on boards too big to rate while the game waits, `solve` still does them
+    pub board: Option<BoardAnalysis>,
+}
+
+impl GameStats {
+    pub fn from_game(game_state: &GameState) -> GameStats {
+        Self {
+            outcome: game_state.phase(),
+            time: game_state.elapsed(),
+            bbbv: bbbv(game_state),
+            clicks: game_state.clicks(),
+            flags: game_state.flag_count(),
+            seed: game_state.seed(),
+            board: (game_state.board().len() <= RATED_TILES)
+                .then(|| BoardAnalysis::new(game_state)),
+        }
+    }
+
+    pub fn bbbv_per_second(&self) -> f32 {
+        let seconds = self.time.as_secs_f32();
+        if seconds > 0.0 {
+            self.bbbv.solved as f32 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    /// Solved 3BV over clicks, 100% is a perfect game without chording.
+    pub fn efficiency(&self) -> f32 {
+        if self.clicks > 0 {
+            self.bbbv.solved as f32 / self.clicks as f32 * 100.0
+        } else {
+            0.0
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        vec![
+            format!("TIME: {:.2}S", self.time.as_secs_f32()),
+            format!("3BV: {}/{}", self.bbbv.solved, self.bbbv.total),
+            format!("3BV/S: {:.2}", self.bbbv_per_second()),
+            format!("CLICKS: {}", self.clicks),
+            format!("EFFICIENCY: {:.0}%", self.efficiency()),
+            format!("FLAGS: {}", self.flags),
+            format!("SEED: {}", self.seed),
+        ]
+        .into_iter()
+        .chain(match &self.board {
+            Some(board) => board.lines(),
+            None => vec![String::from("TOO BIG TO RATE HERE")],
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CommonMineState, Mines, Tiles};
+    use glam::Vec2;
+
+    // rows from the top down, '*' is a mine
+    fn board(rows: &[&str]) -> GameState {
+        let length = rows.len() as u32;
+        let width = rows[0].len() as u32;
+        let mut tiles = Vec::new();
+        for row in 0..length {
+            for (col, cell) in rows[(length - 1 - row) as usize].chars().enumerate() {
+                let mine = (cell == '*').then_some(Mines::Default(CommonMineState {
+                    active: false,
+                    mine_index: tiles.len() as u32,
+                }));
+                let position = Vec2::new(col as f32, row as f32);
+                tiles.push(Tiles::new(position, position, mine, 1.0));
+            }
+        }
+        GameState::new(tiles, width, length, 0)
+    }
+
+    #[test]
+    fn counts_openings_and_islands() {
+        let game_state = board(&["....*.", "....*.", "*****.", "......", "*.*..."]);
+        // the top left zeros, and the bottom right ones
+        assert_eq!(openings(&game_state), 2);
+        // the top right column and the tile between the bottom mines
+        assert_eq!(islands(&game_state), 2);
+        let bbbv = bbbv(&game_state);
+        assert_eq!(bbbv.solved, 0);
+        // the 2 openings and 7 numbers no zero touches, 3 on the right and 4 at the bottom
+        assert_eq!(bbbv.total, 9);
+    }
+
+    #[test]
+    fn zini_chords_what_left_clicks_would_not() {
+        // one mine in the middle, the eight numbers around it are 3BV 8
+        let game_state = board(&["...", ".*.", "..."]);
+        assert_eq!(bbbv(&game_state).total, 8);
+        // open an edge, flag the mine and chord it, then chord the sides it opened
+        assert_eq!(zini(&game_state), 5);
+        let empty = board(&["....", "...."]);
+        assert_eq!(zini(&empty), 1);
+    }
+
+    #[test]
+    fn only_small_boards_are_rated_when_a_game_ends() {
+        let small = GameStats::from_game(&board(&["...", ".*.", "..."]));
+        assert_eq!(small.board.map(|board| board.bbbv), Some(8));
+        let row = format!("*{}", ".".repeat(100));
+        let big = GameStats::from_game(&board(&vec![row.as_str(); 100]));
+        assert_eq!(big.board, None);
+        // 3BV is cheap, it still counts: one opening with the numbers along its edge
+        assert_eq!(big.bbbv.total, 1);
+        assert!(big.lines().contains(&String::from("TOO BIG TO RATE HERE")));
+    }
+}
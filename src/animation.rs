@@ -0,0 +1,246 @@
+use crate::TileVisual;
+use glam::Vec2;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+const FLIP_TIME: f32 = 0.16;
+// seconds between rings of a cascade, shrunk so big openings still finish quickly
+const WAVE_STEP: f32 = 0.03;
+const MAX_WAVE_TIME: f32 = 0.6;
+const FLAG_DROP_TIME: f32 = 0.22;
+const EXPLOSION_TIME: f32 = 0.5;
+const SHAKE_TIME: f32 = 0.4;
+// fraction of the view height
+const SHAKE_STRENGTH: f32 = 0.015;
+// past this a cascade just pops in, animating it would cost more than it shows
+const MAX_ANIMATED_TILES: usize = 20_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AnimationKind {
+    // tile turns over from covered to whatever is under it
+    Flip,
+    // flag falls onto the tile from above
+    FlagDrop,
+    // mine swells and the screen shakes
+    Explosion,
+}
+
+impl AnimationKind {
+    fn duration(&self) -> f32 {
+        match self {
+            AnimationKind::Flip => FLIP_TIME,
+            AnimationKind::FlagDrop => FLAG_DROP_TIME,
+            AnimationKind::Explosion => EXPLOSION_TIME,
+        }
+    }
+}
+
+struct TileAnimation {
+    kind: AnimationKind,
+    // time left before it starts, for waves
+    delay: f32,
+    elapsed: f32,
+}
+
+impl TileAnimation {
+    fn progress(&self) -> f32 {
+        (self.elapsed / self.kind.duration()).clamp(0.0, 1.0)
+    }
+}
+
+/// Extra sprite drawn over a tile while it animates, offset and size are in tile sizes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Overlay {
+    pub sprite: &'static str,
+    pub offset: Vec2,
+    pub scale: f32,
+}
+
+/// How to draw an animating tile this frame instead of its plain state.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileFrame {
+    // shown instead of the real state, e.g. still covered half way through a flip
+    pub visual: Option<TileVisual>,
+    pub scale: Vec2,
+    pub overlay: Option<Overlay>,
+}
+
+/// Time driven tile animations and screen shake. Purely visual, the game state is
+/// already final when an animation starts, so skipping one never changes the game.
+pub struct Animations {
+    enabled: bool,
+    tiles: HashMap<usize, TileAnimation>,
+    shake: f32,
+}
+
+impl Animations {
+    pub fn new(enabled: bool) -> Animations {
+        Self {
+            enabled,
+            tiles: HashMap::new(),
+            shake: 0.0,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) -> Vec<usize> {
+        self.enabled = enabled;
+        if enabled {
+            Vec::new()
+        } else {
+            self.skip()
+        }
+    }
+
+    pub fn is_busy(&self) -> bool {
+        !self.tiles.is_empty() || self.is_shaking()
+    }
+
+    pub fn is_shaking(&self) -> bool {
+        self.shake > 0.0
+    }
+
+    /// Flips tiles in a wave going out from `origin`, tiles come as (index, board position).
+    pub fn start_wave(&mut self, origin: Vec2, tiles: &[(usize, Vec2)]) {
+        if !self.enabled || tiles.len() > MAX_ANIMATED_TILES {
+            return;
+        }
+        let furthest = tiles
+            .iter()
+            .map(|(_, position)| position.distance(origin))
+            .fold(0.0, f32::max);
+        let step = if furthest > 0.0 {
+            WAVE_STEP.min(MAX_WAVE_TIME / furthest)
+        } else {
+            WAVE_STEP
+        };
+        for (index, position) in tiles {
+            self.start(
+                *index,
+                AnimationKind::Flip,
+                position.distance(origin) * step,
+            );
+        }
+    }
+
+    pub fn start_flag_drop(&mut self, index: usize) {
+        if self.enabled {
+            self.start(index, AnimationKind::FlagDrop, 0.0);
+        }
+    }
+
+    pub fn start_explosion(&mut self, index: usize) {
+        if self.enabled {
+            self.start(index, AnimationKind::Explosion, 0.0);
+            self.shake = SHAKE_TIME;
+        }
+    }
+
+    fn start(&mut self, index: usize, kind: AnimationKind, delay: f32) {
+        self.tiles.insert(
+            index,
+            TileAnimation {
+                kind,
+                delay,
+                elapsed: 0.0,
+            },
+        );
+    }
+
+    /// Moves every animation forward, returns the tiles that have to be redrawn.
+    pub fn advance(&mut self, delta: f32) -> Vec<usize> {
+        self.shake = (self.shake - delta).max(0.0);
+        let mut changed = Vec::new();
+        self.tiles.retain(|index, animation| {
+            if animation.delay > 0.0 {
+                animation.delay -= delta;
+                if animation.delay > 0.0 {
+                    return true;
+                }
+                animation.elapsed = -animation.delay;
+            } else {
+                animation.elapsed += delta;
+            }
+            changed.push(*index);
+            animation.elapsed < animation.kind.duration()
+        });
+        changed
+    }
+
+    /// Finishes everything at once, returns the tiles that have to be redrawn.
+    pub fn skip(&mut self) -> Vec<usize> {
+        self.shake = 0.0;
+        self.tiles.drain().map(|(index, _)| index).collect()
+    }
+
+    pub fn frame(&self, index: usize) -> Option<TileFrame> {
+        let animation = self.tiles.get(&index)?;
+        let progress = animation.progress();
+        let frame = match animation.kind {
+            AnimationKind::Flip => {
+                // squash to nothing showing the cover, then grow back showing the tile
+                let turned = progress >= 0.5;
+                TileFrame {
+                    visual: if turned {
+                        None
+                    } else {
+                        Some(TileVisual::Covered)
+                    },
+                    scale: Vec2::new((progress * PI).cos().abs(), 1.0),
+                    overlay: None,
+                }
+            }
+            AnimationKind::FlagDrop => TileFrame {
+                visual: Some(TileVisual::Covered),
+                scale: Vec2::ONE,
+                overlay: Some(Overlay {
+                    sprite: "Flags",
+                    offset: Vec2::new(0.0, 1.5 * (1.0 - ease_out_bounce(progress))),
+                    scale: 1.0,
+                }),
+            },
+            AnimationKind::Explosion => TileFrame {
+                visual: None,
+                scale: Vec2::splat(1.0 + 0.5 * (progress * PI).sin()),
+                overlay: None,
+            },
+        };
+        Some(frame)
+    }
+
+    /// Tiles with an overlay this frame.
+    pub fn overlays(&self) -> Vec<(usize, Overlay)> {
+        self.tiles
+            .keys()
+            .filter_map(|index| Some((*index, self.frame(*index)?.overlay?)))
+            .collect()
+    }
+
+    /// Camera offset for screen shake, as a fraction of the view height.
+    pub fn shake_offset(&self) -> Vec2 {
+        if !self.is_shaking() {
+            return Vec2::ZERO;
+        }
+        let strength = SHAKE_STRENGTH * self.shake / SHAKE_TIME;
+        Vec2::new(
+            rand::random::<f32>() * 2.0 - 1.0,
+            rand::random::<f32>() * 2.0 - 1.0,
+        ) * strength
+    }
+}
+
+fn ease_out_bounce(t: f32) -> f32 {
+    const N: f32 = 7.5625;
+    const D: f32 = 2.75;
+    if t < 1.0 / D {
+        N * t * t
+    } else if t < 2.0 / D {
+        let t = t - 1.5 / D;
+        N * t * t + 0.75
+    } else if t < 2.5 / D {
+        let t = t - 2.25 / D;
+        N * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / D;
+        N * t * t + 0.984375
+    }
+}
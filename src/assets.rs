@@ -0,0 +1,334 @@
+use crate::text::stamp_glyph;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Folder that overrides the embedded assets, files in it win over the built in ones.
+pub const ASSET_DIR_ENV: &str = "MINESWEEPER_ASSETS";
+
+const PLACEHOLDER_SIZE: u32 = 1024;
+// the default theme's grid
+const PLACEHOLDER_GRID: u32 = 32;
+const PLACEHOLDER_CELL: u32 = PLACEHOLDER_SIZE / PLACEHOLDER_GRID;
+
+// everything the game needs to start, keyed by its path from the repo root
+const EMBEDDED: &[(&str, &[u8])] = &[
+    (
+        "src/sprites/spritesheet1.png",
+        include_bytes!("sprites/spritesheet1.png"),
+    ),
+    (
+        "themes/default/theme.toml",
+        include_bytes!("../themes/default/theme.toml"),
+    ),
+    (
+        "themes/midnight/theme.toml",
+        include_bytes!("../themes/midnight/theme.toml"),
+    ),
+    ("masks/heart.txt", include_bytes!("../masks/heart.txt")),
+    (
+        "puzzles/starter.toml",
+        include_bytes!("../puzzles/starter.toml"),
+    ),
+];
+
+#[derive(Debug)]
+pub enum AssetError {
+    // not in the override folder, not embedded and not on disk
+    Missing(String),
+    // found but couldn't be parsed or used
+    Malformed {
+        path: String,
+        reason: String,
+    },
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+}
+
+impl AssetError {
+    pub fn malformed(path: &str, reason: impl fmt::Display) -> AssetError {
+        AssetError::Malformed {
+            path: String::from(path),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::Missing(path) => write!(f, "asset '{}' not found", path),
+            AssetError::Malformed { path, reason } => {
+                write!(f, "asset '{}' is malformed: {}", path, reason)
+            }
+            AssetError::Io { path, source } => write!(f, "reading asset '{}': {}", path, source),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AssetError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Finds assets in the override folder first, then the ones built into the binary,
+/// then plain paths on disk (for files the player points at, like their own masks).
+pub struct AssetLocator {
+    override_dir: Option<PathBuf>,
+}
+
+static LOCATOR: OnceLock<AssetLocator> = OnceLock::new();
+
+/// Sets the override folder, has to happen before the first asset is loaded.
+/// Returns false if the locator was already set up.
+pub fn init(override_dir: Option<PathBuf>) -> bool {
+    LOCATOR.set(AssetLocator { override_dir }).is_ok()
+}
+
+/// The locator, falling back to the MINESWEEPER_ASSETS folder if `init` wasn't called.
+pub fn locator() -> &'static AssetLocator {
+    LOCATOR.get_or_init(|| AssetLocator {
+        override_dir: std::env::var_os(ASSET_DIR_ENV).map(PathBuf::from),
+    })
+}
+
+impl AssetLocator {
+    pub fn read(&self, path: &str) -> Result<Vec<u8>, AssetError> {
+        let key = normalize(path);
+        if let Some(file) = self.override_path(&key) {
+            return std::fs::read(&file).map_err(|source| AssetError::Io {
+                path: key.clone(),
+                source,
+            });
+        }
+        if let Some((_, bytes)) = EMBEDDED.iter().find(|(name, _)| *name == key) {
+            return Ok(bytes.to_vec());
+        }
+        match std::fs::read(path) {
+            Ok(bytes) => Ok(bytes),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(AssetError::Missing(key)),
+            Err(source) => Err(AssetError::Io { path: key, source }),
+        }
+    }
+
+    pub fn read_to_string(&self, path: &str) -> Result<String, AssetError> {
+        String::from_utf8(self.read(path)?).map_err(|e| AssetError::malformed(path, e))
+    }
+
+    /// Where an asset lives on disk, None for embedded ones. Only files on disk can be watched.
+    pub fn disk_path(&self, path: &str) -> Option<PathBuf> {
+        let key = normalize(path);
+        self.override_path(&key).or_else(|| {
+            let embedded = EMBEDDED.iter().any(|(name, _)| *name == key);
+            (!embedded && Path::new(path).is_file()).then(|| PathBuf::from(path))
+        })
+    }
+
+    /// Files directly inside an asset folder, embedded and overridden ones together.
+    pub fn list(&self, folder: &str) -> Vec<String> {
+        let folder = normalize(folder);
+        let mut files: Vec<String> = EMBEDDED
+            .iter()
+            .map(|(name, _)| *name)
+            .filter(|name| Path::new(name).parent() == Some(Path::new(&folder)))
+            .map(String::from)
+            .collect();
+        if let Some(dir) = self.override_join(&folder) {
+            if let Ok(entries) = std::fs::read_dir(dir) {
+                for entry in entries.filter_map(|entry| entry.ok()) {
+                    if entry.path().is_file() {
+                        files.push(format!(
+                            "{}/{}",
+                            folder,
+                            entry.file_name().to_string_lossy()
+                        ));
+                    }
+                }
+            }
+        }
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    /// Folders directly inside an asset folder, embedded and overridden ones together.
+    pub fn list_folders(&self, folder: &str) -> Vec<String> {
+        let folder = normalize(folder);
+        let mut folders: Vec<String> = EMBEDDED
+            .iter()
+            .filter_map(|(name, _)| {
+                let rest = name.strip_prefix(&folder)?.strip_prefix('/')?;
+                let (child, _) = rest.split_once('/')?;
+                Some(format!("{}/{}", folder, child))
+            })
+            .collect();
+        if let Some(dir) = self.override_join(&folder) {
+            if let Ok(entries) = std::fs::read_dir(dir) {
+                for entry in entries.filter_map(|entry| entry.ok()) {
+                    if entry.path().is_dir() {
+                        folders.push(format!(
+                            "{}/{}",
+                            folder,
+                            entry.file_name().to_string_lossy()
+                        ));
+                    }
+                }
+            }
+        }
+        folders.sort();
+        folders.dedup();
+        folders
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        let key = normalize(path);
+        self.override_path(&key).is_some()
+            || EMBEDDED.iter().any(|(name, _)| *name == key)
+            || Path::new(path).is_file()
+    }
+
+    fn override_path(&self, key: &str) -> Option<PathBuf> {
+        let file = self.override_join(key)?;
+        file.is_file().then_some(file)
+    }
+
+    // a normalized key inside the override folder, keys that would climb out of it
+    // or replace it with an absolute path have no place there
+    fn override_join(&self, key: &str) -> Option<PathBuf> {
+        if key.starts_with('/') || key == ".." || key.starts_with("../") {
+            return None;
+        }
+        Some(self.override_dir.as_ref()?.join(key))
+    }
+}
+
+/// Forward slashes and no `.` or `..`, so "themes/default/../../src/a.png" is "src/a.png".
+/// `..` that climbs above the start is kept, absolute paths keep their leading `/`.
+pub fn normalize(path: &str) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::ParentDir => match parts.last().map(String::as_str) {
+                None | Some("..") => parts.push(String::from("..")),
+                // there's nothing above the root
+                Some("") => {}
+                Some(_) => {
+                    parts.pop();
+                }
+            },
+            Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+            Component::CurDir => {}
+            // an empty first part, joined it's the leading `/`
+            Component::RootDir | Component::Prefix(_) => {
+                if parts.is_empty() {
+                    parts.push(String::new());
+                }
+            }
+        }
+    }
+    parts.join("/")
+}
+
+/// A plain atlas drawn in code, so the game still starts when the real one is
+/// missing or broken. Uses the same layout as themes/default/theme.toml.
+pub fn placeholder_atlas() -> image::RgbaImage {
+    let mut atlas = image::RgbaImage::new(PLACEHOLDER_SIZE, PLACEHOLDER_SIZE);
+    // Square, Circle (a square will do), Mines and Tiles
+    fill_cell(&mut atlas, (0, 0), 0, [255, 255, 255, 255]);
+    fill_cell(&mut atlas, (1, 0), 0, [255, 255, 255, 255]);
+    fill_cell(&mut atlas, (0, 1), 0, [255, 255, 255, 255]);
+    fill_cell(&mut atlas, (0, 1), PLACEHOLDER_CELL / 4, [20, 20, 20, 255]);
+    fill_cell(&mut atlas, (0, 2), 0, [217, 217, 217, 255]);
+    fill_cell(
+        &mut atlas,
+        (0, 2),
+        PLACEHOLDER_CELL / 16,
+        [157, 157, 157, 255],
+    );
+    // Flags, on a transparent background like the real one
+    fill_cell(&mut atlas, (2, 1), PLACEHOLDER_CELL / 4, [220, 30, 30, 255]);
+    let digits = [
+        [70, 90, 200, 255],
+        [60, 170, 70, 255],
+        [230, 80, 100, 255],
+        [40, 40, 90, 255],
+        [90, 30, 30, 255],
+        [40, 80, 80, 255],
+        [20, 20, 20, 255],
+        [200, 110, 200, 255],
+    ];
+    for (digit, color) in digits.iter().enumerate() {
+        let cell_x = 2 + digit as u32;
+        fill_cell(&mut atlas, (cell_x, 0), 0, [255, 255, 255, 255]);
+        stamp_glyph(
+            &mut atlas,
+            char::from(b'1' + digit as u8),
+            (cell_x, 0),
+            image::Rgba(*color),
+            PLACEHOLDER_GRID,
+        );
+    }
+    atlas
+}
+
+// fills a cell of the placeholder's grid, leaving `inset` pixels around the edge
+fn fill_cell(
+    atlas: &mut image::RgbaImage,
+    (cell_x, cell_y): (u32, u32),
+    inset: u32,
+    color: [u8; 4],
+) {
+    let cell = atlas.width() / PLACEHOLDER_GRID;
+    for y in inset..cell - inset {
+        for x in inset..cell - inset {
+            atlas.put_pixel(cell_x * cell + x, cell_y * cell + y, image::Rgba(color));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_only_takes_back_folders_it_went_into() {
+        assert_eq!(normalize("themes/default/../../src/a.png"), "src/a.png");
+        assert_eq!(normalize("./masks//heart.txt"), "masks/heart.txt");
+        assert_eq!(normalize("../.."), "../..");
+        assert_eq!(normalize("a/../../b"), "../b");
+        assert_eq!(normalize("/a/../../b"), "/b");
+    }
+
+    #[test]
+    fn override_then_embedded_then_missing() {
+        let root = std::env::temp_dir().join(format!("minesweeper-assets-{}", std::process::id()));
+        let dir = root.join("assets");
+        std::fs::create_dir_all(dir.join("masks")).unwrap();
+        std::fs::write(dir.join("masks/heart.txt"), "#\n").unwrap();
+        std::fs::write(root.join("secret.txt"), "outside").unwrap();
+        let locator = AssetLocator {
+            override_dir: Some(dir),
+        };
+
+        assert_eq!(locator.read("masks/heart.txt").unwrap(), b"#\n");
+        assert_eq!(
+            locator.read("puzzles/starter.toml").unwrap(),
+            include_bytes!("../puzzles/starter.toml")
+        );
+        assert!(matches!(
+            locator.read("masks/nope.txt"),
+            Err(AssetError::Missing(_))
+        ));
+        // nothing outside the override folder is found through it
+        assert!(locator.override_path("../secret.txt").is_none());
+        assert!(locator.read("../secret.txt").is_err());
+        assert!(locator.list_folders("..").is_empty());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,28 @@
+use clap::Parser;
+use minesweeper_roguelike_frfr::cli::Options;
+use minesweeper_roguelike_frfr::tui;
+use std::path::PathBuf;
+
+/// Minesweeper in the terminal. Reads the same flags and config file as the window,
+/// the window only ones (theme, window size, fullscreen, replay) are left alone.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// toml file with any of the options, flags win over it [default: minesweeper.toml]
+    #[arg(long)]
+    config: Option<PathBuf>,
+    #[command(flatten)]
+    options: Options,
+}
+
+fn main() {
+    let args = Args::parse();
+    let result = Options::load(args.config.as_deref())
+        .map(|file| args.options.or(file))
+        .and_then(|options| Ok((options.game_config()?, options.seed)))
+        .and_then(|(config, seed)| tui::run(config, seed));
+    if let Err(e) = result {
+        eprintln!("Error: {:?}", e);
+        std::process::exit(1);
+    }
+}
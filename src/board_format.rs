@@ -0,0 +1,645 @@
+use crate::board_mask::BoardMask;
+use crate::config::MAX_SIDE;
+use crate::game_objects::minefield_from_layout;
+use crate::{GameState, Mines};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+/// Bumped whenever the json board format changes.
+pub const BOARD_FORMAT_VERSION: u32 = 1;
+const JSON_FORMAT_NAME: &str = "minesweeper board";
+/// Where imported and exported boards live.
+pub const BOARD_DIR: &str = "boards";
+/// How many boards the import screen lists.
+pub const LISTED_BOARDS: usize = 5;
+
+// grid characters, the hole is ours, other tools only have mines and safe tiles
+const GRID_MINE: char = '*';
+const GRID_SAFE: char = '.';
+const GRID_HOLE: char = '_';
+
+/// File formats a board layout can be read from and written to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BoardFormat {
+    // rows of '*' and '.' from the top down, '_' for holes
+    Grid,
+    // "width height mines" then one "x y" line per mine, (0, 0) is the top left
+    Coordinates,
+    // ours, with holes and mine kinds
+    Json,
+}
+
+impl BoardFormat {
+    pub const ALL: [BoardFormat; 3] = [
+        BoardFormat::Grid,
+        BoardFormat::Coordinates,
+        BoardFormat::Json,
+    ];
+
+    /// Picks the format from a file extension: .txt, .mines or .json.
+    pub fn from_path(path: &Path) -> Option<BoardFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|format| format.extension() == extension)
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            BoardFormat::Grid => "txt",
+            BoardFormat::Coordinates => "mines",
+            BoardFormat::Json => "json",
+        }
+    }
+}
+
+/// Where and why a board file couldn't be read. Lines and columns count from 1,
+/// 0 means the problem isn't at any one place.
+#[derive(Debug, PartialEq, Eq)]
+pub struct FormatError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl FormatError {
+    fn at(line: usize, column: usize, message: impl fmt::Display) -> FormatError {
+        Self {
+            line,
+            column,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.column) {
+            (0, _) => write!(f, "{}", self.message),
+            (line, 0) => write!(f, "line {}: {}", line, self.message),
+            (line, column) => write!(f, "line {}, column {}: {}", line, column, self.message),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+/// Reads a board, the mines are where the file says and nothing is revealed.
+pub fn parse(text: &str, format: BoardFormat) -> Result<GameState, FormatError> {
+    match format {
+        BoardFormat::Grid => parse_grid(text),
+        BoardFormat::Coordinates => parse_coordinates(text),
+        BoardFormat::Json => parse_json(text),
+    }
+}
+
+/// Writes a board's layout, whatever has been played on it is left out.
+/// The coordinate list has no holes, so boards with a shape can't be written to it.
+pub fn write(game_state: &GameState, format: BoardFormat) -> Result<String, FormatError> {
+    match format {
+        BoardFormat::Grid => Ok(write_grid(game_state)),
+        BoardFormat::Coordinates => write_coordinates(game_state),
+        BoardFormat::Json => Ok(write_json(game_state)),
+    }
+}
+
+/// Reads a board file, the format comes from its extension.
+pub fn import(path: &Path) -> anyhow::Result<GameState> {
+    let format = BoardFormat::from_path(path).ok_or_else(|| {
+        anyhow::anyhow!("'{}' isn't a .txt, .mines or .json board", path.display())
+    })?;
+    let text = std::fs::read_to_string(path)?;
+    parse(&text, format).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+}
+
+/// Writes a board file, the format comes from its extension.
+pub fn export(game_state: &GameState, path: &Path) -> anyhow::Result<()> {
+    let format = BoardFormat::from_path(path).ok_or_else(|| {
+        anyhow::anyhow!("'{}' isn't a .txt, .mines or .json board", path.display())
+    })?;
+    if let Some(folder) = path.parent() {
+        std::fs::create_dir_all(folder)?;
+    }
+    std::fs::write(path, write(game_state, format)?)?;
+    Ok(())
+}
+
+/// Board files in the boards folder, sorted by name.
+pub fn saved_boards() -> Vec<std::path::PathBuf> {
+    let Ok(entries) = std::fs::read_dir(BOARD_DIR) else {
+        return Vec::new();
+    };
+    let mut boards: Vec<std::path::PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| BoardFormat::from_path(path).is_some())
+        .collect();
+    boards.sort();
+    boards
+}
+
+// the tile count of a board, if it has tiles and fits the settings' size limit
+fn tile_count(width: u32, length: u32) -> Result<usize, String> {
+    if width == 0 || length == 0 {
+        return Err(String::from("the board has no tiles"));
+    }
+    if width > MAX_SIDE || length > MAX_SIDE {
+        return Err(format!(
+            "the board is {}x{}, sides go up to {}",
+            width, length, MAX_SIDE
+        ));
+    }
+    (width as usize)
+        .checked_mul(length as usize)
+        .ok_or_else(|| format!("the board is {}x{}, that's too many tiles", width, length))
+}
+
+// builds the board from bottom up cells, checking there's something to play
+fn from_cells(
+    width: u32,
+    length: u32,
+    holes: Option<Vec<bool>>,
+    mines: &[(u32, u32)],
+) -> Result<GameState, FormatError> {
+    let mask = match holes {
+        Some(cells) if cells.contains(&false) => {
+            Some(BoardMask::new(width, length, cells).map_err(|e| FormatError::at(0, 0, e))?)
+        }
+        _ => None,
+    };
+    let game_state = minefield_from_layout(width, length, mask.as_ref(), mines, 0);
+    if game_state.board().iter().all(|tile| tile.has_mine()) {
+        return Err(FormatError::at(0, 0, "the board has no safe tiles"));
+    }
+    Ok(game_state)
+}
+
+fn parse_grid(text: &str) -> Result<GameState, FormatError> {
+    // (line number, line), blank lines around the grid are fine
+    let rows: Vec<(usize, &str)> = text
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim_end()))
+        .filter(|(_, line)| !line.is_empty())
+        .collect();
+    let Some((_, first)) = rows.first() else {
+        return Err(FormatError::at(0, 0, "the board is empty"));
+    };
+    let width = first.chars().count();
+    let length = rows.len();
+    let size = |side: usize| u32::try_from(side).unwrap_or(u32::MAX);
+    let tiles = tile_count(size(width), size(length)).map_err(|e| FormatError::at(0, 0, e))?;
+    let mut cells = vec![true; tiles];
+    let mut mines = Vec::new();
+    for (row_index, (line, row)) in rows.iter().enumerate() {
+        if row.chars().count() != width {
+            return Err(FormatError::at(
+                *line,
+                row.chars().count().min(width) + 1,
+                format!(
+                    "row is {} wide, the first row is {}",
+                    row.chars().count(),
+                    width
+                ),
+            ));
+        }
+        // the first row is the top of the board
+        let board_row = (length - 1 - row_index) as u32;
+        for (col, character) in row.chars().enumerate() {
+            match character {
+                GRID_MINE => mines.push((col as u32, board_row)),
+                GRID_SAFE => {}
+                GRID_HOLE => cells[col + board_row as usize * width] = false,
+                other => {
+                    return Err(FormatError::at(
+                        *line,
+                        col + 1,
+                        format!(
+                            "unexpected '{}', expected '{}', '{}' or '{}'",
+                            other, GRID_MINE, GRID_SAFE, GRID_HOLE
+                        ),
+                    ))
+                }
+            }
+        }
+    }
+    from_cells(width as u32, length as u32, Some(cells), &mines)
+}
+
+fn write_grid(game_state: &GameState) -> String {
+    let mut text = String::new();
+    for row in (0..game_state.length).rev() {
+        for col in 0..game_state.width {
+            let tile = game_state.tile_index(glam::Vec2::new(col as f32, row as f32));
+            text.push(match tile {
+                Some(index) if game_state.board[index].has_mine() => GRID_MINE,
+                Some(_) => GRID_SAFE,
+                None => GRID_HOLE,
+            });
+        }
+        text.push('\n');
+    }
+    text
+}
+
+// the next whitespace separated number on a line, with the column it starts at
+fn number(line: usize, words: &mut Vec<(usize, &str)>, what: &str) -> Result<u32, FormatError> {
+    if words.is_empty() {
+        return Err(FormatError::at(line, 0, format!("missing {}", what)));
+    }
+    let (column, word) = words.remove(0);
+    word.parse()
+        .map_err(|_| FormatError::at(line, column, format!("{} '{}' isn't a number", what, word)))
+}
+
+// words of a line with their 1 based columns, '#' starts a comment
+fn words(line: &str) -> Vec<(usize, &str)> {
+    let line = line.split('#').next().unwrap_or("");
+    let mut words = Vec::new();
+    let mut start = None;
+    for (index, character) in line.char_indices().chain([(line.len(), ' ')]) {
+        match (character.is_whitespace(), start) {
+            (false, None) => start = Some(index),
+            (true, Some(begin)) => {
+                words.push((line[..begin].chars().count() + 1, &line[begin..index]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
+
+fn parse_coordinates(text: &str) -> Result<GameState, FormatError> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, words(line)))
+        .filter(|(_, words)| !words.is_empty());
+    let Some((header_line, mut header)) = lines.next() else {
+        return Err(FormatError::at(0, 0, "the board is empty"));
+    };
+    let width = number(header_line, &mut header, "width")?;
+    let length = number(header_line, &mut header, "height")?;
+    let mine_count = number(header_line, &mut header, "mine count")?;
+    if let Some((column, extra)) = header.first() {
+        return Err(FormatError::at(
+            header_line,
+            *column,
+            format!("unexpected '{}' after the mine count", extra),
+        ));
+    }
+    tile_count(width, length).map_err(|e| FormatError::at(header_line, 1, e))?;
+    let mut mines = Vec::new();
+    for (line, mut words) in lines {
+        let (column, _) = words[0];
+        let x = number(line, &mut words, "x")?;
+        let y = number(line, &mut words, "y")?;
+        if let Some((column, extra)) = words.first() {
+            return Err(FormatError::at(
+                line,
+                *column,
+                format!("unexpected '{}' after the coordinates", extra),
+            ));
+        }
+        if x >= width || y >= length {
+            return Err(FormatError::at(
+                line,
+                column,
+                format!("mine at {} {} is off the {}x{} board", x, y, width, length),
+            ));
+        }
+        // (0, 0) is the top left here and the bottom left on our boards
+        let mine = (x, length - 1 - y);
+        if mines.contains(&mine) {
+            return Err(FormatError::at(
+                line,
+                column,
+                format!("a mine at {} {} is already listed", x, y),
+            ));
+        }
+        mines.push(mine);
+    }
+    if mines.len() != mine_count as usize {
+        return Err(FormatError::at(
+            header_line,
+            0,
+            format!(
+                "the header says {} mines but {} are listed",
+                mine_count,
+                mines.len()
+            ),
+        ));
+    }
+    from_cells(width, length, None, &mines)
+}
+
+fn write_coordinates(game_state: &GameState) -> Result<String, FormatError> {
+    if game_state.board.len() != (game_state.width * game_state.length) as usize {
+        return Err(FormatError::at(
+            0,
+            0,
+            "the board has holes, the coordinate list can't hold them",
+        ));
+    }
+    let mines: Vec<(u32, u32)> = game_state
+        .board
+        .iter()
+        .filter(|tile| tile.has_mine())
+        .map(|tile| {
+            let position = tile.board_position;
+            (position.x as u32, game_state.length - 1 - position.y as u32)
+        })
+        .collect();
+    let mut text = format!(
+        "{} {} {}\n",
+        game_state.width,
+        game_state.length,
+        mines.len()
+    );
+    for (x, y) in mines {
+        text += &format!("{} {}\n", x, y);
+    }
+    Ok(text)
+}
+
+// what a json board looks like, rows read like the grid format
+#[derive(Serialize, Deserialize)]
+struct JsonBoard {
+    format: String,
+    version: u32,
+    width: u32,
+    height: u32,
+    // "x y" pairs from the top left like the coordinate list
+    #[serde(default)]
+    holes: Vec<[u32; 2]>,
+    mines: Vec<JsonMine>,
+}
+
+// mines are written with their kind so new kinds of mine can be added. There are
+// no tile modifiers in the game yet, they get a field here once there are.
+#[derive(Serialize, Deserialize)]
+struct JsonMine {
+    x: u32,
+    y: u32,
+    #[serde(default = "default_kind")]
+    kind: String,
+}
+
+fn default_kind() -> String {
+    String::from("default")
+}
+
+fn parse_json(text: &str) -> Result<GameState, FormatError> {
+    let board: JsonBoard =
+        serde_json::from_str(text).map_err(|e| FormatError::at(e.line(), e.column(), e))?;
+    // serde_json doesn't say where a value was, point at its key instead
+    let key_offset = |key: &str| text.find(&format!("\"{}\"", key));
+    let at_offset = |offset: Option<usize>, message: String| {
+        let (line, column) = offset.map_or((0, 0), |offset| {
+            let before = &text[..offset];
+            let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+            (before.matches('\n').count() + 1, offset - line_start + 1)
+        });
+        FormatError::at(line, column, message)
+    };
+    let locate = |key: &str, message: String| at_offset(key_offset(key), message);
+    // entries are flat, so the nth one starts at the nth `open` after the key
+    // (a hole's `[`, past the one around the list)
+    let locate_entry = |key: &str, index: usize, open: char, message: String| {
+        let skip = usize::from(open == '[');
+        let offset = key_offset(key).and_then(|start| {
+            text[start..]
+                .match_indices(open)
+                .nth(index + skip)
+                .map(|(offset, _)| start + offset)
+        });
+        at_offset(offset, format!("{}[{}]: {}", key, index, message))
+    };
+    if board.format != JSON_FORMAT_NAME {
+        return Err(locate(
+            "format",
+            format!(
+                "format is '{}', expected '{}'",
+                board.format, JSON_FORMAT_NAME
+            ),
+        ));
+    }
+    if board.version > BOARD_FORMAT_VERSION {
+        return Err(locate(
+            "version",
+            format!(
+                "board is version {}, this build reads up to {}",
+                board.version, BOARD_FORMAT_VERSION
+            ),
+        ));
+    }
+    let (width, length) = (board.width, board.height);
+    let tiles = tile_count(width, length).map_err(|e| locate("width", e))?;
+    let on_board = |[x, y]: [u32; 2], key: &str, index: usize, open: char| {
+        if x >= width || y >= length {
+            Err(locate_entry(
+                key,
+                index,
+                open,
+                format!("{} {} is off the {}x{} board", x, y, width, length),
+            ))
+        } else {
+            Ok((x, length - 1 - y))
+        }
+    };
+    let cell = |(col, row): (u32, u32)| col as usize + row as usize * width as usize;
+    let mut cells = vec![true; tiles];
+    for (index, hole) in board.holes.iter().enumerate() {
+        cells[cell(on_board(*hole, "holes", index, '[')?)] = false;
+    }
+    let mut mines = Vec::new();
+    let mut mined = HashSet::new();
+    for (index, mine) in board.mines.iter().enumerate() {
+        let entry_error = |message: String| locate_entry("mines", index, '{', message);
+        if mine.kind != "default" {
+            return Err(entry_error(format!("unknown mine kind '{}'", mine.kind)));
+        }
+        let position = on_board([mine.x, mine.y], "mines", index, '{')?;
+        if !cells[cell(position)] {
+            return Err(entry_error(format!(
+                "a mine at {} {} is on a hole",
+                mine.x, mine.y
+            )));
+        }
+        if !mined.insert(position) {
+            return Err(entry_error(format!(
+                "a mine at {} {} is already listed",
+                mine.x, mine.y
+            )));
+        }
+        mines.push(position);
+    }
+    from_cells(width, length, Some(cells), &mines)
+}
+
+fn write_json(game_state: &GameState) -> String {
+    let (width, length) = (game_state.width, game_state.length);
+    let top_down = |col: u32, row: u32| [col, length - 1 - row];
+    let holes = (0..length)
+        .flat_map(|row| (0..width).map(move |col| (col, row)))
+        .filter(|(col, row)| {
+            game_state
+                .tile_index(glam::Vec2::new(*col as f32, *row as f32))
+                .is_none()
+        })
+        .map(|(col, row)| top_down(col, row))
+        .collect();
+    let mines = game_state
+        .board
+        .iter()
+        .filter_map(|tile| {
+            let kind = match tile.mine.as_ref()? {
+                Mines::Default(_) => "default",
+            };
+            let [x, y] = top_down(tile.board_position.x as u32, tile.board_position.y as u32);
+            Some(JsonMine {
+                x,
+                y,
+                kind: String::from(kind),
+            })
+        })
+        .collect();
+    let board = JsonBoard {
+        format: String::from(JSON_FORMAT_NAME),
+        version: BOARD_FORMAT_VERSION,
+        width,
+        height: length,
+        holes,
+        mines,
+    };
+    serde_json::to_string_pretty(&board).expect("a board is always valid json") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRID: &str = "\
+*.._
+....
+.*..
+";
+
+    fn mines(game_state: &GameState) -> Vec<(u32, u32)> {
+        game_state
+            .board()
+            .iter()
+            .filter(|tile| tile.has_mine())
+            .map(|tile| {
+                let position = tile.board_position();
+                (position.x as u32, position.y as u32)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_format_round_trips() {
+        let game_state = parse(GRID, BoardFormat::Grid).unwrap();
+        assert_eq!(game_state.board().len(), 11);
+        assert_eq!(mines(&game_state), vec![(1, 0), (0, 2)]);
+        assert_eq!(write(&game_state, BoardFormat::Grid).unwrap(), GRID);
+
+        let json = write(&game_state, BoardFormat::Json).unwrap();
+        let from_json = parse(&json, BoardFormat::Json).unwrap();
+        assert_eq!(write(&from_json, BoardFormat::Grid).unwrap(), GRID);
+
+        // the coordinate list has no holes
+        assert!(write(&game_state, BoardFormat::Coordinates).is_err());
+        let full = parse("*...\n.*..\n", BoardFormat::Grid).unwrap();
+        let coordinates = write(&full, BoardFormat::Coordinates).unwrap();
+        assert_eq!(coordinates, "4 2 2\n1 1\n0 0\n");
+        let from_coordinates = parse(&coordinates, BoardFormat::Coordinates).unwrap();
+        assert_eq!(mines(&from_coordinates), mines(&full));
+    }
+
+    #[test]
+    fn errors_point_at_the_line_and_column() {
+        let error = parse("*..\n.x.\n", BoardFormat::Grid).err().unwrap();
+        assert_eq!((error.line, error.column), (2, 2));
+        let error = parse("*..\n..\n", BoardFormat::Grid).err().unwrap();
+        assert_eq!(error.line, 2);
+
+        let error = parse("3 3 1\n# a comment\n  1 q\n", BoardFormat::Coordinates)
+            .err()
+            .unwrap();
+        assert_eq!((error.line, error.column), (3, 5));
+        assert_eq!(error.to_string(), "line 3, column 5: y 'q' isn't a number");
+        let error = parse("3 3 1\n3 0\n", BoardFormat::Coordinates)
+            .err()
+            .unwrap();
+        assert_eq!((error.line, error.column), (2, 1));
+        let error = parse("3 3 2\n0 0\n", BoardFormat::Coordinates)
+            .err()
+            .unwrap();
+        assert_eq!(error.line, 1);
+
+        let error = parse("{\n  \"width\": 3,\n  oops\n}", BoardFormat::Json)
+            .err()
+            .unwrap();
+        assert_eq!(error.line, 3);
+    }
+
+    #[test]
+    fn oversized_boards_are_rejected() {
+        let error = parse("70000 70000 0\n", BoardFormat::Coordinates)
+            .err()
+            .unwrap();
+        assert_eq!((error.line, error.column), (1, 1));
+        assert!(error.message.contains("sides go up to"));
+
+        let json = format!(
+            "{{\n  \"format\": \"{}\",\n  \"version\": 1,\n  \"width\": 4294967295,\n  \"height\": 2,\n  \"mines\": []\n}}",
+            JSON_FORMAT_NAME
+        );
+        let error = parse(&json, BoardFormat::Json).err().unwrap();
+        assert_eq!((error.line, error.column), (4, 3));
+        assert!(error.message.contains("sides go up to"));
+
+        let wide = format!("{}\n", ".".repeat(MAX_SIDE as usize + 1));
+        assert!(parse(&wide, BoardFormat::Grid).is_err());
+    }
+
+    #[test]
+    fn bad_json_entries_are_pointed_at() {
+        let json = |holes: &str, mines: &str| {
+            format!(
+                "{{\n  \"format\": \"{}\",\n  \"version\": 1,\n  \"width\": 3,\n  \"height\": 2,\n  \"holes\": [[0, 0], [2, 1]],\n  \"mines\": [\n    {}\n  ]\n}}",
+                JSON_FORMAT_NAME,
+                [holes, mines].join(",\n    ")
+            )
+        };
+        let mine =
+            |x: u32, y: u32| format!("{{\"x\": {}, \"y\": {}, \"kind\": \"default\"}}", x, y);
+        assert!(parse(&json(&mine(1, 0), &mine(0, 1)), BoardFormat::Json).is_ok());
+
+        let error = parse(&json(&mine(1, 0), &mine(1, 0)), BoardFormat::Json)
+            .err()
+            .unwrap();
+        assert_eq!((error.line, error.column), (9, 5));
+        assert!(error.message.contains("mines[1]"), "{}", error.message);
+        assert!(
+            error.message.contains("already listed"),
+            "{}",
+            error.message
+        );
+
+        let error = parse(&json(&mine(1, 0), &mine(2, 1)), BoardFormat::Json)
+            .err()
+            .unwrap();
+        assert_eq!((error.line, error.column), (9, 5));
+        assert!(error.message.contains("on a hole"), "{}", error.message);
+
+        let off = json(&mine(1, 0), &mine(0, 1)).replace("[2, 1]", "[3, 1]");
+        let error = parse(&off, BoardFormat::Json).err().unwrap();
+        assert_eq!((error.line, error.column), (6, 21));
+        assert!(error.message.contains("holes[1]"), "{}", error.message);
+    }
+}
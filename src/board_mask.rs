@@ -0,0 +1,162 @@
+use crate::assets::locator;
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+
+/// Which cells of a width x length grid actually exist on the board.
+/// Row 0 is the bottom row of the board, same as `Tiles::board_position`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoardMask {
+    width: u32,
+    length: u32,
+    cells: Vec<bool>,
+}
+
+impl BoardMask {
+    /// Loads a mask through the asset locator, `.png` files are read as images and anything else as ascii.
+    pub fn load(path: &str) -> Result<BoardMask> {
+        let is_png = Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+        if is_png {
+            let bytes = locator().read(path)?;
+            let image = image::load_from_memory(&bytes)
+                .with_context(|| format!("reading mask '{}'", path))?;
+            Self::from_image(&image)
+        } else {
+            Self::from_ascii(&locator().read_to_string(path)?)
+        }
+    }
+
+    /// `.` and spaces are holes, every other character is a tile.
+    /// The first line is the top of the board, short lines are padded with holes.
+    pub fn from_ascii(text: &str) -> Result<BoardMask> {
+        let lines: Vec<&str> = text
+            .lines()
+            .map(|line| line.trim_end())
+            .skip_while(|line| line.is_empty())
+            .collect();
+        let lines: Vec<&str> = match lines.iter().rposition(|line| !line.is_empty()) {
+            Some(last) => lines[..=last].to_vec(),
+            None => bail!("mask is empty"),
+        };
+        let width = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0) as u32;
+        let length = lines.len() as u32;
+        let mut cells = vec![false; (width * length) as usize];
+        for (line_index, line) in lines.iter().enumerate() {
+            let row = length - 1 - line_index as u32;
+            for (col, character) in line.chars().enumerate() {
+                cells[(col as u32 + row * width) as usize] = character != '.' && character != ' ';
+            }
+        }
+        Self::new(width, length, cells)
+    }
+
+    /// Opaque dark pixels are tiles, transparent or light pixels are holes.
+    pub fn from_image(image: &image::DynamicImage) -> Result<BoardMask> {
+        let image = image.to_rgba8();
+        let (width, length) = image.dimensions();
+        let mut cells = vec![false; (width * length) as usize];
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let [r, g, b, a] = pixel.0;
+            let luminance = (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000;
+            let row = length - 1 - y;
+            cells[(x + row * width) as usize] = a >= 128 && luminance < 128;
+        }
+        Self::new(width, length, cells)
+    }
+
+    pub fn new(width: u32, length: u32, cells: Vec<bool>) -> Result<BoardMask> {
+        if cells.len() != (width * length) as usize {
+            bail!("mask is {}x{} but has {} cells", width, length, cells.len());
+        }
+        if !cells.iter().any(|cell| *cell) {
+            bail!("mask has no tiles");
+        }
+        Ok(Self {
+            width,
+            length,
+            cells,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    pub fn contains(&self, col: u32, row: u32) -> bool {
+        col < self.width && row < self.length && self.cells[(col + row * self.width) as usize]
+    }
+
+    pub fn tile_count(&self) -> usize {
+        self.cells.iter().filter(|cell| **cell).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_objects::minefield_from_layout;
+    use glam::Vec2;
+
+    #[test]
+    fn ascii_masks() {
+        // the top line is the top row, the short middle line is padded with holes
+        let mask = BoardMask::from_ascii("\n#.#\n# \n###\n\n").unwrap();
+        assert_eq!((mask.width(), mask.length()), (3, 3));
+        assert!(mask.contains(0, 2) && !mask.contains(1, 2) && mask.contains(2, 2));
+        assert!(mask.contains(0, 1) && !mask.contains(1, 1) && !mask.contains(2, 1));
+        assert!((0..3).all(|col| mask.contains(col, 0)));
+        assert!(!mask.contains(3, 0) && !mask.contains(0, 3));
+        assert_eq!(mask.tile_count(), 6);
+
+        assert!(BoardMask::from_ascii("").is_err());
+        assert!(BoardMask::from_ascii("\n  \n").is_err());
+        assert!(BoardMask::from_ascii("..\n. \n").is_err());
+    }
+
+    #[test]
+    fn image_masks() {
+        let dark = image::Rgba([20, 20, 20, 255]);
+        let light = image::Rgba([240, 240, 240, 255]);
+        let clear = image::Rgba([0, 0, 0, 0]);
+        // the top pixel row is the top row of the board
+        let image = image::RgbaImage::from_fn(2, 2, |x, y| match (x, y) {
+            (0, 0) => dark,
+            (1, 0) => clear,
+            (0, 1) => light,
+            _ => dark,
+        });
+        let mask = BoardMask::from_image(&image::DynamicImage::ImageRgba8(image)).unwrap();
+        assert!(mask.contains(0, 1) && !mask.contains(1, 1));
+        assert!(!mask.contains(0, 0) && mask.contains(1, 0));
+
+        let blank = image::RgbaImage::from_pixel(3, 3, clear);
+        assert!(BoardMask::from_image(&image::DynamicImage::ImageRgba8(blank)).is_err());
+    }
+
+    #[test]
+    fn neighbors_skip_holes() {
+        let mask = BoardMask::from_ascii("#.#\n.##\n###\n").unwrap();
+        let game_state = minefield_from_layout(3, 3, Some(&mask), &[], 0);
+        let positions = |neighbors: Vec<usize>| -> Vec<Vec2> {
+            neighbors
+                .into_iter()
+                .map(|index| game_state.board()[index].board_position())
+                .collect()
+        };
+        let around_center = positions(game_state.neighbors(Vec2::new(1.0, 1.0)));
+        assert_eq!(around_center.len(), 6);
+        assert!(!around_center.contains(&Vec2::new(1.0, 2.0)));
+        assert!(!around_center.contains(&Vec2::new(0.0, 1.0)));
+        // a corner has three cells around it, one of them is a hole
+        assert_eq!(game_state.neighbors(Vec2::new(2.0, 2.0)).len(), 2);
+    }
+}
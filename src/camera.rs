@@ -1,5 +1,4 @@
-use glam::Mat4;
-
+use glam::{Mat4, Vec2};
 
 pub struct OrthographicCamera {
     pub projection_matrix: Mat4,
@@ -12,6 +11,27 @@ impl OrthographicCamera {
 
         OrthographicCamera { projection_matrix }
     }
+    /// World position under a pixel. `screen` is in physical pixels from the top left,
+    /// like winit's cursor, and `viewport` is the window's physical size.
+    pub fn screen_to_world(&self, screen: Vec2, viewport: Vec2) -> Vec2 {
+        let ndc = Vec2::new(
+            2.0 * screen.x / viewport.x - 1.0,
+            1.0 - 2.0 * screen.y / viewport.y,
+        );
+        self.projection_matrix
+            .inverse()
+            .project_point3(ndc.extend(0.0))
+            .truncate()
+    }
+
+    /// Pixel a world position is drawn at, the inverse of `screen_to_world`.
+    pub fn world_to_screen(&self, world: Vec2, viewport: Vec2) -> Vec2 {
+        let ndc = self.projection_matrix.project_point3(world.extend(0.0));
+        Vec2::new(
+            (ndc.x + 1.0) * 0.5 * viewport.x,
+            (1.0 - ndc.y) * 0.5 * viewport.y,
+        )
+    }
     // ... (update_projection method will be explained later)
     pub fn update_projection(
         &mut self,
@@ -32,6 +52,12 @@ pub struct CameraUniform {
     projection: [[f32; 4]; 4],
 }
 
+impl Default for CameraUniform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CameraUniform {
     pub fn new() -> Self {
         Self {
@@ -42,3 +68,345 @@ impl CameraUniform {
         self.projection = camera.projection_matrix.to_cols_array_2d();
     }
 }
+
+// how quickly the view catches up with its target, higher is snappier
+const SMOOTHING: f32 = 14.0;
+// how quickly a flung pan slows down
+const FRICTION: f32 = 5.0;
+// slower than this, in view heights per second, a flung pan stops
+const MIN_FLING_SPEED: f32 = 0.02;
+// most zoomed in shows this many tiles top to bottom
+const MIN_VISIBLE_TILES: f32 = 4.0;
+// most zoomed out shows the board this many times over
+const MAX_BOARD_SCALE: f32 = 4.0;
+// space around the board when it's framed, as a fraction of its size
+const FIT_MARGIN: f32 = 0.1;
+
+/// The part of the world on screen. Zooms and pans move a target and the view
+/// glides after it, a pan that's let go mid drag keeps drifting for a bit.
+pub struct CameraView {
+    center: Vec2,
+    // world units from the bottom to the top of the window
+    height: f32,
+    target_center: Vec2,
+    target_height: f32,
+    // window width over height
+    aspect: f32,
+    // world units per second, for flung pans
+    velocity: Vec2,
+    dragging: bool,
+    drag_motion: Vec2,
+    min_height: f32,
+    max_height: f32,
+    // the board the view stays on, None before there is one
+    bounds: Option<(Vec2, Vec2)>,
+    // true until the player moves the camera, a fitted view refits on resize
+    fitted: bool,
+}
+
+impl CameraView {
+    pub fn new(center: Vec2, height: f32, aspect: f32) -> CameraView {
+        Self {
+            center,
+            height,
+            target_center: center,
+            target_height: height,
+            aspect,
+            velocity: Vec2::ZERO,
+            dragging: false,
+            drag_motion: Vec2::ZERO,
+            min_height: 0.0,
+            max_height: f32::INFINITY,
+            bounds: None,
+            fitted: false,
+        }
+    }
+
+    pub fn left(&self) -> f32 {
+        self.center.x - 0.5 * self.height * self.aspect
+    }
+
+    pub fn right(&self) -> f32 {
+        self.center.x + 0.5 * self.height * self.aspect
+    }
+
+    pub fn down(&self) -> f32 {
+        self.center.y - 0.5 * self.height
+    }
+
+    pub fn up(&self) -> f32 {
+        self.center.y + 0.5 * self.height
+    }
+
+    /// Bottom left corner of the view.
+    pub fn min(&self) -> Vec2 {
+        Vec2::new(self.left(), self.down())
+    }
+
+    /// Top right corner of the view.
+    pub fn max(&self) -> Vec2 {
+        Vec2::new(self.right(), self.up())
+    }
+
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+
+    /// Keeps the height, so the window getting wider shows more of the sides.
+    pub fn set_aspect(&mut self, aspect: f32) {
+        self.aspect = aspect;
+        if self.fitted {
+            self.fit();
+            self.snap();
+        }
+    }
+
+    /// Sets the board the view frames and stays on, zoom limits scale with its size.
+    pub fn set_board(&mut self, min: Vec2, max: Vec2, tile_size: f32) {
+        let extent = (max - min).max_element();
+        self.bounds = Some((min, max));
+        self.min_height = (MIN_VISIBLE_TILES * tile_size).min(extent);
+        self.max_height = MAX_BOARD_SCALE * extent;
+        self.fit();
+    }
+
+    /// Frames the whole board, the view glides there.
+    pub fn fit(&mut self) {
+        let Some((min, max)) = self.bounds else {
+            return;
+        };
+        let size = (max - min) * (1.0 + 2.0 * FIT_MARGIN);
+        self.target_center = 0.5 * (min + max);
+        self.target_height = size.y.max(size.x / self.aspect);
+        self.velocity = Vec2::ZERO;
+        self.fitted = true;
+    }
+
+    /// Jumps straight to the target.
+    pub fn snap(&mut self) {
+        self.center = self.target_center;
+        self.height = self.target_height;
+    }
+
+    /// Moves the target by a world offset, for keyboard panning.
+    pub fn pan(&mut self, offset: Vec2) {
+        self.target_center += offset;
+        self.velocity = Vec2::ZERO;
+        self.fitted = false;
+        self.clamp_target();
+    }
+
+    /// Moves the view with the cursor, no smoothing so the board sticks to it.
+    pub fn drag(&mut self, offset: Vec2) {
+        self.dragging = true;
+        self.drag_motion += offset;
+        self.center += offset;
+        self.target_center += offset;
+        self.velocity = Vec2::ZERO;
+        self.fitted = false;
+        self.clamp_target();
+    }
+
+    /// Lets go of a drag, the view keeps the speed it was moving at.
+    pub fn end_drag(&mut self) {
+        self.dragging = false;
+    }
+
+    /// Zooms by `factor` (above 1 zooms out) keeping the world point under `anchor`
+    /// where it is, `anchor` is in fractions of the window from the bottom left.
+    pub fn zoom_at(&mut self, anchor: Vec2, factor: f32) {
+        let point = self.min() + anchor * Vec2::new(self.height * self.aspect, self.height);
+        self.target_height = (self.target_height * factor).clamp(self.min_height, self.max_height);
+        let size = Vec2::new(self.target_height * self.aspect, self.target_height);
+        self.target_center = point + (Vec2::splat(0.5) - anchor) * size;
+        self.velocity = Vec2::ZERO;
+        self.fitted = false;
+        self.clamp_target();
+    }
+
+    /// Moves the view towards its target, returns true if it moved.
+    pub fn update(&mut self, delta: f32) -> bool {
+        if self.dragging {
+            if delta > 0.0 {
+                self.velocity = self.drag_motion / delta;
+            }
+            self.drag_motion = Vec2::ZERO;
+        } else if self.velocity != Vec2::ZERO {
+            self.target_center += self.velocity * delta;
+            self.velocity *= (-FRICTION * delta).exp();
+            if self.velocity.length() < MIN_FLING_SPEED * self.height {
+                self.velocity = Vec2::ZERO;
+            }
+            self.clamp_target();
+        }
+        if self.center == self.target_center && self.height == self.target_height {
+            return false;
+        }
+        let t = 1.0 - (-SMOOTHING * delta).exp();
+        self.center = self.center.lerp(self.target_center, t);
+        // zoom evenly in log space, so zooming in and out feel the same
+        self.height = (self.height.ln() + (self.target_height.ln() - self.height.ln()) * t).exp();
+        // close enough, stop instead of creeping forever
+        if self.center.distance(self.target_center) < 1e-4 * self.height
+            && (self.height - self.target_height).abs() < 1e-4 * self.height
+        {
+            self.snap();
+        }
+        true
+    }
+
+    // the view's center stays over the board, so it can't be lost off screen
+    fn clamp_target(&mut self) {
+        if let Some((min, max)) = self.bounds {
+            self.target_center = self.target_center.clamp(min, max);
+            if self.dragging {
+                self.center = self.center.clamp(min, max);
+            }
+        }
+    }
+
+    /// World position under a pixel, ignoring screen shake so clicks land where they're aimed.
+    pub fn screen_to_world(&self, screen: Vec2, viewport: Vec2) -> Vec2 {
+        self.projection(Vec2::ZERO)
+            .screen_to_world(screen, viewport)
+    }
+
+    pub fn world_to_screen(&self, world: Vec2, viewport: Vec2) -> Vec2 {
+        self.projection(Vec2::ZERO).world_to_screen(world, viewport)
+    }
+
+    pub fn projection(&self, offset: Vec2) -> OrthographicCamera {
+        OrthographicCamera::new(
+            self.left() + offset.x,
+            self.right() + offset.x,
+            self.down() + offset.y,
+            self.up() + offset.y,
+            -1.0,
+            1.0,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GameConfig;
+    use crate::game_objects::create_minefield;
+
+    const SIZES: [(f32, f32); 5] = [
+        (800.0, 800.0),
+        (1920.0, 1080.0),
+        (300.0, 900.0),
+        (1234.0, 567.0),
+        (201.0, 200.0),
+    ];
+
+    fn view_for(viewport: Vec2) -> CameraView {
+        let mut view = CameraView::new(Vec2::splat(0.5), 1.4, viewport.x / viewport.y);
+        view.set_board(Vec2::ZERO, Vec2::ONE, 1.0 / 15.0);
+        view.snap();
+        view
+    }
+
+    fn assert_close(a: Vec2, b: Vec2) {
+        assert!(a.distance(b) < 1e-3, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn corners_map_to_the_view_edges() {
+        for (width, height) in SIZES {
+            let viewport = Vec2::new(width, height);
+            let view = view_for(viewport);
+            assert_close(
+                view.screen_to_world(Vec2::new(0.0, height), viewport),
+                view.min(),
+            );
+            assert_close(
+                view.screen_to_world(Vec2::new(width, 0.0), viewport),
+                view.max(),
+            );
+        }
+    }
+
+    #[test]
+    fn round_trips_through_world_space() {
+        for (width, height) in SIZES {
+            let viewport = Vec2::new(width, height);
+            let mut view = view_for(viewport);
+            view.zoom_at(Vec2::new(0.2, 0.7), 0.5);
+            view.snap();
+            for screen in [
+                Vec2::ZERO,
+                Vec2::new(width * 0.3, height * 0.9),
+                Vec2::new(width, height),
+            ] {
+                let world = view.screen_to_world(screen, viewport);
+                let back = view.world_to_screen(world, viewport);
+                assert!(back.distance(screen) < 0.01, "{} != {}", back, screen);
+            }
+        }
+    }
+
+    #[test]
+    fn world_is_not_stretched_in_wide_or_tall_windows() {
+        for (width, height) in SIZES {
+            let viewport = Vec2::new(width, height);
+            let view = view_for(viewport);
+            let origin = view.world_to_screen(Vec2::ZERO, viewport);
+            let across = view.world_to_screen(Vec2::new(0.1, 0.0), viewport);
+            let up = view.world_to_screen(Vec2::new(0.0, 0.1), viewport);
+            assert!((origin.distance(across) - origin.distance(up)).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn zoom_keeps_the_point_under_the_cursor() {
+        let viewport = Vec2::new(1280.0, 720.0);
+        let mut view = view_for(viewport);
+        let cursor = Vec2::new(1000.0, 200.0);
+        let anchor = Vec2::new(cursor.x / viewport.x, 1.0 - cursor.y / viewport.y);
+        let before = view.screen_to_world(cursor, viewport);
+        for factor in [0.5, 0.8, 1.25] {
+            view.zoom_at(anchor, factor);
+            view.snap();
+            assert_close(view.screen_to_world(cursor, viewport), before);
+        }
+    }
+
+    #[test]
+    fn same_logical_cursor_hits_the_same_spot_at_any_scale_factor() {
+        // 1 world unit tall and 4/3 wide, so the view runs from -1/6 to 7/6 across
+        let logical = Vec2::new(640.0, 480.0);
+        let view = CameraView::new(Vec2::splat(0.5), 1.0, logical.x / logical.y);
+        // a quarter of the way in from the top left
+        let cursor = Vec2::new(160.0, 120.0);
+        let expected = Vec2::new(-1.0 / 6.0 + 0.25 * 4.0 / 3.0, 0.75);
+        for scale in [1.0, 1.5, 2.0] {
+            // winit hands over physical pixels for both the cursor and the window
+            let hit = view.screen_to_world(cursor * scale, logical * scale);
+            assert_close(hit, expected);
+        }
+    }
+
+    #[test]
+    fn clicks_land_on_the_intended_tile_after_resizes_and_zooms() {
+        let game_state = create_minefield(&GameConfig::default(), None, 7);
+        let mut view = view_for(Vec2::new(800.0, 800.0));
+        for (step, (width, height)) in SIZES.into_iter().enumerate() {
+            let viewport = Vec2::new(width, height);
+            view.set_aspect(width / height);
+            view.zoom_at(Vec2::new(0.3, 0.6), 0.6 + 0.2 * step as f32);
+            view.snap();
+            for index in (0..game_state.board().len()).step_by(13) {
+                let tile = &game_state.board()[index];
+                let center = tile.position + Vec2::splat(0.5 * tile.size);
+                let screen = view.world_to_screen(center, viewport);
+                let on_screen = screen.cmpge(Vec2::ZERO).all() && screen.cmple(viewport).all();
+                if on_screen {
+                    let world = view.screen_to_world(screen, viewport);
+                    assert_eq!(game_state.tile_at(world), Some(index));
+                }
+            }
+        }
+    }
+}
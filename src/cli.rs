@@ -0,0 +1,352 @@
+use crate::analysis::BoardAnalysis;
+use crate::board_format::{self, BoardFormat};
+use crate::board_mask::BoardMask;
+use crate::config::{Difficulty, GameConfig, Rules, MAX_SIDE, MIN_SIDE};
+use crate::game_objects::create_minefield;
+use crate::stats::{StatsDb, CATEGORIES, STATS_FILE};
+use crate::theme::{available_themes, THEME_DIR};
+use anyhow::{bail, Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Read on start when there's no --config, it's fine for it not to exist.
+pub const CONFIG_FILE: &str = "minesweeper.toml";
+const DEFAULT_WINDOW: WindowSize = WindowSize {
+    width: 800,
+    height: 800,
+};
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Minesweeper, opens the game window unless a command is given"
+)]
+pub struct Cli {
+    /// toml file with any of the options, flags win over it [default: minesweeper.toml]
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    #[command(flatten)]
+    pub options: Options,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Deal a board with the options and print it
+    Generate {
+        #[arg(long, value_enum, default_value_t = Format::Grid)]
+        format: Format,
+    },
+    /// Print 3BV, openings, islands, ZiNi and the guesses a board file needs
+    Solve { file: PathBuf },
+    /// Print the stats of every finished game, per difficulty
+    Stats,
+}
+
+/// Everything that can be set with flags or in the config file, the keys are the flag names.
+#[derive(Args, Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Options {
+    /// Board width in tiles
+    #[arg(long, global = true)]
+    pub width: Option<u32>,
+    /// Board height in tiles
+    #[arg(long, global = true)]
+    pub height: Option<u32>,
+    /// Number of mines
+    #[arg(long, global = true)]
+    pub mines: Option<u32>,
+    /// Start a game on this seed straight away
+    #[arg(long, global = true)]
+    pub seed: Option<u64>,
+    /// Preset size and mines, width, height and mines change it
+    #[arg(long, value_enum, global = true)]
+    pub difficulty: Option<Preset>,
+    /// First click rules
+    #[arg(long, value_enum, global = true)]
+    pub rules: Option<RuleSet>,
+    /// Folder name in themes/ or the path of a theme.toml
+    #[arg(long)]
+    pub theme: Option<String>,
+    /// Window size in logical pixels, like 1280x720
+    #[arg(long)]
+    pub window_size: Option<WindowSize>,
+    /// Borderless fullscreen on the current monitor
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub fullscreen: Option<bool>,
+    /// Watch a replay file instead of playing
+    #[arg(long)]
+    pub replay: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    Beginner,
+    Intermediate,
+    Expert,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum RuleSet {
+    Classic,
+    SafeOpening,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// rows of '*' and '.', '_' for holes
+    Grid,
+    /// "width height mines" and one "x y" line per mine
+    Mines,
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct WindowSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for WindowSize {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<WindowSize, String> {
+        let size = text
+            .split_once('x')
+            .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)));
+        match size {
+            Some((width, height)) if width > 0 && height > 0 => Ok(Self { width, height }),
+            _ => Err(format!("'{}' isn't a size like 1280x720", text)),
+        }
+    }
+}
+
+impl TryFrom<String> for WindowSize {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<WindowSize, String> {
+        text.parse()
+    }
+}
+
+/// What the window starts with.
+#[derive(Clone, Debug)]
+pub struct Launch {
+    pub game_config: GameConfig,
+    // None opens the main menu
+    pub seed: Option<u64>,
+    pub window_size: WindowSize,
+    pub fullscreen: bool,
+    pub replay: Option<PathBuf>,
+}
+
+impl Options {
+    /// Reads a config file. Without a path the default one is read if it's there.
+    pub fn load(path: Option<&Path>) -> Result<Options> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(CONFIG_FILE).exists() => Path::new(CONFIG_FILE),
+            None => return Ok(Options::default()),
+        };
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading '{}'", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("reading '{}'", path.display()))
+    }
+
+    /// Fills whatever isn't set here from `other`.
+    pub fn or(self, other: Options) -> Options {
+        Self {
+            width: self.width.or(other.width),
+            height: self.height.or(other.height),
+            mines: self.mines.or(other.mines),
+            seed: self.seed.or(other.seed),
+            difficulty: self.difficulty.or(other.difficulty),
+            rules: self.rules.or(other.rules),
+            theme: self.theme.or(other.theme),
+            window_size: self.window_size.or(other.window_size),
+            fullscreen: self.fullscreen.or(other.fullscreen),
+            replay: self.replay.or(other.replay),
+        }
+    }
+
+    /// The default settings with these on top.
+    pub fn game_config(&self) -> Result<GameConfig> {
+        let mut config = GameConfig::default();
+        if let Some(preset) = self.difficulty {
+            let difficulty = match preset {
+                Preset::Beginner => Difficulty::Beginner,
+                Preset::Intermediate => Difficulty::Intermediate,
+                Preset::Expert => Difficulty::Expert,
+            };
+            if let Some((width, length, mines)) = difficulty.preset() {
+                config.width = width;
+                config.length = length;
+                config.mines = mines;
+                config.shape = None;
+            }
+        }
+        if let Some(width) = self.width {
+            config.width = width;
+            config.shape = None;
+        }
+        if let Some(height) = self.height {
+            config.length = height;
+            config.shape = None;
+        }
+        if let Some(mines) = self.mines {
+            config.mines = mines;
+        }
+        for side in [config.width, config.length] {
+            if !(MIN_SIDE..=MAX_SIDE).contains(&side) {
+                bail!(
+                    "boards are {} to {} tiles across, not {}",
+                    MIN_SIDE,
+                    MAX_SIDE,
+                    side
+                );
+            }
+        }
+        if config.mines == 0 || config.mines > config.max_mines() {
+            bail!(
+                "a {}x{} board takes 1 to {} mines, not {}",
+                config.width,
+                config.length,
+                config.max_mines(),
+                config.mines
+            );
+        }
+        config.match_difficulty();
+        if let Some(rules) = self.rules {
+            config.rules = match rules {
+                RuleSet::Classic => Rules::Classic,
+                RuleSet::SafeOpening => Rules::SafeOpening,
+            };
+        }
+        if let Some(theme) = self.theme.as_deref() {
+            config.theme = find_theme(theme)?;
+        }
+        Ok(config)
+    }
+
+    pub fn launch(&self) -> Result<Launch> {
+        Ok(Launch {
+            game_config: self.game_config()?,
+            seed: self.seed,
+            window_size: self.window_size.unwrap_or(DEFAULT_WINDOW),
+            fullscreen: self.fullscreen.unwrap_or(false),
+            replay: self.replay.clone(),
+        })
+    }
+}
+
+// a manifest path or the name of its folder, whichever case
+fn find_theme(theme: &str) -> Result<String> {
+    let themes = available_themes();
+    let folder = format!("{}/{}/", THEME_DIR, theme.to_ascii_lowercase());
+    themes
+        .iter()
+        .find(|path| *path == theme || path.to_ascii_lowercase().starts_with(&folder))
+        .cloned()
+        .with_context(|| format!("no theme '{}', there's {}", theme, themes.join(", ")))
+}
+
+/// Runs a command, or returns how to open the window when there's none.
+pub fn execute(cli: Cli) -> Result<Option<Launch>> {
+    let options = cli.options.or(Options::load(cli.config.as_deref())?);
+    match cli.command {
+        None => options.launch().map(Some),
+        Some(Command::Generate { format }) => {
+            let config = options.game_config()?;
+            let mask = config.shape.as_deref().map(BoardMask::load).transpose()?;
+            let seed = options.seed.unwrap_or_else(rand::random);
+            let game_state = create_minefield(&config, mask.as_ref(), seed);
+            let format = match format {
+                Format::Grid => BoardFormat::Grid,
+                Format::Mines => BoardFormat::Coordinates,
+                Format::Json => BoardFormat::Json,
+            };
+            print!("{}", board_format::write(&game_state, format)?);
+            // stdout is only the board, so it can go straight into a file
+            eprintln!("seed {}", seed);
+            Ok(None)
+        }
+        Some(Command::Solve { file }) => {
+            let game_state = board_format::import(&file)?;
+            let analysis = BoardAnalysis::new(&game_state);
+            println!("3BV: {}", analysis.bbbv);
+            for line in analysis.lines() {
+                println!("{}", line);
+            }
+            Ok(None)
+        }
+        Some(Command::Stats) => {
+            let stats = StatsDb::open(STATS_FILE)?;
+            for category in CATEGORIES {
+                println!("{}", category);
+                for line in stats.summary(category).lines() {
+                    println!("  {}", line);
+                }
+            }
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_win_over_the_config_file() {
+        let file: Options = toml::from_str(
+            "difficulty = \"expert\"\nrules = \"safe-opening\"\nwindow-size = \"1280x720\"\nseed = 7\n",
+        )
+        .unwrap();
+        let cli = Cli::try_parse_from(["minesweeper", "--mines", "50", "--fullscreen"]).unwrap();
+        let launch = cli.options.or(file).launch().unwrap();
+        assert_eq!(
+            (
+                launch.game_config.width,
+                launch.game_config.length,
+                launch.game_config.mines
+            ),
+            (30, 16, 50)
+        );
+        assert_eq!(launch.game_config.difficulty, Difficulty::Custom);
+        assert_eq!(launch.game_config.rules, Rules::SafeOpening);
+        assert_eq!(
+            launch.window_size,
+            WindowSize {
+                width: 1280,
+                height: 720
+            }
+        );
+        assert!(launch.fullscreen);
+        assert_eq!(launch.seed, Some(7));
+    }
+
+    #[test]
+    fn bad_options_are_errors() {
+        assert!(toml::from_str::<Options>("widht = 10\n").is_err());
+        assert!(toml::from_str::<Options>("window-size = \"big\"\n").is_err());
+        let too_many = Options {
+            width: Some(9),
+            height: Some(9),
+            mines: Some(80),
+            ..Default::default()
+        };
+        assert!(too_many.game_config().is_err());
+        assert!(find_theme("default").is_ok());
+        assert!(find_theme("nope").is_err());
+
+        let cli = Cli::try_parse_from(["minesweeper", "generate", "--width", "12"]).unwrap();
+        assert_eq!(cli.options.width, Some(12));
+        assert!(matches!(cli.command, Some(Command::Generate { .. })));
+    }
+}
@@ -0,0 +1,277 @@
+use crate::assets::locator;
+use crate::palette::Palette;
+use crate::theme::{available_themes, DEFAULT_THEME};
+use crate::undo::UndoPolicy;
+use crate::{BOARD_LENGTH, BOARD_MASK, BOARD_WIDTH, MINE_COUNT};
+use serde::{Deserialize, Serialize};
+
+/// Smallest and largest board side the settings allow.
+pub const MIN_SIDE: u32 = 5;
+pub const MAX_SIDE: u32 = 1000;
+const MASK_DIR: &str = "masks";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Difficulty {
+    Beginner,
+    Intermediate,
+    Expert,
+    Custom,
+}
+
+impl Difficulty {
+    const ALL: [Difficulty; 4] = [
+        Difficulty::Beginner,
+        Difficulty::Intermediate,
+        Difficulty::Expert,
+        Difficulty::Custom,
+    ];
+
+    /// Width, length and mines of the classic presets.
+    pub fn preset(&self) -> Option<(u32, u32, u32)> {
+        match self {
+            Difficulty::Beginner => Some((9, 9, 10)),
+            Difficulty::Intermediate => Some((16, 16, 40)),
+            Difficulty::Expert => Some((30, 16, 99)),
+            Difficulty::Custom => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Difficulty::Beginner => "BEGINNER",
+            Difficulty::Intermediate => "INTERMEDIATE",
+            Difficulty::Expert => "EXPERT",
+            Difficulty::Custom => "CUSTOM",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rules {
+    // first click is never a mine
+    Classic,
+    // first click always opens a zero, when the board allows it
+    SafeOpening,
+}
+
+impl Rules {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Rules::Classic => "CLASSIC",
+            Rules::SafeOpening => "SAFE OPENING",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Setting {
+    Difficulty,
+    Width,
+    Length,
+    Mines,
+    Shape,
+    Rules,
+    QuestionMarks,
+    Animations,
+    Theme,
+    Palette,
+    NumberPatterns,
+    Undo,
+}
+
+/// Everything picked in the menus before a game starts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameConfig {
+    pub difficulty: Difficulty,
+    pub width: u32,
+    pub length: u32,
+    pub mines: u32,
+    // path of a board mask, None for a plain rectangle
+    pub shape: Option<String>,
+    pub rules: Rules,
+    pub question_marks: bool,
+    pub animations: bool,
+    // path of a theme.toml
+    pub theme: String,
+    pub palette: Palette,
+    // a different pattern behind each number, so they don't rely on color alone
+    pub number_patterns: bool,
+    pub undo: UndoPolicy,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        let mut config = Self {
+            difficulty: Difficulty::Custom,
+            width: BOARD_WIDTH,
+            length: BOARD_LENGTH,
+            mines: MINE_COUNT,
+            shape: BOARD_MASK.map(String::from),
+            rules: Rules::Classic,
+            question_marks: false,
+            animations: true,
+            theme: String::from(DEFAULT_THEME),
+            palette: Palette::Classic,
+            number_patterns: false,
+            undo: UndoPolicy::Off,
+        };
+        config.match_difficulty();
+        config
+    }
+}
+
+impl GameConfig {
+    pub fn setting_label(&self, setting: Setting) -> String {
+        match setting {
+            Setting::Difficulty => format!("DIFFICULTY < {} >", self.difficulty.label()),
+            Setting::Width => format!("WIDTH < {} >", self.width),
+            Setting::Length => format!("HEIGHT < {} >", self.length),
+            Setting::Mines => format!("MINES < {} >", self.mines),
+            Setting::Shape => format!(
+                "SHAPE < {} >",
+                self.shape
+                    .as_deref()
+                    .map_or(String::from("RECTANGLE"), shape_name)
+            ),
+            Setting::Rules => format!("RULES < {} >", self.rules.label()),
+            Setting::QuestionMarks => format!(
+                "QUESTION MARKS < {} >",
+                if self.question_marks { "ON" } else { "OFF" }
+            ),
+            Setting::Animations => format!(
+                "ANIMATIONS < {} >",
+                if self.animations { "ON" } else { "OFF" }
+            ),
+            Setting::Theme => format!("THEME < {} >", theme_name(&self.theme)),
+            Setting::Palette => format!("NUMBER COLORS < {} >", self.palette.label()),
+            Setting::NumberPatterns => format!(
+                "NUMBER PATTERNS < {} >",
+                if self.number_patterns { "ON" } else { "OFF" }
+            ),
+            Setting::Undo => format!("UNDO < {} >", self.undo.label()),
+        }
+    }
+
+    /// Steps a setting forwards or backwards, wrapping around for the list settings.
+    pub fn adjust(&mut self, setting: Setting, step: i32) {
+        match setting {
+            Setting::Difficulty => {
+                let current = Difficulty::ALL
+                    .iter()
+                    .position(|difficulty| *difficulty == self.difficulty)
+                    .unwrap_or(0);
+                self.difficulty = Difficulty::ALL[wrap(current, step, Difficulty::ALL.len())];
+                if let Some((width, length, mines)) = self.difficulty.preset() {
+                    self.width = width;
+                    self.length = length;
+                    self.mines = mines;
+                }
+            }
+            Setting::Width => {
+                self.width = self
+                    .width
+                    .saturating_add_signed(step)
+                    .clamp(MIN_SIDE, MAX_SIDE);
+                self.clamp_mines();
+                self.match_difficulty();
+            }
+            Setting::Length => {
+                self.length = self
+                    .length
+                    .saturating_add_signed(step)
+                    .clamp(MIN_SIDE, MAX_SIDE);
+                self.clamp_mines();
+                self.match_difficulty();
+            }
+            Setting::Mines => {
+                self.mines = self.mines.saturating_add_signed(step);
+                self.clamp_mines();
+                self.match_difficulty();
+            }
+            Setting::Shape => {
+                let mut shapes: Vec<Option<String>> = vec![None];
+                shapes.extend(available_shapes().into_iter().map(Some));
+                let current = shapes
+                    .iter()
+                    .position(|shape| *shape == self.shape)
+                    .unwrap_or(0);
+                self.shape = shapes[wrap(current, step, shapes.len())].clone();
+            }
+            Setting::Rules => {
+                self.rules = match self.rules {
+                    Rules::Classic => Rules::SafeOpening,
+                    Rules::SafeOpening => Rules::Classic,
+                };
+            }
+            Setting::QuestionMarks => self.question_marks = !self.question_marks,
+            Setting::Animations => self.animations = !self.animations,
+            Setting::Theme => {
+                let themes = available_themes();
+                if let Some(current) = themes.iter().position(|theme| *theme == self.theme) {
+                    self.theme = themes[wrap(current, step, themes.len())].clone();
+                } else if let Some(first) = themes.first() {
+                    self.theme = first.clone();
+                }
+            }
+            Setting::Palette => {
+                let current = Palette::ALL
+                    .iter()
+                    .position(|palette| *palette == self.palette)
+                    .unwrap_or(0);
+                self.palette = Palette::ALL[wrap(current, step, Palette::ALL.len())];
+            }
+            Setting::NumberPatterns => self.number_patterns = !self.number_patterns,
+            Setting::Undo => {
+                let current = UndoPolicy::ALL
+                    .iter()
+                    .position(|policy| *policy == self.undo)
+                    .unwrap_or(0);
+                self.undo = UndoPolicy::ALL[wrap(current, step, UndoPolicy::ALL.len())];
+            }
+        }
+    }
+
+    /// Leaves room for a safe first click and its neighbours.
+    pub fn max_mines(&self) -> u32 {
+        (self.width * self.length).saturating_sub(9).max(1)
+    }
+
+    fn clamp_mines(&mut self) {
+        self.mines = self.mines.clamp(1, self.max_mines());
+    }
+
+    /// Picks the preset the size and mines match, or Custom.
+    pub fn match_difficulty(&mut self) {
+        self.difficulty = Difficulty::ALL
+            .into_iter()
+            .find(|difficulty| difficulty.preset() == Some((self.width, self.length, self.mines)))
+            .unwrap_or(Difficulty::Custom);
+    }
+}
+
+fn wrap(current: usize, step: i32, len: usize) -> usize {
+    (current as i64 + step as i64).rem_euclid(len as i64) as usize
+}
+
+fn shape_name(path: &str) -> String {
+    std::path::Path::new(path)
+        .file_stem()
+        .map_or(String::from(path), |stem| {
+            stem.to_string_lossy().to_uppercase()
+        })
+}
+
+// themes are named after their folder
+fn theme_name(path: &str) -> String {
+    std::path::Path::new(path)
+        .parent()
+        .and_then(|folder| folder.file_name())
+        .map_or(String::from(path), |name| {
+            name.to_string_lossy().to_uppercase()
+        })
+}
+
+/// Mask files in the masks folder, embedded and overridden ones together.
+pub fn available_shapes() -> Vec<String> {
+    locator().list(MASK_DIR)
+}
@@ -0,0 +1,455 @@
+use crate::board_format::{self, BoardFormat, BOARD_DIR};
+use crate::board_mask::BoardMask;
+use crate::game_objects::minefield_from_layout;
+use crate::hud::{ScreenRect, HUD_HEIGHT};
+use crate::instance::{Instance, InstanceRaw};
+use crate::solver::{solve, Solution};
+use crate::text::{text_instances, text_size};
+use crate::{GameState, TileVisual};
+use anyhow::{bail, Result};
+use glam::{Vec2, Vec4};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use winit::keyboard::KeyCode;
+
+// every edit redraws the whole board and checking runs the solver, so boards stay small
+const EDITOR_MIN_SIDE: u32 = 2;
+const EDITOR_MAX_SIDE: u32 = 100;
+const TOOLBAR_MARGIN: f32 = 8.0;
+const BUTTON_HEIGHT: f32 = 20.0;
+const BUTTON_TEXT_HEIGHT: f32 = 12.0;
+const BUTTON_PADDING: f32 = 8.0;
+const STATUS_TEXT_HEIGHT: f32 = 10.0;
+const Z_TOOLBAR_BACK: f32 = 0.3;
+const Z_TOOLBAR_TEXT: f32 = 0.4;
+
+/// What a cell of the board being edited holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Cell {
+    Safe,
+    Mine,
+    // no tile, the board's shape
+    Hole,
+}
+
+/// What a left click paints. Holes are the only special tiles so far.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditorTool {
+    Mine,
+    Hole,
+}
+
+impl EditorTool {
+    fn label(&self) -> &'static str {
+        match self {
+            EditorTool::Mine => "MINE",
+            EditorTool::Hole => "HOLE",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditorAction {
+    Tool(EditorTool),
+    // columns and rows to add, negative takes them away
+    Resize(i32, i32),
+    Clear,
+    Check,
+    Save,
+    // saves, then plays the board as it was drawn
+    Play,
+    Exit,
+}
+
+/// Toolbar shortcuts.
+pub fn key_action(key: KeyCode) -> Option<EditorAction> {
+    match key {
+        KeyCode::Digit1 => Some(EditorAction::Tool(EditorTool::Mine)),
+        KeyCode::Digit2 => Some(EditorAction::Tool(EditorTool::Hole)),
+        KeyCode::KeyC => Some(EditorAction::Check),
+        KeyCode::KeyS => Some(EditorAction::Save),
+        KeyCode::Enter => Some(EditorAction::Play),
+        KeyCode::Escape => Some(EditorAction::Exit),
+        _ => None,
+    }
+}
+
+/// A board being drawn by hand. It's shown as a full grid with every safe tile
+/// open, so the numbers update as mines go down, and holes covered.
+pub struct Editor {
+    width: u32,
+    length: u32,
+    // row 0 at the bottom, like the board
+    cells: Vec<Cell>,
+    tool: EditorTool,
+    // the solver's run since the last edit
+    verdict: Option<Solution>,
+    // where the board was last saved, saving again overwrites it
+    path: Option<PathBuf>,
+    message: Option<String>,
+    buttons: Vec<(ScreenRect, EditorAction)>,
+    bar: ScreenRect,
+}
+
+impl Editor {
+    /// An empty board of the given size, cut to the mask's shape if there is one.
+    pub fn new(width: u32, length: u32, mask: Option<&BoardMask>) -> Editor {
+        let (width, length) = match mask {
+            Some(mask) => (mask.width(), mask.length()),
+            None => (width, length),
+        };
+        let (wanted_width, wanted_length) = (width, length);
+        let width = width.clamp(EDITOR_MIN_SIDE, EDITOR_MAX_SIDE);
+        let length = length.clamp(EDITOR_MIN_SIDE, EDITOR_MAX_SIDE);
+        let mut cells = Vec::with_capacity((width * length) as usize);
+        for row in 0..length {
+            for col in 0..width {
+                let hole = mask.is_some_and(|mask| !mask.contains(col, row));
+                cells.push(if hole { Cell::Hole } else { Cell::Safe });
+            }
+        }
+        Self {
+            width,
+            length,
+            cells,
+            tool: EditorTool::Mine,
+            verdict: None,
+            path: None,
+            // the cells past the edge are gone, say so rather than leave it to be noticed
+            message: ((width, length) != (wanted_width, wanted_length)).then(|| {
+                format!(
+                    "{}x{} DOESN'T FIT, CUT TO {}x{} ({} TO {} A SIDE)",
+                    wanted_width, wanted_length, width, length, EDITOR_MIN_SIDE, EDITOR_MAX_SIDE
+                )
+            }),
+            buttons: Vec::new(),
+            bar: ScreenRect::from_center(Vec2::ZERO, Vec2::ZERO),
+        }
+    }
+
+    pub fn tool(&self) -> EditorTool {
+        self.tool
+    }
+
+    pub fn set_tool(&mut self, tool: EditorTool) {
+        self.tool = tool;
+    }
+
+    pub fn mine_count(&self) -> usize {
+        self.cells
+            .iter()
+            .filter(|cell| **cell == Cell::Mine)
+            .count()
+    }
+
+    /// The board as it's drawn in the editor, every cell is a tile. Board index and
+    /// cell index are the same.
+    pub fn preview(&self) -> GameState {
+        minefield_from_layout(self.width, self.length, None, &self.mines(), 0)
+    }
+
+    /// The board as it's played, holes cut out.
+    pub fn board(&self) -> Result<GameState> {
+        if !self.cells.contains(&Cell::Safe) {
+            bail!("the board has no safe tiles");
+        }
+        let mask = if self.cells.contains(&Cell::Hole) {
+            let cells = self.cells.iter().map(|cell| *cell != Cell::Hole).collect();
+            Some(BoardMask::new(self.width, self.length, cells)?)
+        } else {
+            None
+        };
+        Ok(minefield_from_layout(
+            self.width,
+            self.length,
+            mask.as_ref(),
+            &self.mines(),
+            0,
+        ))
+    }
+
+    fn mines(&self) -> Vec<(u32, u32)> {
+        (0..self.cells.len() as u32)
+            .filter(|cell| self.cells[*cell as usize] == Cell::Mine)
+            .map(|cell| (cell % self.width, cell / self.width))
+            .collect()
+    }
+
+    /// How a tile of `preview` is drawn.
+    pub fn visual(&self, index: usize, preview: &GameState) -> TileVisual {
+        match self.cells[index] {
+            Cell::Safe => TileVisual::Revealed(preview.adjacent_mines(index)),
+            Cell::Mine => TileVisual::Mine,
+            Cell::Hole => TileVisual::Covered,
+        }
+    }
+
+    /// Left click paints the tool, or takes it off again, right click clears the cell.
+    /// Returns true if the cell changed.
+    pub fn paint(&mut self, index: usize, erase: bool) -> bool {
+        let Some(cell) = self.cells.get(index).copied() else {
+            return false;
+        };
+        let painted = match self.tool {
+            EditorTool::Mine => Cell::Mine,
+            EditorTool::Hole => Cell::Hole,
+        };
+        let new = if erase || cell == painted {
+            Cell::Safe
+        } else {
+            painted
+        };
+        if new == cell {
+            return false;
+        }
+        self.cells[index] = new;
+        self.edited();
+        true
+    }
+
+    /// Grows or shrinks the board from the top right, what's left keeps its cells.
+    /// Returns true if the size changed.
+    pub fn resize(&mut self, columns: i32, rows: i32) -> bool {
+        let width = (self.width as i32 + columns)
+            .clamp(EDITOR_MIN_SIDE as i32, EDITOR_MAX_SIDE as i32) as u32;
+        let length = (self.length as i32 + rows)
+            .clamp(EDITOR_MIN_SIDE as i32, EDITOR_MAX_SIDE as i32) as u32;
+        if (width, length) == (self.width, self.length) {
+            return false;
+        }
+        let mut cells = vec![Cell::Safe; (width * length) as usize];
+        for row in 0..length.min(self.length) {
+            for col in 0..width.min(self.width) {
+                cells[(col + row * width) as usize] = self.cells[(col + row * self.width) as usize];
+            }
+        }
+        self.width = width;
+        self.length = length;
+        self.cells = cells;
+        self.edited();
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.fill(Cell::Safe);
+        self.edited();
+    }
+
+    /// Runs the solver from the first opening to see if the board needs guessing.
+    pub fn check(&mut self) -> Result<Solution> {
+        let solution = solve(&self.board()?, None);
+        self.verdict = Some(solution);
+        Ok(solution)
+    }
+
+    /// Writes the board to the boards folder as json, the same file every time.
+    pub fn save(&mut self) -> Result<PathBuf> {
+        let board = self.board()?;
+        let path = self.path.clone().unwrap_or_else(|| {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs());
+            PathBuf::from(BOARD_DIR).join(format!(
+                "editor-{}.{}",
+                now,
+                BoardFormat::Json.extension()
+            ))
+        });
+        board_format::export(&board, &path)?;
+        self.message = Some(format!("SAVED {}", path.display()));
+        self.path = Some(path.clone());
+        Ok(path)
+    }
+
+    /// A line about the last thing that went wrong, shown in the toolbar.
+    pub fn set_message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+
+    // anything checked or said before is out of date now
+    fn edited(&mut self) {
+        self.verdict = None;
+        self.message = None;
+    }
+
+    fn status(&self) -> String {
+        let verdict = match self.verdict {
+            None => String::from("C CHECKS FOR GUESSES"),
+            Some(solution) if solution.guesses == 0 => {
+                format!("NO GUESSING NEEDED, {}", solution.rating().label())
+            }
+            Some(solution) => format!(
+                "NEEDS {} GUESSES, {}",
+                solution.guesses,
+                solution.rating().label()
+            ),
+        };
+        let mut status = format!(
+            "{}X{}  {} MINES  {}",
+            self.width,
+            self.length,
+            self.mine_count(),
+            verdict
+        );
+        if let Some(message) = &self.message {
+            status += "  ";
+            status += message;
+        }
+        status
+    }
+
+    /// Toolbar clicks, checked before the board like the HUD.
+    pub fn hit_test(&self, screen_position: Vec2) -> Option<EditorAction> {
+        self.buttons
+            .iter()
+            .find(|(rect, _)| rect.contains(screen_position))
+            .map(|(_, action)| *action)
+    }
+
+    pub fn covers(&self, screen_position: Vec2) -> bool {
+        self.bar.contains(screen_position)
+    }
+
+    /// The toolbar along the top of the window, in place of the HUD.
+    pub fn toolbar(
+        &mut self,
+        screen_size: winit::dpi::PhysicalSize<u32>,
+        sprites: &HashMap<String, Vec4>,
+    ) -> Vec<InstanceRaw> {
+        let width = screen_size.width as f32;
+        let top = screen_size.height as f32;
+        self.bar = ScreenRect {
+            min: Vec2::new(0.0, top - HUD_HEIGHT),
+            max: Vec2::new(width, top),
+        };
+        let background = *sprites.get("Tiles").expect("No Tiles :c");
+        let mut instances = vec![Instance::to_raw(
+            self.bar.center(),
+            0.0,
+            self.bar.size(),
+            Z_TOOLBAR_BACK,
+            background,
+            0,
+        )];
+
+        let buttons = [
+            (
+                tool_label(self.tool, EditorTool::Mine),
+                EditorAction::Tool(EditorTool::Mine),
+            ),
+            (
+                tool_label(self.tool, EditorTool::Hole),
+                EditorAction::Tool(EditorTool::Hole),
+            ),
+            (String::from("W-"), EditorAction::Resize(-1, 0)),
+            (String::from("W+"), EditorAction::Resize(1, 0)),
+            (String::from("H-"), EditorAction::Resize(0, -1)),
+            (String::from("H+"), EditorAction::Resize(0, 1)),
+            (String::from("CLEAR"), EditorAction::Clear),
+            (String::from("CHECK"), EditorAction::Check),
+            (String::from("SAVE"), EditorAction::Save),
+            (String::from("PLAY"), EditorAction::Play),
+            (String::from("EXIT"), EditorAction::Exit),
+        ];
+        self.buttons.clear();
+        let middle = top - TOOLBAR_MARGIN - BUTTON_HEIGHT / 2.0;
+        let mut left = TOOLBAR_MARGIN;
+        for (label, action) in buttons {
+            let label_size = text_size(&label, BUTTON_TEXT_HEIGHT);
+            let button_width = label_size.x + BUTTON_PADDING * 2.0;
+            let rect = ScreenRect::from_center(
+                Vec2::new(left + button_width / 2.0, middle),
+                Vec2::new(button_width, BUTTON_HEIGHT),
+            );
+            instances.push(Instance::to_raw(
+                rect.center(),
+                0.0,
+                rect.size(),
+                Z_TOOLBAR_BACK + 0.01,
+                background,
+                0,
+            ));
+            instances.extend(text_instances(
+                &label,
+                rect.center() - label_size / 2.0,
+                BUTTON_TEXT_HEIGHT,
+                Z_TOOLBAR_TEXT,
+                sprites,
+            ));
+            self.buttons.push((rect, action));
+            left += button_width + TOOLBAR_MARGIN / 2.0;
+        }
+
+        instances.extend(text_instances(
+            &self.status(),
+            Vec2::new(TOOLBAR_MARGIN, top - HUD_HEIGHT + TOOLBAR_MARGIN / 2.0),
+            STATUS_TEXT_HEIGHT,
+            Z_TOOLBAR_TEXT,
+            sprites,
+        ));
+        instances
+    }
+}
+
+// the picked tool is marked
+fn tool_label(picked: EditorTool, tool: EditorTool) -> String {
+    if picked == tool {
+        format!(">{}", tool.label())
+    } else {
+        String::from(tool.label())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn painting_and_resizing_keep_the_layout() {
+        let mut editor = Editor::new(4, 3, None);
+        assert!(editor.paint(0, false));
+        assert!(editor.paint(5, false));
+        editor.set_tool(EditorTool::Hole);
+        assert!(editor.paint(11, false));
+        // the hole tool on a mine swaps it, right click clears
+        assert!(editor.paint(5, false));
+        assert!(editor.paint(5, true));
+        assert!(!editor.paint(5, true));
+        assert_eq!(editor.mine_count(), 1);
+
+        // the hole on the top row goes with it
+        assert!(editor.resize(1, -1));
+        let board = editor.board().unwrap();
+        assert_eq!(board.mine_count(), 1);
+        assert!(board.board()[0].has_mine());
+        assert_eq!(board.board().len(), (editor.width * editor.length) as usize);
+
+        let preview = editor.preview();
+        assert_eq!(editor.visual(1, &preview), TileVisual::Revealed(1));
+        assert_eq!(editor.visual(0, &preview), TileVisual::Mine);
+    }
+
+    #[test]
+    fn oversized_boards_are_cut_with_a_message() {
+        let editor = Editor::new(200, 50, None);
+        assert_eq!((editor.width, editor.length), (EDITOR_MAX_SIDE, 50));
+        assert!(editor.message.as_ref().unwrap().contains("CUT TO 100x50"));
+        assert!(Editor::new(30, 16, None).message.is_none());
+    }
+
+    #[test]
+    fn holes_are_cut_out_and_checking_finds_guesses() {
+        let mut editor = Editor::new(3, 3, None);
+        editor.set_tool(EditorTool::Hole);
+        editor.paint(8, false);
+        assert_eq!(editor.board().unwrap().board().len(), 8);
+        assert_eq!(editor.check().unwrap().guesses, 0);
+
+        // one mine in a 2x2 touches every tile, no number gives it away
+        let mut coin_flip = Editor::new(2, 2, None);
+        coin_flip.paint(0, false);
+        assert!(coin_flip.check().unwrap().guesses > 0);
+        assert!(coin_flip.paint(0, true));
+        assert!(coin_flip.verdict.is_none());
+    }
+}
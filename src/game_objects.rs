@@ -1,61 +1,252 @@
+use crate::animation::Overlay;
+use crate::board_mask::BoardMask;
+use crate::config::{GameConfig, Rules};
+use crate::graphics_and_window::tex_from_grid;
 use crate::instance::{Instance, InstanceRaw};
-use crate::{graphics_and_window::tex_from_coords, BOARD_LENGTH, BOARD_WIDTH};
-use crate::{GameState, Mines, Tiles, MINE_COUNT};
+use crate::text::{glyph_cells, stamp_glyph};
+use crate::{GamePhase, GameState, Mines, TileVisual, Tiles};
 use glam::{Vec2, Vec4};
-use std::collections::HashMap;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
 
 pub const Z_BOARD: f32 = 0.01;
-pub const Z_MINE: f32 = 0.02;
-
-pub fn create_hashmap() -> HashMap<String, Vec4> {
-    let mut sprites = HashMap::new();
-    sprites.insert(String::from("Teto"), tex_from_coords([28, 0, 32, 4]));
-    sprites.insert(String::from("Square"), tex_from_coords([0, 0, 1, 1]));
-    sprites.insert(String::from("Circle"), tex_from_coords([1, 0, 2, 1]));
-
-    // game objects
-    sprites.insert(String::from("Mines"), tex_from_coords([0, 1, 1, 2]));
-    sprites.insert(String::from("Tiles"), tex_from_coords([0, 2, 1, 3]));
-    sprites.insert(String::from("Flags"), tex_from_coords([2, 1, 3, 2]));
-
-    // number tiles
-    sprites.insert(String::from("1"), tex_from_coords([2, 0, 3, 1]));
-    sprites.insert(String::from("2"), tex_from_coords([3, 0, 4, 1]));
-    sprites.insert(String::from("3"), tex_from_coords([4, 0, 5, 1]));
-    sprites.insert(String::from("4"), tex_from_coords([5, 0, 6, 1]));
-    sprites.insert(String::from("5"), tex_from_coords([6, 0, 7, 1]));
-    sprites.insert(String::from("6"), tex_from_coords([7, 0, 8, 1]));
-    sprites.insert(String::from("7"), tex_from_coords([8, 0, 9, 1]));
-    sprites.insert(String::from("8"), tex_from_coords([9, 0, 10, 1]));
-    sprites.insert(String::from("0"), tex_from_coords([0, 0, 1, 1]));
+// animating tiles and their overlays go over their neighbours
+const Z_ANIMATING: f32 = 0.02;
+const Z_OVERLAY: f32 = 0.03;
+/// Cells the tile states are drawn into, the row above the font.
+/// [left, top, right, bottom] like a theme's sprites.
+pub fn tile_state_cells(grid: u32) -> [u16; 4] {
+    let row = glyph_cells(grid)[1] - 1;
+    [0, row, 4, row + 1]
+}
 
-    // return
-    sprites
+/// Adds the sprites built by stamp_tile_states to a theme's sprites.
+pub fn add_tile_state_sprites(sprites: &mut HashMap<String, Vec4>, grid: u32) {
+    let row = tile_state_cells(grid)[1];
+    for (col, name) in ["Flagged", "Questioned", "WrongFlag", "Exploded"]
+        .into_iter()
+        .enumerate()
+    {
+        let col = col as u16;
+        sprites.insert(
+            String::from(name),
+            tex_from_grid([col, row, col + 1, row + 1], grid),
+        );
+    }
+}
+
+/// Builds the tile states that need more than one sprite (a flag on a covered tile,
+/// a mine on red...) into an empty spritesheet row, so every tile is one instance.
+/// Has to run after stamp_glyph_atlas.
+pub fn stamp_tile_states(image: &mut image::RgbaImage, sprites: &HashMap<String, Vec4>, grid: u32) {
+    let cell = image.width() / grid;
+    let row = tile_state_cells(grid)[1] as u32;
+    let sprite = |name: &str| *sprites.get(name).expect("theme checks its sprites");
+    let covered = sprite("Tiles");
+    copy_rect(image, covered, (0, row), false, grid);
+    copy_rect(image, sprite("Flags"), (0, row), true, grid);
+    copy_rect(image, covered, (1, row), false, grid);
+    stamp_glyph(image, '?', (1, row), image::Rgba([0, 0, 0, 255]), grid);
+    // a wrong flag is the flagged tile with a cross over it
+    copy_rect(image, sprite("Flagged"), (2, row), false, grid);
+    stamp_glyph(image, 'X', (2, row), image::Rgba([220, 30, 30, 255]), grid);
+    // the mine sprite is drawn on white, swap the white for red
+    copy_rect(image, sprite("Mines"), (3, row), false, grid);
+    for y in 0..cell {
+        for x in 0..cell {
+            let pixel = image.get_pixel_mut(3 * cell + x, row * cell + y);
+            if pixel.0[0] > 240 && pixel.0[1] > 240 && pixel.0[2] > 240 {
+                *pixel = image::Rgba([230, 40, 40, 255]);
+            }
+        }
+    }
 }
 
-pub fn create_minefield(sprites: HashMap<String, Vec4>) -> (Vec<InstanceRaw>, GameState) {
+// scales a sprite (texture coordinates) into one cell of the grid, alpha
+// blending it over what's there when `blend` is set
+fn copy_rect(image: &mut image::RgbaImage, from: Vec4, to: (u32, u32), blend: bool, grid: u32) {
+    let cell = image.width() / grid;
+    let (width, height) = (image.width() as f32, image.height() as f32);
+    for y in 0..cell {
+        for x in 0..cell {
+            let u = from.x + (from.z - from.x) * (x as f32 + 0.5) / cell as f32;
+            let v = from.y + (from.w - from.y) * (y as f32 + 0.5) / cell as f32;
+            let top = *image.get_pixel(
+                ((u * width) as u32).min(image.width() - 1),
+                ((v * height) as u32).min(image.height() - 1),
+            );
+            let bottom = image.get_pixel_mut(to.0 * cell + x, to.1 * cell + y);
+            if !blend {
+                *bottom = top;
+                continue;
+            }
+            let alpha = top.0[3] as u32;
+            for channel in 0..3 {
+                bottom.0[channel] = ((top.0[channel] as u32 * alpha
+                    + bottom.0[channel] as u32 * (255 - alpha))
+                    / 255) as u8;
+            }
+            bottom.0[3] = bottom.0[3].max(top.0[3]);
+        }
+    }
+}
+
+/// The one instance a tile is drawn with.
+pub fn tile_instance(
+    tile: &Tiles,
+    visual: TileVisual,
+    sprites: &HashMap<String, Vec4>,
+) -> InstanceRaw {
+    scaled_tile_instance(tile, visual, Vec2::ONE, sprites)
+}
+
+/// A tile part way through an animation, drawn over its neighbours.
+pub fn scaled_tile_instance(
+    tile: &Tiles,
+    visual: TileVisual,
+    scale: Vec2,
+    sprites: &HashMap<String, Vec4>,
+) -> InstanceRaw {
+    let sprite = match visual {
+        TileVisual::Covered => String::from("Tiles"),
+        TileVisual::Flagged => String::from("Flagged"),
+        TileVisual::Questioned => String::from("Questioned"),
+        TileVisual::Revealed(count) => count.to_string(),
+        TileVisual::Exploded => String::from("Exploded"),
+        TileVisual::Mine => String::from("Mines"),
+        TileVisual::WrongFlag => String::from("WrongFlag"),
+    };
+    let size = tile.size;
+    let instance = Instance::to_raw(
+        tile.position + Vec2::splat(0.5 * size),
+        0.0,
+        scale * size,
+        if scale == Vec2::ONE {
+            Z_BOARD
+        } else {
+            Z_ANIMATING
+        },
+        *sprites.get(&sprite).expect("no sprite for tile state"),
+        0,
+    );
+    match visual {
+        TileVisual::Revealed(count) if count > 0 => instance.with_digit(count),
+        _ => instance,
+    }
+}
+
+pub fn overlay_instance(
+    tile: &Tiles,
+    overlay: Overlay,
+    sprites: &HashMap<String, Vec4>,
+) -> InstanceRaw {
+    let size = tile.size;
+    Instance::to_raw(
+        tile.position + (Vec2::splat(0.5) + overlay.offset) * size,
+        0.0,
+        Vec2::splat(overlay.scale * size),
+        Z_OVERLAY,
+        *sprites.get(overlay.sprite).expect("no sprite for overlay"),
+        0,
+    )
+}
+
+pub fn create_minefield(config: &GameConfig, mask: Option<&BoardMask>, seed: u64) -> GameState {
     // initialize
-    let mut objects: Vec<InstanceRaw> = Vec::new();
-    let mut board: Vec<Tiles> = Vec::with_capacity((BOARD_WIDTH * BOARD_LENGTH) as usize);
-    let mut mine_size: f32 = 1.0 / BOARD_LENGTH as f32;
-    if BOARD_WIDTH > BOARD_LENGTH {
-        mine_size = 1.0 / BOARD_WIDTH as f32;
-    }
-    // Tiles
-
-    for row in 0..BOARD_LENGTH {
-        for col in 0..BOARD_WIDTH {
-            objects.push(Instance::to_raw(
-                Vec2::new(
-                    (col as f32 + 0.5) * mine_size,
-                    (row as f32 + 0.5) * mine_size,
-                ),
-                0.0,
-                Vec2::new(mine_size, mine_size),
-                Z_BOARD,
-                *sprites.get("Tiles").expect("No Tiles :c"),
-                0,
-            ));
+    let (width, length) = match mask {
+        Some(mask) => (mask.width(), mask.length()),
+        None => (config.width, config.length),
+    };
+    let mut board = lay_tiles(width, length, mask);
+    // Mines
+
+    // leave at least one safe tile on tiny masks
+    let total_mines = config.mines.min(board.len() as u32 - 1);
+    // the same seed, config and mask always deal the same board
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut mine_count = 0;
+    while mine_count < total_mines {
+        let index = rng.random_range(0..board.len());
+        if !board[index].has_mine() {
+            board[index].set_mine(Some(default_mine(mine_count)));
+            mine_count += 1;
+        }
+    }
+    // return
+    GameState::new(board, width, length, seed)
+}
+
+/// Moves mines off the first click so it's allowed by the rules, see `Rules`. They go
+/// to free tiles picked with the game's seed, so a seed and first click always end
+/// up on the same board. Returns true if any mine moved.
+pub fn prepare_first_click(game_state: &mut GameState, rules: Rules, tile_index: usize) -> bool {
+    if game_state.phase() != GamePhase::Ready {
+        return false;
+    }
+    let mut cleared = vec![tile_index];
+    if rules == Rules::SafeOpening {
+        cleared.extend(game_state.neighbors(game_state.board[tile_index].board_position));
+    }
+    let moving: Vec<usize> = cleared
+        .iter()
+        .copied()
+        .filter(|index| game_state.board[*index].has_mine())
+        .collect();
+    if moving.is_empty() {
+        return false;
+    }
+    let free: Vec<usize> = (0..game_state.board.len())
+        .filter(|index| !game_state.board[*index].has_mine() && !cleared.contains(index))
+        .collect();
+    if free.len() < moving.len() {
+        // no room for an opening on dense boards, a safe click still has to happen
+        return rules == Rules::SafeOpening
+            && prepare_first_click(game_state, Rules::Classic, tile_index);
+    }
+    let mut rng = StdRng::seed_from_u64(game_state.seed());
+    let targets = rand::seq::index::sample(&mut rng, free.len(), moving.len());
+    for (from, to) in moving.into_iter().zip(targets) {
+        let mine = game_state.board[from].mine.take();
+        game_state.board[free[to]].set_mine(mine);
+    }
+    true
+}
+
+/// A board with mines exactly on the given (col, row) cells, row 0 at the bottom,
+/// for layouts read from a file rather than dealt. Mines on holes are dropped.
+pub fn minefield_from_layout(
+    width: u32,
+    length: u32,
+    mask: Option<&BoardMask>,
+    mines: &[(u32, u32)],
+    seed: u64,
+) -> GameState {
+    let mut board = lay_tiles(width, length, mask);
+    let mines: HashSet<(u32, u32)> = mines.iter().copied().collect();
+    let mut mine_count = 0;
+    for tile in board.iter_mut() {
+        let position = tile.board_position;
+        if mines.contains(&(position.x as u32, position.y as u32)) {
+            tile.set_mine(Some(default_mine(mine_count)));
+            mine_count += 1;
+        }
+    }
+    GameState::new(board, width, length, seed)
+}
+
+// tiles of a width x length grid from the bottom row up, without the mask's holes
+fn lay_tiles(width: u32, length: u32, mask: Option<&BoardMask>) -> Vec<Tiles> {
+    let mut board: Vec<Tiles> = Vec::with_capacity(width as usize * length as usize);
+    let mut mine_size: f32 = 1.0 / length as f32;
+    if width > length {
+        mine_size = 1.0 / width as f32;
+    }
+    for row in 0..length {
+        for col in 0..width {
+            if mask.is_some_and(|mask| !mask.contains(col, row)) {
+                continue;
+            }
             board.push(Tiles::new(
                 Vec2::new(col as f32, row as f32),
                 Vec2::new(col as f32 * mine_size, row as f32 * mine_size),
@@ -64,19 +255,72 @@ pub fn create_minefield(sprites: HashMap<String, Vec4>) -> (Vec<InstanceRaw>, Ga
             ));
         }
     }
-    // Mines
+    board
+}
 
-    let mut mine_count = 0;
-    while mine_count < MINE_COUNT {
-        let index = rand::random_range(0..((BOARD_LENGTH * BOARD_WIDTH) - 1)) as usize;
-        if !board[index].has_mine() {
-            board[index].set_mine(Some(Mines::Default(crate::CommonMineState {
-                active: false,
-                mine_index: 1 + mine_count as u16,
-            })));
-            mine_count += 1;
-        }
+fn default_mine(mine_count: u32) -> Mines {
+    Mines::Default(crate::CommonMineState {
+        active: false,
+        mine_index: 1 + mine_count,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_mine_gets_its_own_index_past_u16() {
+        let config = GameConfig {
+            width: 300,
+            length: 300,
+            mines: 70_000,
+            ..Default::default()
+        };
+        let game_state = create_minefield(&config, None, 1);
+        let indices: HashSet<u32> = game_state
+            .board
+            .iter()
+            .filter_map(|tile| tile.get_mine_index())
+            .collect();
+        assert_eq!(indices.len(), 70_000);
+        assert_eq!(indices.iter().max(), Some(&70_000));
+    }
+
+    fn mines(game_state: &GameState) -> Vec<usize> {
+        (0..game_state.board.len())
+            .filter(|index| game_state.board[*index].has_mine())
+            .collect()
+    }
+
+    #[test]
+    fn first_click_moves_mines_the_same_way_every_time() {
+        let config = GameConfig {
+            width: 9,
+            length: 9,
+            mines: 72,
+            rules: Rules::SafeOpening,
+            ..Default::default()
+        };
+        let mut game_state = create_minefield(&config, None, 5);
+        let click = mines(&game_state)[0];
+        assert!(prepare_first_click(&mut game_state, config.rules, click));
+        assert!(!game_state.board[click].has_mine());
+        assert_eq!(game_state.adjacent_mines(click), 0);
+        assert_eq!(mines(&game_state).len(), 72);
+        let mut again = create_minefield(&config, None, 5);
+        prepare_first_click(&mut again, config.rules, click);
+        assert_eq!(mines(&again), mines(&game_state));
+
+        // one free tile is no room for an opening, but the click is still safe
+        let full = GameConfig {
+            mines: 80,
+            ..config
+        };
+        let mut game_state = create_minefield(&full, None, 5);
+        let click = mines(&game_state)[0];
+        assert!(prepare_first_click(&mut game_state, full.rules, click));
+        assert!(!game_state.board[click].has_mine());
+        assert_eq!(mines(&game_state).len(), 80);
     }
-    // return
-    (objects, GameState::new(board))
 }
@@ -1,23 +1,41 @@
+use crate::analysis::GameStats;
+use crate::animation::Animations;
+use crate::board_format;
+use crate::board_mask::BoardMask;
 use crate::camera::CameraUniform;
+use crate::camera::CameraView;
 use crate::camera::OrthographicCamera;
-use crate::game_objects::create_hashmap;
-use crate::game_objects::create_minefield;
-use crate::game_objects::Z_MINE;
+use crate::cli::Launch;
+use crate::config::GameConfig;
+use crate::editor::{self, Editor, EditorAction};
+use crate::game_objects::{create_minefield, prepare_first_click};
+use crate::game_objects::{overlay_instance, scaled_tile_instance, tile_instance};
+use crate::hud::{Hud, HudAction};
 use crate::instance::Instance;
 use crate::instance::InstanceRaw;
+use crate::instance::{InstanceSlots, SlotChanges};
+use crate::palette::PaletteUniform;
+use crate::particles::{Particles, MAX_PARTICLES};
+use crate::puzzle::{self, ProgressDb, PuzzlePack, PuzzleRun, PROGRESS_FILE};
+use crate::replay::{saved_replays, Action, Playback, Replay, Step};
+use crate::save::{self, AUTOSAVE, SLOTS};
+use crate::screens::{Menu, MenuAction, MenuContext, Screen, ScreenStack};
+use crate::stats::{GameRecord, StatsDb, STATS_FILE};
+use crate::text::text_instances;
 use crate::texture::Texture;
-use crate::CommonMineState;
+use crate::theme::{Theme, ThemeWatcher};
+use crate::undo::{History, UndoPolicy};
+use crate::GamePhase;
 use crate::GameState;
-use crate::Mines;
-use crate::Tiles;
-use crate::BOARD_LENGTH;
-use crate::BOARD_WIDTH;
+use crate::TileVisual;
 use crate::CAMERA_MOVE_SPEED;
 use glam::{Vec2, Vec4};
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::time::Instant;
 
 use wgpu::util::DeviceExt;
+
 use winit::{
     event::*,
     event_loop::EventLoop,
@@ -25,6 +43,16 @@ use winit::{
     window::{Window, WindowBuilder},
 };
 
+const FPS_TEXT_HEIGHT: f32 = 14.0;
+const Z_UI_TEXT: f32 = 0.5;
+// overlay slots for animations, flags dropping in and the like
+const MAX_OVERLAYS: usize = 64;
+// each wheel notch zooms by this much
+const ZOOM_STEP: f32 = 1.1;
+// trackpads scroll in pixels, this many make up one wheel notch
+const PIXELS_PER_LINE: f32 = 40.0;
+const REPLAY_TEXT_HEIGHT: f32 = 14.0;
+
 struct State<'a> {
     surface: wgpu::Surface<'a>,
     device: wgpu::Device,
@@ -37,21 +65,30 @@ struct State<'a> {
     index_buffer: wgpu::Buffer,
     num_indices: u32,
     diffuse_bind_group: wgpu::BindGroup,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
     camera: OrthographicCamera,
     camera_uniform: CameraUniform,
     camera_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
+    // screen space camera, (0, 0) is the bottom left pixel of the window
+    ui_camera_uniform: CameraUniform,
+    ui_camera_buffer: wgpu::Buffer,
+    ui_camera_bind_group: wgpu::BindGroup,
+    // number colors and patterns, shared by every pass
+    palette_uniform: PaletteUniform,
+    palette_buffer: wgpu::Buffer,
+    palette_bind_group: wgpu::BindGroup,
     // instances
-    instances_raw: Vec<InstanceRaw>,
-    instances_hash: HashMap<String, Vec<InstanceRaw>>,
+    // world instances live in fixed slots so a click only rewrites what it touched
+    world_instances: InstanceSlots,
     instance_buffer: wgpu::Buffer,
+    ui_instances_raw: Vec<InstanceRaw>,
+    ui_instances_hash: HashMap<String, Vec<InstanceRaw>>,
+    ui_instance_buffer: wgpu::Buffer,
     //
     depth_texture: Texture,
     blank_instance: Vec<InstanceRaw>,
-    camera_left: f32,
-    camera_right: f32,
-    camera_up: f32,
-    camera_down: f32,
+    view: CameraView,
     // Keypresses
     is_up_pressed: bool,
     is_down_pressed: bool,
@@ -64,16 +101,46 @@ struct State<'a> {
     fps_count: u32,
     one_sec_fps: Instant,
     game_state: GameState,
+    board_mask: Option<BoardMask>,
+    // the file an imported board came from, its layout is played as it is
+    board_source: Option<PathBuf>,
+    // the board being drawn on the editor screen
+    editor: Option<Editor>,
+    puzzle: Option<PuzzleRun>,
+    hud: Hud,
+    game_config: GameConfig,
+    animations: Animations,
+    particles: Particles,
+    // particle slots written last frame, the ones past the live count get cleared
+    particles_shown: usize,
+    // the camera was shaken last frame and has to be put back
+    camera_shaken: bool,
+    // the game being played, None for loaded saves that didn't start on this run
+    recording: Option<Replay>,
+    playback: Option<Playback>,
+    // last status line drawn for the playback, redrawn when it changes
+    playback_status: String,
+    history: History,
+    // recorded steps of the undone actions, put back on redo
+    undone_steps: Vec<Step>,
+    // the game's result went into the stats, only its first ending counts
+    result_recorded: bool,
+    personal_best: bool,
+    results: Option<GameStats>,
+    // Screens
+    screens: ScreenStack,
+    menu: Option<Menu>,
     // Sprites
     sprites: HashMap<String, Vec4>,
+    theme: Theme,
+    theme_watcher: Option<ThemeWatcher>,
 }
 
 impl<'a> State<'a> {
     // Creating some of the wgpu types requires async code
-    async fn new(window: &'a Window) -> State<'a> {
+    async fn new(window: &'a Window, game_config: GameConfig) -> State<'a> {
+        // physical pixels, the surface and the cursor both use them so HiDPI needs no scaling
         let size = window.inner_size();
-        let smaller_dimension = size.width.min(size.height);
-        let size = winit::dpi::PhysicalSize::new(smaller_dimension, smaller_dimension);
         // The instance is a handle to our GPU
         // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
@@ -135,8 +202,8 @@ impl<'a> State<'a> {
         surface.configure(&device, &config);
 
         // Sprites Stuff!!!=
-        let spritesheet = ["src/sprites/spritesheet1.png"];
-        let sprites = create_hashmap();
+        let (theme, atlas) = Theme::load_or_placeholder(&game_config.theme);
+        let sprites = theme.sprites().clone();
         // Texture stuff!!
         let blank_instance = vec![Instance::to_raw(
             Vec2::new(0.0, 0.0),
@@ -147,36 +214,13 @@ impl<'a> State<'a> {
             0,
         )];
 
-        let mut diffuse_textures: Vec<Texture> = vec![];
-        let mut diffuse_bytes_vec: Vec<Vec<u8>> = Vec::with_capacity(spritesheet.len());
-        // -- Loading Textures --
-        for sprites_path in spritesheet.iter() {
-            match std::fs::read(sprites_path) {
-                Ok(diffuse_bytes) => {
-                    diffuse_bytes_vec.push(diffuse_bytes);
-                }
-                Err(e) => {
-                    eprintln!("Error reading file '{}': {:?}", sprites_path, e);
-                }
-            }
-        }
-        match Texture::from_bytes(&device, &queue, &diffuse_bytes_vec) {
-            Ok(texture) => {
-                diffuse_textures.push(texture);
-            }
-            Err(e) => {
-                eprintln!("Error creating texture from bytes {:?}", e)
-            }
-        }
-
         // -- Instance Buffer --
-        let instances_raw: Vec<InstanceRaw> = blank_instance.clone();
+        let world_instances = InstanceSlots::new();
         let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Instance Buffer"),
-            contents: bytemuck::cast_slice(&instances_raw),
+            contents: bytemuck::cast_slice(&blank_instance),
             usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
         });
-        let instances_hash: HashMap<String, Vec<InstanceRaw>> = HashMap::new();
 
         // -- Texture Bind Group Layout!! --
         let texture_bind_group_layout =
@@ -190,7 +234,7 @@ impl<'a> State<'a> {
                             view_dimension: wgpu::TextureViewDimension::D2Array,
                             sample_type: wgpu::TextureSampleType::Float { filterable: true },
                         },
-                        count: std::num::NonZeroU32::new(diffuse_textures.len() as u32),
+                        count: std::num::NonZeroU32::new(1),
                     },
                     wgpu::BindGroupLayoutEntry {
                         binding: 1,
@@ -203,39 +247,18 @@ impl<'a> State<'a> {
                 ],
                 label: Some("texture_bind_group_layout"),
             });
-        let diffuse_views: Vec<&wgpu::TextureView> = diffuse_textures
-            .iter()
-            .map(|texture| &texture.view)
-            .collect();
-        let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureViewArray(&diffuse_views),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_textures[0].sampler),
-                },
-            ],
-            label: Some("diffuse_bind_group"),
-        });
+        let diffuse_bind_group =
+            create_diffuse_bind_group(&device, &queue, &texture_bind_group_layout, atlas)
+                .expect("Error creating texture from atlas");
+        let theme_watcher = watch_theme(&theme);
 
         // Camera
-        let initial_camera_left = -0.2;
-        let initial_camera_right = 1.2;
-        let initial_camera_up = 1.2;
-        let initial_camera_down = -0.2;
-
-        let camera = OrthographicCamera::new(
-            initial_camera_left,
-            initial_camera_right,
-            initial_camera_down,
-            initial_camera_up,
-            -1.0,
-            1.0,
+        let view = CameraView::new(
+            Vec2::splat(0.5),
+            1.4,
+            size.width as f32 / size.height as f32,
         );
+        let camera = view.projection(Vec2::ZERO);
         let mut camera_uniform = CameraUniform::new();
         camera_uniform.update_view_proj(&camera);
 
@@ -269,6 +292,60 @@ impl<'a> State<'a> {
             label: Some("camera_bind_group"),
         });
 
+        let mut ui_camera_uniform = CameraUniform::new();
+        ui_camera_uniform.update_view_proj(&screen_camera(size));
+        let ui_camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("UI Camera Buffer"),
+            contents: bytemuck::cast_slice(&[ui_camera_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let ui_camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: ui_camera_buffer.as_entire_binding(),
+            }],
+            label: Some("ui_camera_bind_group"),
+        });
+
+        let palette_uniform = PaletteUniform::new(game_config.palette, game_config.number_patterns);
+        let palette_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Palette Buffer"),
+            contents: bytemuck::cast_slice(&[palette_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let palette_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("palette_bind_group_layout"),
+            });
+        let palette_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &palette_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: palette_buffer.as_entire_binding(),
+            }],
+            label: Some("palette_bind_group"),
+        });
+
+        // -- UI Instance Buffer --
+        let ui_instances_raw: Vec<InstanceRaw> = blank_instance.clone();
+        let ui_instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("UI Instance Buffer"),
+            contents: bytemuck::cast_slice(&ui_instances_raw),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+        let ui_instances_hash: HashMap<String, Vec<InstanceRaw>> = HashMap::new();
+
         // Shaders
 
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
@@ -285,7 +362,11 @@ impl<'a> State<'a> {
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&texture_bind_group_layout, &camera_bind_group_layout],
+                bind_group_layouts: &[
+                    &texture_bind_group_layout,
+                    &camera_bind_group_layout,
+                    &palette_bind_group_layout,
+                ],
                 push_constant_ranges: &[],
             });
 
@@ -369,19 +450,25 @@ impl<'a> State<'a> {
             index_buffer,
             num_indices,
             diffuse_bind_group,
+            texture_bind_group_layout,
             camera,
             camera_uniform,
             camera_buffer,
             camera_bind_group,
-            instances_raw,
-            instances_hash,
+            ui_camera_uniform,
+            ui_camera_buffer,
+            ui_camera_bind_group,
+            palette_uniform,
+            palette_buffer,
+            palette_bind_group,
+            world_instances,
             instance_buffer,
+            ui_instances_raw,
+            ui_instances_hash,
+            ui_instance_buffer,
             depth_texture,
             blank_instance,
-            camera_up: initial_camera_up,
-            camera_left: initial_camera_left,
-            camera_right: initial_camera_right,
-            camera_down: initial_camera_down,
+            view,
             //Keypresses
             is_up_pressed: false,
             is_down_pressed: false,
@@ -393,7 +480,29 @@ impl<'a> State<'a> {
             time_delta: new_time_delta,
             one_sec_fps: new_fps_one_sec,
             fps_count: new_fps_count,
-            game_state: GameState { board: Vec::new() },
+            game_state: GameState::empty(),
+            board_mask: None,
+            board_source: None,
+            editor: None,
+            puzzle: None,
+            hud: Hud::new(),
+            animations: Animations::new(game_config.animations),
+            game_config,
+            theme,
+            theme_watcher,
+            particles: Particles::new(),
+            particles_shown: 0,
+            camera_shaken: false,
+            recording: None,
+            playback: None,
+            playback_status: String::new(),
+            history: History::new(UndoPolicy::Off),
+            undone_steps: Vec::new(),
+            result_recorded: false,
+            personal_best: false,
+            results: None,
+            screens: ScreenStack::new(Screen::MainMenu),
+            menu: None,
             // Sprites!!!
             sprites,
         }
@@ -405,46 +514,25 @@ impl<'a> State<'a> {
 
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
-            let old_aspect_ratio = (self.size.width as f32) / (self.size.height as f32);
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
             self.surface.configure(&self.device, &self.config);
             self.depth_texture =
                 Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
-            let aspect_ratio = (new_size.width as f32) / (new_size.height as f32);
-
-            let current_world_width = self.camera_right - self.camera_left;
-            let current_world_height = self.camera_up - self.camera_down;
-            let current_center_x = self.camera_left + current_world_width / 2.0;
-            let current_center_y = self.camera_down + current_world_height / 2.0;
-
-            if aspect_ratio > old_aspect_ratio {
-                // Window became wider, maintain world height
-                let new_world_width = current_world_height * aspect_ratio;
-                self.camera_left = current_center_x - new_world_width / 2.0;
-                self.camera_right = current_center_x + new_world_width / 2.0;
-            } else if aspect_ratio < old_aspect_ratio {
-                // Window became narrower, maintain world width
-                let new_world_height = current_world_width / aspect_ratio;
-                self.camera_down = current_center_y - new_world_height / 2.0;
-                self.camera_up = current_center_y + new_world_height / 2.0;
-            }
-            let camera = OrthographicCamera::new(
-                self.camera_left,
-                self.camera_right,
-                self.camera_down,
-                self.camera_up,
-                -1.0,
-                1.0,
-            );
-
-            self.camera_uniform.update_view_proj(&camera);
+            // a view that frames the board keeps framing it, see CameraView::set_aspect
+            self.view
+                .set_aspect(new_size.width as f32 / new_size.height as f32);
+            self.update_camera();
+            self.ui_camera_uniform
+                .update_view_proj(&screen_camera(self.size));
             self.queue.write_buffer(
-                &self.camera_buffer,
+                &self.ui_camera_buffer,
                 0,
-                bytemuck::cast_slice(&[self.camera_uniform]),
+                bytemuck::cast_slice(&[self.ui_camera_uniform]),
             );
+            self.refresh_menu();
+            self.refresh_toolbar();
         }
     }
 
@@ -472,12 +560,7 @@ impl<'a> State<'a> {
                     view: &view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.0,
-                            g: 0.0,
-                            b: 0.0,
-                            a: 1.0,
-                        }),
+                        load: wgpu::LoadOp::Clear(self.theme.background),
                         store: wgpu::StoreOp::Store,
                     },
                 })],
@@ -496,10 +579,65 @@ impl<'a> State<'a> {
             render_pass.set_pipeline(&self.render_pipeline);
             render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
             render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.palette_bind_group, &[]);
             render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
             render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
             render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16); // 1
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..self.instances_raw.len() as _);
+            if self.screens.shows_board() && !self.world_instances.is_empty() {
+                // only the rows on screen, big boards have far more tiles than pixels
+                let layer_start = self.world_instances.layer_range("Tiles").start;
+                for range in self
+                    .game_state
+                    .visible_ranges(self.view.min(), self.view.max())
+                {
+                    render_pass.draw_indexed(
+                        0..self.num_indices,
+                        0,
+                        (layer_start + range.start) as u32..(layer_start + range.end) as u32,
+                    );
+                }
+                // overlays and particles sit right after each other
+                let effects = self.world_instances.layer_range("Overlays").start
+                    ..self.world_instances.layer_range("Particles").end;
+                render_pass.draw_indexed(
+                    0..self.num_indices,
+                    0,
+                    effects.start as u32..effects.end as u32,
+                );
+            }
+        }
+        {
+            // UI goes on top of the world, so it gets its own depth
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("UI Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                occlusion_query_set: None,
+                timestamp_writes: None,
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.ui_camera_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.palette_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, self.ui_instance_buffer.slice(..));
+            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            render_pass.draw_indexed(0..self.num_indices, 0, 0..self.ui_instances_raw.len() as _);
         }
 
         // submit will accept anything that implements IntoIter
@@ -514,261 +652,1059 @@ impl<'a> State<'a> {
         self.fps_count += 1;
         if self.one_sec_fps.elapsed().as_secs() > 0 {
             // println!("{}", self.fps_count);
+            let fps_text = format!("FPS {}", self.fps_count);
+            self.set_ui_layer(
+                "Fps",
+                text_instances(
+                    &fps_text,
+                    Vec2::new(8.0, 8.0),
+                    FPS_TEXT_HEIGHT,
+                    Z_UI_TEXT,
+                    &self.sprites,
+                ),
+            );
             self.fps_count = 0;
             self.one_sec_fps = Instant::now();
         }
         //  println!("{}", self.time_delta.elapsed().as_nanos());
+        let mut direction = Vec2::ZERO;
         if self.is_up_pressed {
-            self.camera_up += CAMERA_MOVE_SPEED
-                * (self.camera_up - self.camera_down)
-                * self.time_delta.elapsed().as_nanos() as f32;
-            self.camera_down += CAMERA_MOVE_SPEED
-                * (self.camera_up - self.camera_down)
-                * self.time_delta.elapsed().as_nanos() as f32;
-            self.update_camera();
+            direction.y += 1.0;
         }
         if self.is_down_pressed {
-            self.camera_up -= CAMERA_MOVE_SPEED
-                * (self.camera_up - self.camera_down)
-                * self.time_delta.elapsed().as_nanos() as f32;
-            self.camera_down -= CAMERA_MOVE_SPEED
-                * (self.camera_up - self.camera_down)
-                * self.time_delta.elapsed().as_nanos() as f32;
-            self.update_camera();
+            direction.y -= 1.0;
         }
         if self.is_left_pressed {
-            self.camera_left -= CAMERA_MOVE_SPEED
-                * (self.camera_up - self.camera_down)
-                * self.time_delta.elapsed().as_nanos() as f32;
-            self.camera_right -= CAMERA_MOVE_SPEED
-                * (self.camera_up - self.camera_down)
-                * self.time_delta.elapsed().as_nanos() as f32;
-            self.update_camera();
+            direction.x -= 1.0;
         }
         if self.is_right_pressed {
-            self.camera_left += CAMERA_MOVE_SPEED
-                * (self.camera_up - self.camera_down)
-                * self.time_delta.elapsed().as_nanos() as f32;
-            self.camera_right += CAMERA_MOVE_SPEED
-                * (self.camera_up - self.camera_down)
-                * self.time_delta.elapsed().as_nanos() as f32;
-            self.update_camera();
+            direction.x += 1.0;
+        }
+        if direction != Vec2::ZERO {
+            self.view.pan(
+                direction
+                    * CAMERA_MOVE_SPEED
+                    * self.view.height()
+                    * self.time_delta.elapsed().as_nanos() as f32,
+            );
         }
         if self.is_pgup_pressed {
-            self.camera_zoom(MouseScrollDelta::LineDelta(0.0, 1.0));
+            self.camera_zoom(MouseScrollDelta::LineDelta(0.0, 1.0), Vec2::splat(0.5));
             self.is_pgup_pressed = false;
         }
         if self.is_pgdown_pressed {
-            self.camera_zoom(MouseScrollDelta::LineDelta(0.0, -1.0));
+            self.camera_zoom(MouseScrollDelta::LineDelta(0.0, -1.0), Vec2::splat(0.5));
             self.is_pgdown_pressed = false;
         }
 
+        // results wait for the explosion to play out
+        if self.screens.top() == Screen::Playing
+            && self.game_state.phase().is_over()
+            && !self.animations.is_busy()
+        {
+            if self.puzzle.is_some() {
+                self.record_puzzle();
+                self.screens.push(Screen::PuzzleSolved);
+            } else {
+                self.record_result();
+                self.screens.push(Screen::GameOver);
+            }
+            self.sync_screen();
+        }
+        self.play_replay();
+        // the editor has its toolbar up there instead
+        if self.screens.shows_board() && self.editor.is_none() {
+            let note = match self.puzzle.as_ref() {
+                Some(run) => Some(format!("MISTAKES {}", run.mistakes)),
+                None => self
+                    .history
+                    .charges()
+                    .map(|charges| format!("UNDO {}", charges)),
+            };
+            if let Some(hud_instances) =
+                self.hud
+                    .update(&self.game_state, note, self.size, &self.sprites)
+            {
+                self.set_ui_layer("Hud", hud_instances);
+            }
+        }
+
+        self.check_theme();
+        self.check_palette();
+        self.animate();
+        self.flush_instances();
         self.time_delta = Instant::now();
     }
 
-    fn create_instance(&mut self, key: &str, new_instance: &mut Vec<InstanceRaw>) {
-        if self.instances_hash.contains_key(key) {
-            let current_instances = self.instances_hash.get_mut(key);
-            current_instances
-                .expect("key but no hashes?")
-                .append(new_instance);
-        } else {
-            self.instances_hash
-                .insert(String::from(key), new_instance.to_vec());
+    /// Switches theme when the settings changed it and reloads it when its files change.
+    fn check_theme(&mut self) {
+        let switched = self.game_config.theme != self.theme.manifest;
+        let edited = self
+            .theme_watcher
+            .as_ref()
+            .is_some_and(|watcher| watcher.changed());
+        if switched || edited {
+            let path = self.game_config.theme.clone();
+            if let Err(e) = self.load_theme(&path) {
+                eprintln!("Error loading theme '{}': {:?}", path, e);
+                // stay on the old theme rather than retrying every frame
+                self.game_config.theme = self.theme.manifest.clone();
+                self.refresh_menu();
+            }
         }
-        self.update_instance_buffer();
+    }
+
+    /// Sends the number colors to the GPU when the settings changed them.
+    fn check_palette(&mut self) {
+        let palette =
+            PaletteUniform::new(self.game_config.palette, self.game_config.number_patterns);
+        if palette != self.palette_uniform {
+            self.palette_uniform = palette;
+            self.queue.write_buffer(
+                &self.palette_buffer,
+                0,
+                bytemuck::cast_slice(&[self.palette_uniform]),
+            );
+        }
+    }
+
+    fn load_theme(&mut self, path: &str) -> anyhow::Result<()> {
+        let theme = Theme::load(path)?;
+        self.diffuse_bind_group = create_diffuse_bind_group(
+            &self.device,
+            &self.queue,
+            &self.texture_bind_group_layout,
+            theme.load_atlas()?,
+        )?;
+        self.sprites = theme.sprites().clone();
+        self.theme_watcher = watch_theme(&theme);
+        self.theme = theme;
+        if !self.world_instances.is_empty() {
+            self.sync_board();
+        }
+        self.hud.invalidate();
+        self.refresh_menu();
+        // text is drawn from the atlas too, the glyphs may have moved
+        self.refresh_toolbar();
+        self.playback_status.clear();
+        Ok(())
+    }
+
+    /// Moves animations along by the frame time and redraws whatever they touched.
+    fn animate(&mut self) {
+        let delta = self.time_delta.elapsed().as_secs_f32();
+        for index in self.animations.advance(delta) {
+            self.sync_tile(index);
+        }
+        self.sync_overlays();
+        self.particles.update(delta);
+        self.sync_particles();
+        let moved = self.view.update(delta);
+        if moved || self.camera_shaken || self.animations.is_shaking() {
+            self.update_camera();
+        }
+    }
+
+    fn skip_animations(&mut self) {
+        for index in self.animations.skip() {
+            self.sync_tile(index);
+        }
+        self.sync_overlays();
+    }
+
+    fn sync_overlays(&mut self) {
+        if self.world_instances.is_empty() {
+            return;
+        }
+        let overlays = self.animations.overlays();
+        for slot in 0..MAX_OVERLAYS {
+            match overlays.get(slot) {
+                Some((index, overlay)) => {
+                    let instance =
+                        overlay_instance(&self.game_state.board[*index], *overlay, &self.sprites);
+                    self.world_instances.set("Overlays", slot, instance);
+                }
+                None => self.world_instances.clear_slot("Overlays", slot),
+            }
+        }
+    }
+
+    fn sync_particles(&mut self) {
+        if self.world_instances.is_empty()
+            || (self.particles.is_empty() && self.particles_shown == 0)
+        {
+            return;
+        }
+        let instances = self.particles.instances(&self.sprites);
+        for (slot, instance) in instances.iter().enumerate() {
+            self.world_instances.set("Particles", slot, *instance);
+        }
+        for slot in instances.len()..self.particles_shown {
+            self.world_instances.clear_slot("Particles", slot);
+        }
+        self.particles_shown = instances.len();
     }
 
     fn reset_instances(&mut self) {
-        self.instances_hash = HashMap::new();
-        self.game_state = GameState { board: Vec::new() };
-        self.instances_raw = self.blank_instance.clone();
-        self.update_instance_buffer();
-        self.update_instance_buffer();
+        self.animations.skip();
+        self.particles.clear();
+        self.particles_shown = 0;
+        self.world_instances.clear();
+        self.game_state = GameState::empty();
     }
 
-    fn update_instance_buffer(&mut self) {
-        self.instances_raw = self.blank_instance.clone();
+    /// Throws away the board and its instances and deals a new one.
+    fn restart(&mut self) {
+        // a puzzle starts over rather than dealing a random board
+        if let Some(run) = self.puzzle.as_ref() {
+            let (pack, index) = (run.pack, run.index);
+            if let Err(e) = self.play_puzzle(pack, index) {
+                eprintln!("Error loading puzzle: {:?}", e);
+            }
+            return;
+        }
+        self.restart_with_seed(rand::random());
+    }
 
-        for (_key, value) in self.sort_hash_by_z().iter() {
-            self.instances_raw.extend(value.iter().clone());
+    fn restart_with_seed(&mut self, seed: u64) {
+        self.board_mask = self.config_mask();
+        self.deal_board(seed);
+        self.recording = Some(Replay::new(&self.game_config, seed));
+        self.reset_history(self.game_config.undo);
+        // new boards start framed, the view glides over from wherever it was
+        if let Some((min, max, tile_size)) = self.game_state.bounds() {
+            self.view.set_board(min, max, tile_size);
         }
-        let instance_buffer = self
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Instance Buffer"),
-                contents: bytemuck::cast_slice(&self.instances_raw),
-                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    }
+
+    fn config_mask(&self) -> Option<BoardMask> {
+        self.game_config
+            .shape
+            .as_deref()
+            .and_then(|path| match BoardMask::load(path) {
+                Ok(mask) => Some(mask),
+                Err(e) => {
+                    eprintln!("Error loading board mask '{}': {:?}", path, e);
+                    None
+                }
+            })
+    }
+
+    /// New board with the current config and mask, used for restarts.
+    fn deal_board(&mut self, seed: u64) {
+        self.reset_instances();
+        self.roll_game_state(seed);
+        self.add_board_layers();
+    }
+
+    fn add_board_layers(&mut self) {
+        self.world_instances
+            .add_layer("Tiles", self.game_state.board.len());
+        self.world_instances.add_layer("Overlays", MAX_OVERLAYS);
+        self.world_instances.add_layer("Particles", MAX_PARTICLES);
+        self.sync_board();
+    }
+
+    /// Swaps in a saved game and the settings it was played with.
+    fn load_game(&mut self, slot: &str) -> anyhow::Result<()> {
+        let saved = save::load(slot)?;
+        saved.apply_settings(&mut self.game_config);
+        self.board_mask = None;
+        // the start of the game isn't in the save, so there's nothing to replay
+        self.recording = None;
+        self.reset_history(self.game_config.undo);
+        // a save of a finished game was counted when it ended
+        self.result_recorded = saved.game_state.phase().is_over();
+        self.show_game(saved.game_state);
+        self.screens.reset(Screen::Playing);
+        Ok(())
+    }
+
+    /// Plays a board file as it is, the first click doesn't move its mines.
+    fn import_board(&mut self, path: PathBuf) -> anyhow::Result<()> {
+        let mut game_state = board_format::import(&path)?;
+        game_state.set_question_marks(self.game_config.question_marks);
+        self.board_mask = None;
+        // replays deal their board from a seed, an imported one has none
+        self.recording = None;
+        self.reset_history(self.game_config.undo);
+        // it doesn't belong to any difficulty, so it stays out of the stats
+        self.result_recorded = true;
+        self.show_game(game_state);
+        self.board_source = Some(path);
+        self.screens.reset(Screen::Playing);
+        Ok(())
+    }
+
+    /// Writes the current layout to the boards folder as json.
+    fn export_board(&self) -> anyhow::Result<PathBuf> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let path = PathBuf::from(board_format::BOARD_DIR).join(format!(
+            "{}-{:016x}.{}",
+            now,
+            self.game_state.seed(),
+            board_format::BoardFormat::Json.extension()
+        ));
+        board_format::export(&self.game_state, &path)?;
+        Ok(path)
+    }
+
+    /// Plays a puzzle of a pack, its given tiles already open.
+    fn play_puzzle(&mut self, pack: usize, index: usize) -> anyhow::Result<()> {
+        let path = puzzle::pack_paths()
+            .get(pack)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("no puzzle pack {}", pack))?;
+        let puzzles = PuzzlePack::load(&path)?;
+        let mut game_state = puzzles
+            .puzzles
+            .get(index)
+            .ok_or_else(|| anyhow::anyhow!("no puzzle {} in '{}'", index, path))?
+            .game_state()?;
+        game_state.set_question_marks(self.game_config.question_marks);
+        self.board_mask = None;
+        self.recording = None;
+        // mistakes are the score, they can't be taken back
+        self.reset_history(UndoPolicy::Off);
+        // puzzles keep their own progress rather than stats
+        self.result_recorded = true;
+        self.show_game(game_state);
+        self.puzzle = Some(PuzzleRun::new(pack, index, &puzzles));
+        self.screens.reset(Screen::Playing);
+        Ok(())
+    }
+
+    /// Adds a solved puzzle to the progress file.
+    fn record_puzzle(&mut self) {
+        let Some(run) = self.puzzle.as_mut() else {
+            return;
+        };
+        let result = puzzle::pack_paths()
+            .get(run.pack)
+            .ok_or_else(|| anyhow::anyhow!("no puzzle pack {}", run.pack))
+            .and_then(|path| PuzzlePack::load(path))
+            .and_then(|pack| {
+                let puzzle = pack
+                    .puzzles
+                    .iter()
+                    .find(|puzzle| puzzle.title == run.title)
+                    .ok_or_else(|| anyhow::anyhow!("no puzzle '{}'", run.title))?;
+                ProgressDb::open(PROGRESS_FILE)?.record(&pack, puzzle, run.mistakes)
             });
-        self.instance_buffer = instance_buffer;
+        match result {
+            Ok(progress) => run.progress = Some(progress),
+            Err(e) => eprintln!("Error recording puzzle progress: {:?}", e),
+        }
     }
 
-    fn sort_hash_by_z(&self) -> Vec<(String, Vec<InstanceRaw>)> {
-        let mut hash_as_vec: Vec<(String, Vec<InstanceRaw>)> =
-            self.instances_hash.clone().into_iter().collect();
-        hash_as_vec.sort_by(|(_, vec_a), (_, vec_b)| {
-            let z_a = vec_a.first().map(|instance| instance.z_index);
-            let z_b = vec_b.first().map(|instance| instance.z_index);
-
-            match (z_a, z_b) {
-                (Some(za), Some(zb)) => za.partial_cmp(&zb).unwrap_or(std::cmp::Ordering::Equal),
-                (Some(_), None) => std::cmp::Ordering::Less,
-                (None, Some(_)) => std::cmp::Ordering::Greater,
-                (None, None) => std::cmp::Ordering::Equal,
-            }
-        });
-        hash_as_vec
+    /// Starts the editor on an empty board of the configured size and shape.
+    fn open_editor(&mut self) {
+        let mask = self.config_mask();
+        self.editor = Some(Editor::new(
+            self.game_config.width,
+            self.game_config.length,
+            mask.as_ref(),
+        ));
+        self.board_mask = None;
+        self.recording = None;
+        self.reset_history(UndoPolicy::Off);
+        self.screens.reset(Screen::Editor);
+        self.show_editor_board(true);
     }
 
-    fn update_camera(&mut self) {
-        let new_camera = OrthographicCamera::new(
-            self.camera_left,
-            self.camera_right,
-            self.camera_down,
-            self.camera_up,
-            -1.0,
-            1.0,
-        );
-        self.camera = new_camera;
-        self.camera_uniform.update_view_proj(&self.camera);
-        self.queue.write_buffer(
-            &self.camera_buffer,
-            0,
-            bytemuck::cast_slice(&[self.camera_uniform]),
-        );
+    /// Redraws the board being edited, `reframe` when its size changed.
+    fn show_editor_board(&mut self, reframe: bool) {
+        let Some(editor) = self.editor.as_ref() else {
+            return;
+        };
+        let preview = editor.preview();
+        if reframe {
+            self.show_game(preview);
+        } else {
+            // same tiles, only the numbers around the edit moved
+            self.game_state = preview;
+            self.sync_board();
+        }
+        self.refresh_toolbar();
+    }
+
+    fn refresh_toolbar(&mut self) {
+        let toolbar = match self.editor.as_mut() {
+            Some(editor) => editor.toolbar(self.size, &self.sprites),
+            None => Vec::new(),
+        };
+        self.set_ui_layer("Toolbar", toolbar);
+    }
+
+    /// A click on the editor screen, on the toolbar or a tile.
+    fn editor_click(&mut self, screen_cursor: Vec2, erase: bool) {
+        let Some(editor) = self.editor.as_ref() else {
+            return;
+        };
+        if let Some(action) = editor.hit_test(screen_cursor) {
+            self.editor_action(action);
+            return;
+        }
+        if editor.covers(screen_cursor) {
+            return;
+        }
+        let cursor = self.cursor_world(screen_cursor);
+        let Some(index) = self.game_state.tile_at(cursor) else {
+            return;
+        };
+        if self
+            .editor
+            .as_mut()
+            .is_some_and(|editor| editor.paint(index, erase))
+        {
+            self.show_editor_board(false);
+        }
     }
 
-    fn camera_zoom(&mut self, delta: MouseScrollDelta) {
-        if let MouseScrollDelta::LineDelta(_x, y) = delta {
-            if y > 0.0 {
-                let x1 = (self.camera_right + self.camera_left) / 2.0;
-                let y1 = (self.camera_up + self.camera_down) / 2.0;
-
-                self.camera_left *= 1.1;
-                self.camera_right *= 1.1;
-                self.camera_up *= 1.1;
-                self.camera_down *= 1.1;
-                let x2 = (self.camera_right + self.camera_left) / 2.0;
-                let y2 = (self.camera_up + self.camera_down) / 2.0;
-
-                self.camera_left -= x2 - x1;
-                self.camera_right -= x2 - x1;
-                self.camera_up -= y2 - y1;
-                self.camera_down -= y2 - y1;
+    fn editor_action(&mut self, action: EditorAction) {
+        let Some(editor) = self.editor.as_mut() else {
+            return;
+        };
+        match action {
+            EditorAction::Tool(tool) => editor.set_tool(tool),
+            EditorAction::Resize(columns, rows) => {
+                if editor.resize(columns, rows) {
+                    self.show_editor_board(true);
+                }
+            }
+            EditorAction::Clear => {
+                editor.clear();
+                self.show_editor_board(false);
+            }
+            EditorAction::Check => {
+                if let Err(e) = editor.check() {
+                    editor.set_message(e.to_string());
+                }
             }
-            if y < 0.0 {
-                let x1 = (self.camera_right + self.camera_left) / 2.0;
-                let y1 = (self.camera_up + self.camera_down) / 2.0;
-
-                self.camera_left /= 1.1;
-                self.camera_right /= 1.1;
-                self.camera_up /= 1.1;
-                self.camera_down /= 1.1;
-                let x2 = (self.camera_right + self.camera_left) / 2.0;
-                let y2 = (self.camera_up + self.camera_down) / 2.0;
-
-                self.camera_left -= x2 - x1;
-                self.camera_right -= x2 - x1;
-                self.camera_up -= y2 - y1;
-                self.camera_down -= y2 - y1;
+            EditorAction::Save => match editor.save() {
+                Ok(path) => println!("Saved board to {}", path.display()),
+                Err(e) => {
+                    eprintln!("Error saving board: {:?}", e);
+                    editor.set_message(e.to_string());
+                }
+            },
+            EditorAction::Play => match editor.save() {
+                Ok(path) => {
+                    self.editor = None;
+                    if let Err(e) = self.import_board(path) {
+                        eprintln!("Error importing board: {:?}", e);
+                    }
+                    self.sync_screen();
+                }
+                Err(e) => {
+                    eprintln!("Error saving board: {:?}", e);
+                    editor.set_message(e.to_string());
+                }
+            },
+            EditorAction::Exit => {
+                self.editor = None;
+                self.screens.reset(Screen::MainMenu);
+                self.reset_instances();
+                self.sync_screen();
             }
+        }
+        self.refresh_toolbar();
+    }
 
-            self.update_camera();
+    /// Puts a game on the board and frames it.
+    fn show_game(&mut self, game_state: GameState) {
+        self.reset_instances();
+        self.game_state = game_state;
+        self.add_board_layers();
+        if let Some((min, max, tile_size)) = self.game_state.bounds() {
+            self.view.set_board(min, max, tile_size);
         }
     }
 
-    /// GAMEPLAY STUFF THAT REQUIRES State
-    fn click_tile(&mut self, index: usize) {
-        let clicked_tile: (Vec2, f32, Vec2);
-        let mut sprite_needed: Option<&str> = None;
-        println!("{}", index);
-        let tiles: &mut Tiles = self.game_state.board.get_mut(index).unwrap();
-        let mine_index = tiles.get_mine_index();
-        tiles.clicked = true;
-        if tiles.has_mine() {
-            clicked_tile = (
-                Vec2::new(
-                    tiles.position.x + (0.505 * tiles.size),
-                    tiles.position.y + (0.505 * tiles.size),
-                ),
-                tiles.size * 0.95,
-                tiles.board_position,
-            );
-            sprite_needed = Some("Mines");
-            tiles.mine = Some(Mines::Default(CommonMineState {
-                active: true,
-                mine_index: mine_index.unwrap(),
-            }));
+    fn reset_history(&mut self, policy: UndoPolicy) {
+        self.history = History::new(policy);
+        self.undone_steps.clear();
+        self.board_source = None;
+        self.puzzle = None;
+        self.result_recorded = false;
+        self.personal_best = false;
+        self.results = None;
+    }
+
+    /// Works out the results of the game that just ended and adds them to the
+    /// stats, once per game.
+    fn record_result(&mut self) {
+        let results = GameStats::from_game(&self.game_state);
+        let record = GameRecord::new(&results, &self.game_config, self.history.used());
+        self.results = Some(results);
+        if self.result_recorded {
+            return;
+        }
+        self.result_recorded = true;
+        match StatsDb::open(STATS_FILE).and_then(|mut db| db.record(record)) {
+            Ok(personal_best) => self.personal_best = personal_best,
+            Err(e) => eprintln!("Error recording stats: {:?}", e),
+        }
+    }
+
+    /// Runs a player action, from a click or a replay, and records it.
+    fn perform(&mut self, action: Action) {
+        let before = self.history.before(&self.game_state);
+        self.game_state.count_click();
+        match action {
+            Action::Reveal(index) => {
+                // an imported board is played as it was drawn
+                if self.board_source.is_none() {
+                    self.prepare_first_click(index);
+                }
+                self.click_tile(index);
+            }
+            Action::Chord(index) => {
+                for neighbor in self.game_state.chord_targets(index) {
+                    self.click_tile(neighbor);
+                }
+            }
+            Action::Flag(index) => {
+                if self.game_state.toggle_flag(index) {
+                    self.animations.start_flag_drop(index);
+                }
+                self.sync_tile(index);
+            }
+        }
+        if let Some(before) = before {
+            self.history.push(before, &self.game_state);
+            self.undone_steps.clear();
+        }
+        if let Some(recording) = self.recording.as_mut() {
+            recording.record(&self.game_state, action);
+        }
+    }
+
+    /// Takes back the last action, or does an undone one again, and redraws what it changed.
+    fn step_history(&mut self, redo: bool) {
+        let phase = self.game_state.phase();
+        let changed = if redo {
+            self.history.redo(&mut self.game_state)
+        } else {
+            self.history.undo(&mut self.game_state)
+        };
+        let Some(changed) = changed else {
+            return;
+        };
+        // the replay follows along, so it shows the game as it was finally played
+        if let Some(recording) = self.recording.as_mut() {
+            if redo {
+                recording.steps.extend(self.undone_steps.pop());
+            } else {
+                self.undone_steps.extend(recording.steps.pop());
+            }
+        }
+        // a half played flip would draw the tile as it was before
+        for index in self.animations.skip() {
+            self.sync_tile(index);
+        }
+        if self.game_state.phase() != phase {
+            // mines and wrong flags show or hide with the loss
+            self.sync_board();
         } else {
-            clicked_tile = (
-                Vec2::new(
-                    tiles.position.x + (0.505 * tiles.size),
-                    tiles.position.y + (0.505 * tiles.size),
+            for index in changed {
+                self.sync_tile(index);
+            }
+        }
+    }
+
+    fn watch_replay(&mut self, replay: Replay) -> anyhow::Result<()> {
+        // play it through once first, so a broken replay fails here and not half way
+        replay.simulate(replay.duration())?;
+        let mut playback = Playback::new(replay);
+        self.show_game(playback.rewind()?);
+        self.playback = Some(playback);
+        // perform records whatever it does, the replay's actions aren't a new game
+        self.recording = None;
+        self.reset_history(UndoPolicy::Off);
+        self.screens.reset(Screen::Replay);
+        Ok(())
+    }
+
+    /// Moves the playback along and runs whatever actions came due.
+    fn play_replay(&mut self) {
+        let Some(playback) = self.playback.as_mut() else {
+            return;
+        };
+        let delta = self.time_delta.elapsed().as_secs_f32();
+        for action in playback.advance(delta) {
+            self.perform(action);
+        }
+        let Some(playback) = self.playback.as_ref() else {
+            return;
+        };
+        self.game_state.freeze_timer(playback.time());
+        let status = playback.status();
+        if status != self.playback_status {
+            self.set_ui_layer(
+                "Replay",
+                text_instances(
+                    &format!(
+                        "{}\nSPACE PAUSE  LEFT RIGHT SEEK  UP DOWN SPEED  HOME RESTART  ESC EXIT",
+                        status
+                    ),
+                    Vec2::new(8.0, 28.0),
+                    REPLAY_TEXT_HEIGHT,
+                    Z_UI_TEXT,
+                    &self.sprites,
                 ),
-                tiles.size * 0.95,
-                tiles.board_position,
             );
+            self.playback_status = status;
         }
+    }
 
-        if sprite_needed.is_none() {
-            let num = &self.find_nearby_mines(clicked_tile.2).to_string();
-            self.create_instance(
-                "Numbers",
-                &mut vec![Instance::to_raw(
-                    clicked_tile.0,
-                    0.0,
-                    Vec2::new(clicked_tile.1, clicked_tile.1),
-                    Z_MINE,
-                    *self.sprites.get(num).expect("smth wrong with get sprite"),
-                    0,
-                )],
-            );
+    fn replay_key(&mut self, key: KeyCode) {
+        let Some(playback) = self.playback.as_mut() else {
+            return;
+        };
+        let rebuilt = match key {
+            KeyCode::Space => playback.toggle_pause().transpose(),
+            KeyCode::ArrowUp => {
+                playback.change_speed(1);
+                None
+            }
+            KeyCode::ArrowDown => {
+                playback.change_speed(-1);
+                None
+            }
+            KeyCode::ArrowLeft => Some(playback.seek(-1)),
+            KeyCode::ArrowRight => Some(playback.seek(1)),
+            KeyCode::Home => Some(playback.rewind()),
+            KeyCode::Digit0 => Some(playback.seek_to(0.0)),
+            KeyCode::Digit1 => Some(playback.seek_to(0.1)),
+            KeyCode::Digit2 => Some(playback.seek_to(0.2)),
+            KeyCode::Digit3 => Some(playback.seek_to(0.3)),
+            KeyCode::Digit4 => Some(playback.seek_to(0.4)),
+            KeyCode::Digit5 => Some(playback.seek_to(0.5)),
+            KeyCode::Digit6 => Some(playback.seek_to(0.6)),
+            KeyCode::Digit7 => Some(playback.seek_to(0.7)),
+            KeyCode::Digit8 => Some(playback.seek_to(0.8)),
+            KeyCode::Digit9 => Some(playback.seek_to(0.9)),
+            KeyCode::Escape => {
+                self.stop_replay();
+                return;
+            }
+            _ => None,
+        };
+        match rebuilt {
+            // seeking rebuilds the board without touching the camera
+            Some(Ok(game_state)) => {
+                self.reset_instances();
+                self.game_state = game_state;
+                self.add_board_layers();
+            }
+            Some(Err(e)) => eprintln!("Error seeking replay: {:?}", e),
+            None => {}
+        }
+    }
+
+    fn stop_replay(&mut self) {
+        self.playback = None;
+        self.playback_status.clear();
+        self.set_ui_layer("Replay", Vec::new());
+        self.screens.reset(Screen::MainMenu);
+        self.reset_instances();
+        self.sync_screen();
+    }
+
+    /// Keeps an unfinished game for CONTINUE on the next launch, a finished one drops it.
+    fn autosave(&self) {
+        // a puzzle is picked again from its pack, a save would play it as a plain game
+        if !self.screens.contains(Screen::Playing)
+            || self.game_state.board().is_empty()
+            || self.puzzle.is_some()
+        {
+            return;
+        }
+        let result = if self.game_state.phase().is_over() {
+            save::delete(AUTOSAVE)
         } else {
-            self.create_instance(
-                "Mines",
-                &mut vec![Instance::to_raw(
-                    clicked_tile.0,
-                    0.0,
-                    Vec2::new(clicked_tile.1, clicked_tile.1),
-                    Z_MINE,
-                    *self
-                        .sprites
-                        .get(sprite_needed.expect("smth wrong with num"))
-                        .expect("smth wrong with get sprite"),
-                    0,
-                )],
-            );
+            save::save(AUTOSAVE, &self.game_state, &self.game_config)
+        };
+        if let Err(e) = result {
+            eprintln!("Error autosaving: {:?}", e);
         }
     }
 
-    fn propogate(&mut self, board_position: Vec2) {
-        for index in nearby_possible_positions(board_position).iter() {
-            if !self
-                .game_state
-                .board
-                .get(*index)
-                .expect("propgate mine index not working")
-                .clicked
-            {
-                println!("board_index: {}, board_position: {}", index, board_position);
-                self.click_tile(*index);
+    // game state only, the tile layout stays the same for a seed change
+    fn roll_game_state(&mut self, seed: u64) {
+        self.game_state = create_minefield(&self.game_config, self.board_mask.as_ref(), seed);
+        self.game_state
+            .set_question_marks(self.game_config.question_marks);
+    }
+
+    /// Points a tile's instance at whatever its state looks like now.
+    fn sync_tile(&mut self, index: usize) {
+        let tile = &self.game_state.board[index];
+        let visual = match self.editor.as_ref() {
+            Some(editor) => editor.visual(index, &self.game_state),
+            None => self.game_state.tile_visual(index),
+        };
+        let instance = match self.animations.frame(index) {
+            Some(frame) => scaled_tile_instance(
+                tile,
+                frame.visual.unwrap_or(visual),
+                frame.scale,
+                &self.sprites,
+            ),
+            None => tile_instance(tile, visual, &self.sprites),
+        };
+        self.world_instances.set("Tiles", index, instance);
+    }
+
+    fn sync_board(&mut self) {
+        for index in 0..self.game_state.board.len() {
+            self.sync_tile(index);
+        }
+    }
+
+    /// Moves mines off the first click as the rules say, see `Rules`. A replay
+    /// moves them as the rules it was played with did.
+    fn prepare_first_click(&mut self, tile_index: usize) {
+        let rules = match self.playback.as_ref() {
+            Some(playback) => playback.replay.rules,
+            None => self.game_config.rules,
+        };
+        if prepare_first_click(&mut self.game_state, rules, tile_index) {
+            self.sync_board();
+        }
+    }
+
+    /// Rebuilds the menu for whichever screen is on top and pauses the game under menus.
+    fn sync_screen(&mut self) {
+        let top = self.screens.top();
+        self.menu = Menu::for_screen(
+            top,
+            &MenuContext {
+                game_state: &self.game_state,
+                results: self.results.as_ref(),
+                has_replay: self.recording.is_some(),
+                can_undo: self.history.can_undo(),
+                personal_best: self.personal_best,
+                puzzle: self.puzzle.as_ref(),
+            },
+        );
+        self.game_state.set_paused(!top.is_playing());
+        // a replay keeps the question mark setting it was recorded with
+        if self.playback.is_none() {
+            self.game_state
+                .set_question_marks(self.game_config.question_marks);
+        }
+        for index in self.animations.set_enabled(self.game_config.animations) {
+            self.sync_tile(index);
+        }
+        if !top.is_playing() {
+            // keys released while a menu is open never reach the camera
+            self.is_up_pressed = false;
+            self.is_down_pressed = false;
+            self.is_left_pressed = false;
+            self.is_right_pressed = false;
+        }
+        if !self.screens.shows_board() {
+            self.hud.invalidate();
+            self.set_ui_layer("Hud", Vec::new());
+        }
+        self.refresh_menu();
+    }
+
+    fn refresh_menu(&mut self) {
+        let menu_instances = match self.menu.as_mut() {
+            Some(menu) => menu.instances(&self.game_config, self.size, &self.sprites),
+            None => Vec::new(),
+        };
+        self.set_ui_layer("Menu", menu_instances);
+    }
+
+    /// Returns true when the game should quit.
+    fn apply_menu_action(&mut self, action: MenuAction) -> bool {
+        match action {
+            MenuAction::StartGame | MenuAction::Restart => {
+                if action == MenuAction::StartGame {
+                    self.puzzle = None;
+                }
+                self.screens.reset(Screen::Playing);
+                self.restart();
+            }
+            MenuAction::RetrySeed => match self.board_source.clone() {
+                Some(path) => {
+                    if let Err(e) = self.import_board(path) {
+                        eprintln!("Error importing board: {:?}", e);
+                    }
+                }
+                None => {
+                    let seed = self.game_state.seed();
+                    self.screens.reset(Screen::Playing);
+                    self.restart_with_seed(seed);
+                }
+            },
+            MenuAction::Resume | MenuAction::Back => {
+                self.screens.pop();
+            }
+            MenuAction::OpenSettings => self.screens.push(Screen::Settings),
+            MenuAction::OpenSave => self.screens.push(Screen::SaveGame),
+            MenuAction::OpenLoad => self.screens.push(Screen::LoadGame),
+            MenuAction::SaveSlot(index) => {
+                match save::save(SLOTS[index], &self.game_state, &self.game_config) {
+                    Ok(()) => {
+                        self.screens.pop();
+                    }
+                    Err(e) => eprintln!("Error saving to '{}': {:?}", SLOTS[index], e),
+                }
+            }
+            MenuAction::LoadSlot(index) => {
+                if let Err(e) = self.load_game(SLOTS[index]) {
+                    eprintln!("Error loading '{}': {:?}", SLOTS[index], e);
+                }
+            }
+            MenuAction::ViewReplay => {
+                if let Some(replay) = self.recording.clone() {
+                    if let Err(e) = self.watch_replay(replay) {
+                        eprintln!("Error playing replay: {:?}", e);
+                    }
+                }
+            }
+            MenuAction::SaveReplay => {
+                if let Some(replay) = self.recording.as_ref() {
+                    match replay.save() {
+                        Ok(path) => println!("Saved replay to {}", path.display()),
+                        Err(e) => eprintln!("Error saving replay: {:?}", e),
+                    }
+                }
+            }
+            MenuAction::OpenReplays => self.screens.push(Screen::Replays),
+            MenuAction::OpenImport => self.screens.push(Screen::ImportBoard),
+            MenuAction::OpenEditor => self.open_editor(),
+            MenuAction::OpenPuzzlePacks => self.screens.push(Screen::PuzzlePacks),
+            MenuAction::OpenPuzzles(pack, first) => {
+                match self.screens.top() {
+                    // NEXT PAGE swaps the page rather than stacking another one
+                    Screen::Puzzles(..) => {
+                        self.screens.pop();
+                    }
+                    // from a solved puzzle, BACK goes to the packs
+                    Screen::PuzzleSolved => {
+                        self.screens.reset(Screen::MainMenu);
+                        self.screens.push(Screen::PuzzlePacks);
+                        self.reset_instances();
+                    }
+                    _ => {}
+                }
+                self.screens.push(Screen::Puzzles(pack, first));
+            }
+            MenuAction::PlayPuzzle(pack, index) => {
+                if let Err(e) = self.play_puzzle(pack, index) {
+                    eprintln!("Error loading puzzle: {:?}", e);
+                }
+            }
+            MenuAction::ImportBoard(index) => {
+                let result = board_format::saved_boards()
+                    .get(index)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("no board {}", index))
+                    .and_then(|path| self.import_board(path));
+                if let Err(e) = result {
+                    eprintln!("Error importing board: {:?}", e);
+                }
+            }
+            MenuAction::ExportBoard => match self.export_board() {
+                Ok(path) => println!("Exported board to {}", path.display()),
+                Err(e) => eprintln!("Error exporting board: {:?}", e),
+            },
+            MenuAction::ShowStats(category) => {
+                // NEXT swaps the stats screen rather than stacking another one
+                if matches!(self.screens.top(), Screen::Stats(_)) {
+                    self.screens.pop();
+                }
+                self.screens.push(Screen::Stats(category));
+            }
+            MenuAction::Undo => {
+                self.step_history(false);
+                self.screens.pop();
+            }
+            MenuAction::WatchReplay(index) => {
+                let result = saved_replays()
+                    .get(index)
+                    .ok_or_else(|| anyhow::anyhow!("no replay {}", index))
+                    .and_then(|path| Replay::load(path))
+                    .and_then(|replay| self.watch_replay(replay));
+                if let Err(e) = result {
+                    eprintln!("Error playing replay: {:?}", e);
+                }
+            }
+            MenuAction::Continue => match self.load_game(AUTOSAVE) {
+                // it's written again on exit, a stale one would come back after this game ends
+                Ok(()) => {
+                    if let Err(e) = save::delete(AUTOSAVE) {
+                        eprintln!("Error removing the autosave: {:?}", e);
+                    }
+                }
+                Err(e) => eprintln!("Error loading the autosave: {:?}", e),
+            },
+            MenuAction::MainMenu => {
+                self.screens.reset(Screen::MainMenu);
+                self.reset_instances();
+            }
+            MenuAction::Quit => {
+                self.autosave();
+                return true;
+            }
+        }
+        self.sync_screen();
+        false
+    }
+
+    /// Sends the world instances that changed this frame to the GPU.
+    fn flush_instances(&mut self) {
+        match self.world_instances.take_changes() {
+            SlotChanges::None => {}
+            SlotChanges::Range(range) => {
+                let offset = (range.start * std::mem::size_of::<InstanceRaw>()) as u64;
+                self.queue.write_buffer(
+                    &self.instance_buffer,
+                    offset,
+                    bytemuck::cast_slice(&self.world_instances.as_slice()[range]),
+                );
             }
+            SlotChanges::Full => {
+                // an empty buffer can't be bound, keep a blank instance around
+                let contents = if self.world_instances.is_empty() {
+                    self.blank_instance.as_slice()
+                } else {
+                    self.world_instances.as_slice()
+                };
+                self.instance_buffer =
+                    self.device
+                        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                            label: Some("Instance Buffer"),
+                            contents: bytemuck::cast_slice(contents),
+                            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                        });
+            }
+        }
+    }
+
+    /// Replaces a whole UI layer, UI is rebuilt every time it changes rather than appended to.
+    fn set_ui_layer(&mut self, key: &str, instances: Vec<InstanceRaw>) {
+        self.ui_instances_hash.insert(String::from(key), instances);
+        self.update_ui_instance_buffer();
+    }
+
+    fn update_ui_instance_buffer(&mut self) {
+        self.ui_instances_raw = self.blank_instance.clone();
+
+        for (_key, value) in sort_hash_by_z(&self.ui_instances_hash).iter() {
+            self.ui_instances_raw.extend(value.iter().clone());
         }
+        let ui_instance_buffer =
+            self.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("UI Instance Buffer"),
+                    contents: bytemuck::cast_slice(&self.ui_instances_raw),
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                });
+        self.ui_instance_buffer = ui_instance_buffer;
     }
 
-    fn find_nearby_mines(&mut self, board_position: Vec2) -> u8 {
-        let mut mine_amt: u8 = 0;
-        for index in nearby_possible_positions(board_position).iter() {
-            if let Some(potential_mine) = self.game_state.board.get(*index) {
-                if potential_mine.has_mine() {
-                    mine_amt += 1;
+    fn update_camera(&mut self) {
+        let shake = self.animations.shake_offset() * self.view.height();
+        self.camera_shaken = shake != Vec2::ZERO;
+        self.camera = self.view.projection(shake);
+        self.camera_uniform.update_view_proj(&self.camera);
+        self.queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[self.camera_uniform]),
+        );
+    }
+
+    /// Zooms out for wheel up and in for wheel down, keeping the point under `anchor`
+    /// (fractions of the window from the bottom left) in place.
+    fn camera_zoom(&mut self, delta: MouseScrollDelta, anchor: Vec2) {
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_x, y) => y,
+            MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+        };
+        if lines != 0.0 {
+            self.view.zoom_at(anchor, ZOOM_STEP.powf(lines));
+        }
+    }
+
+    /// World position under the cursor, `screen_cursor` is from the bottom left like the UI.
+    /// Worked out at click time so the camera gliding after the last mouse move is accounted for.
+    fn cursor_world(&self, screen_cursor: Vec2) -> Vec2 {
+        let viewport = Vec2::new(self.size.width as f32, self.size.height as f32);
+        self.view.screen_to_world(
+            Vec2::new(screen_cursor.x, viewport.y - screen_cursor.y),
+            viewport,
+        )
+    }
+
+    /// Middle mouse drag, the board follows the cursor.
+    fn drag_camera(&mut self, screen_delta: Vec2) {
+        let world_per_pixel = self.view.height() / self.size.height as f32;
+        self.view.drag(-screen_delta * world_per_pixel);
+        self.update_camera();
+    }
+
+    /// GAMEPLAY STUFF THAT REQUIRES State
+    fn click_tile(&mut self, index: usize) {
+        println!("{}", index);
+        // in a puzzle a mine costs a mistake rather than the game, and gets its flag
+        if let Some(run) = self.puzzle.as_mut() {
+            if self.game_state.board[index].has_mine() {
+                run.mistakes += 1;
+                if !self.game_state.board[index].flagged {
+                    self.game_state.place_flag(index);
+                    self.animations.start_flag_drop(index);
                 }
+                self.sync_tile(index);
+                return;
             }
         }
-        if mine_amt == 0 {
-            self.propogate(board_position);
+        let was_over = self.game_state.phase().is_over();
+        let revealed = self.game_state.open(index);
+        let origin = self.game_state.board[index].board_position;
+        if !was_over && self.game_state.phase() == GamePhase::Lost {
+            // losing shows every mine and wrong flag, flipping out from the explosion
+            let shown: Vec<(usize, Vec2)> = (0..self.game_state.board.len())
+                .filter(|tile| {
+                    *tile != index
+                        && matches!(
+                            self.game_state.tile_visual(*tile),
+                            TileVisual::Mine | TileVisual::WrongFlag
+                        )
+                })
+                .map(|tile| (tile, self.game_state.board[tile].board_position))
+                .collect();
+            self.animations.start_wave(origin, &shown);
+            self.animations.start_explosion(index);
+            if self.game_config.animations {
+                let tile = &self.game_state.board[index];
+                self.particles
+                    .explosion(tile.position + Vec2::splat(0.5 * tile.size), tile.size);
+            }
+            self.sync_board();
+        } else {
+            let flipped: Vec<(usize, Vec2)> = revealed
+                .iter()
+                .map(|tile| (*tile, self.game_state.board[*tile].board_position))
+                .collect();
+            self.animations.start_wave(origin, &flipped);
+            for index in revealed {
+                self.sync_tile(index);
+            }
+            if !was_over && self.game_state.phase() == GamePhase::Won && self.game_config.animations
+            {
+                self.particles.confetti(self.view.min(), self.view.max());
+            }
         }
-        mine_amt
     }
 }
 #[repr(C)]
@@ -836,426 +1772,358 @@ impl Vertex {
     }
 }
 
-pub fn tex_from_coords(coords: [u16; 4]) -> Vec4 {
+/// Texture coordinates of a rect of cells in an atlas cut into a `grid` x `grid` grid.
+pub fn tex_from_grid(coords: [u16; 4], grid: u32) -> Vec4 {
     Vec4::new(
-        coords[0] as f32 / 32.0,
-        coords[1] as f32 / 32.0,
-        coords[2] as f32 / 32.0,
-        coords[3] as f32 / 32.0,
+        coords[0] as f32 / grid as f32,
+        coords[1] as f32 / grid as f32,
+        coords[2] as f32 / grid as f32,
+        coords[3] as f32 / grid as f32,
     )
 }
 
-fn nearby_possible_positions(board_position: Vec2) -> Vec<usize> {
-    const VAL: f32 = BOARD_WIDTH as f32 - 1.0;
-    const VALY: f32 = BOARD_LENGTH as f32 - 1.0;
-    match (board_position.x, board_position.y) {
-        (0.0, VALY) => {
-            vec![
-                //  y-1
-                (board_position.x + (board_position.y - 1.0) * BOARD_WIDTH as f32) as usize,
-                (board_position.x + 1.0 + (board_position.y - 1.0) * BOARD_WIDTH as f32) as usize,
-                // y
-                (board_position.x + 1.0 + (board_position.y) * BOARD_WIDTH as f32) as usize,
-            ]
-        }
-        (VAL, VALY) => {
-            vec![
-                //  y-1
-                (board_position.x - 1.0 + (board_position.y - 1.0) * BOARD_WIDTH as f32) as usize,
-                (board_position.x + (board_position.y - 1.0) * BOARD_WIDTH as f32) as usize,
-                // y
-                (board_position.x - 1.0 + (board_position.y) * (BOARD_WIDTH as f32)) as usize,
-            ]
-        }
-        (_, VALY) => {
-            vec![
-                //  y-1
-                (board_position.x - 1.0 + (board_position.y - 1.0) * BOARD_WIDTH as f32) as usize,
-                (board_position.x + (board_position.y - 1.0) * BOARD_WIDTH as f32) as usize,
-                (board_position.x + 1.0 + (board_position.y - 1.0) * BOARD_WIDTH as f32) as usize,
-                // y
-                (board_position.x - 1.0 + (board_position.y) * (BOARD_WIDTH as f32)) as usize,
-                (board_position.x + 1.0 + (board_position.y) * BOARD_WIDTH as f32) as usize,
-                // y+1
-            ]
-        }
-        (0.0, 0.0) => {
-            vec![
-                // y
-                (board_position.x + 1.0 + (board_position.y) * BOARD_WIDTH as f32) as usize,
-                // y+1
-                (board_position.x + (board_position.y + 1.0) * (BOARD_WIDTH as f32)) as usize,
-                (board_position.x + 1.0 + (board_position.y + 1.0) * BOARD_WIDTH as f32) as usize,
-            ]
-        }
-        (VAL, 0.0) => {
-            vec![
-                // y
-                (board_position.x - 1.0 + (board_position.y) * (BOARD_WIDTH as f32)) as usize,
-                // y+1
-                (board_position.x - 1.0 + (board_position.y + 1.0) * (BOARD_WIDTH as f32)) as usize,
-                (board_position.x + (board_position.y + 1.0) * (BOARD_WIDTH as f32)) as usize,
-            ]
-        }
-        (_, 0.0) => {
-            vec![
-                // y
-                (board_position.x - 1.0 + (board_position.y) * (BOARD_WIDTH as f32)) as usize,
-                (board_position.x + 1.0 + (board_position.y) * BOARD_WIDTH as f32) as usize,
-                // y+1
-                (board_position.x - 1.0 + (board_position.y + 1.0) * (BOARD_WIDTH as f32)) as usize,
-                (board_position.x + (board_position.y + 1.0) * (BOARD_WIDTH as f32)) as usize,
-                (board_position.x + 1.0 + (board_position.y + 1.0) * BOARD_WIDTH as f32) as usize,
-            ]
-        }
-        (0.0, _) => {
-            vec![
-                //  y-1
-                (board_position.x + (board_position.y - 1.0) * BOARD_WIDTH as f32) as usize,
-                (board_position.x + 1.0 + (board_position.y - 1.0) * BOARD_WIDTH as f32) as usize,
-                // y
-                (board_position.x + 1.0 + (board_position.y) * BOARD_WIDTH as f32) as usize,
-                // y+1
-                (board_position.x + (board_position.y + 1.0) * (BOARD_WIDTH as f32)) as usize,
-                (board_position.x + 1.0 + (board_position.y + 1.0) * BOARD_WIDTH as f32) as usize,
-            ]
-        }
-        (VAL, _) => {
-            vec![
-                //  y-1
-                (board_position.x - 1.0 + (board_position.y - 1.0) * BOARD_WIDTH as f32) as usize,
-                (board_position.x + (board_position.y - 1.0) * BOARD_WIDTH as f32) as usize,
-                // y
-                (board_position.x - 1.0 + (board_position.y) * (BOARD_WIDTH as f32)) as usize,
-                // y+1
-                (board_position.x - 1.0 + (board_position.y + 1.0) * (BOARD_WIDTH as f32)) as usize,
-                (board_position.x + (board_position.y + 1.0) * (BOARD_WIDTH as f32)) as usize,
-            ]
-        }
-        (_, _) => {
-            vec![
-                //  y-1
-                (board_position.x - 1.0 + (board_position.y - 1.0) * BOARD_WIDTH as f32) as usize,
-                (board_position.x + (board_position.y - 1.0) * BOARD_WIDTH as f32) as usize,
-                (board_position.x + 1.0 + (board_position.y - 1.0) * BOARD_WIDTH as f32) as usize,
-                // y
-                (board_position.x - 1.0 + (board_position.y) * (BOARD_WIDTH as f32)) as usize,
-                (board_position.x + 1.0 + (board_position.y) * BOARD_WIDTH as f32) as usize,
-                // y+1
-                (board_position.x - 1.0 + (board_position.y + 1.0) * (BOARD_WIDTH as f32)) as usize,
-                (board_position.x + (board_position.y + 1.0) * (BOARD_WIDTH as f32)) as usize,
-                (board_position.x + 1.0 + (board_position.y + 1.0) * BOARD_WIDTH as f32) as usize,
-            ]
+fn create_diffuse_bind_group(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    atlas: image::RgbaImage,
+) -> anyhow::Result<wgpu::BindGroup> {
+    let texture = Texture::from_image(device, queue, &[image::DynamicImage::ImageRgba8(atlas)])?;
+    Ok(device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureViewArray(&[&texture.view]),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            },
+        ],
+        label: Some("diffuse_bind_group"),
+    }))
+}
+
+// hot reloading is a nice to have, the game runs fine without it
+fn watch_theme(theme: &Theme) -> Option<ThemeWatcher> {
+    match ThemeWatcher::new(theme) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("Error watching theme '{}': {:?}", theme.name, e);
+            None
         }
     }
 }
 
-pub async fn run() {
+fn sort_hash_by_z(
+    instances_hash: &HashMap<String, Vec<InstanceRaw>>,
+) -> Vec<(String, Vec<InstanceRaw>)> {
+    let mut hash_as_vec: Vec<(String, Vec<InstanceRaw>)> =
+        instances_hash.clone().into_iter().collect();
+    hash_as_vec.sort_by(|(_, vec_a), (_, vec_b)| {
+        let z_a = vec_a.first().map(|instance| instance.z_index);
+        let z_b = vec_b.first().map(|instance| instance.z_index);
+
+        match (z_a, z_b) {
+            (Some(za), Some(zb)) => za.partial_cmp(&zb).unwrap_or(std::cmp::Ordering::Equal),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        }
+    });
+    hash_as_vec
+}
+
+/// Pixel space projection for the UI, independent of the world camera.
+fn screen_camera(size: winit::dpi::PhysicalSize<u32>) -> OrthographicCamera {
+    OrthographicCamera::new(0.0, size.width as f32, 0.0, size.height as f32, -1.0, 1.0)
+}
+
+pub async fn run(launch: Launch) {
     env_logger::init();
     let event_loop = EventLoop::new().unwrap();
     let window = WindowBuilder::new()
         .with_title("Minesweeper :3")
-        .with_inner_size(winit::dpi::PhysicalSize::new(800, 800))
-        .with_min_inner_size(winit::dpi::PhysicalSize::new(200, 200))
+        .with_inner_size(winit::dpi::LogicalSize::new(
+            launch.window_size.width,
+            launch.window_size.height,
+        ))
+        .with_min_inner_size(winit::dpi::LogicalSize::new(200, 200))
+        .with_fullscreen(
+            launch
+                .fullscreen
+                .then_some(winit::window::Fullscreen::Borderless(None)),
+        )
         .build(&event_loop)
         .unwrap();
 
-    let mut last_cursor_position: Option<Vec2> = None;
-    let mut last_cursor_position_test: Option<Vec2> = None;
-    let mut render_state = State::new(&window).await;
-    let mut new_board: Vec<InstanceRaw>;
-    (new_board, render_state.game_state) = create_minefield(render_state.sprites.clone());
-    render_state.create_instance("Tiles", &mut new_board);
+    let mut last_screen_cursor_position: Option<Vec2> = None;
+    // middle mouse is held, the camera follows the cursor
+    let mut is_dragging = false;
+    let mut render_state = State::new(&window, launch.game_config).await;
+    if let Some(path) = launch.replay {
+        if let Err(e) = Replay::load(&path).and_then(|replay| render_state.watch_replay(replay)) {
+            eprintln!("Error playing replay '{}': {:?}", path.display(), e);
+        }
+    } else if let Some(seed) = launch.seed {
+        render_state.screens.reset(Screen::Playing);
+        render_state.restart_with_seed(seed);
+    }
+    render_state.sync_screen();
 
     let _ = event_loop.run(move |event, control_flow| match event {
         Event::WindowEvent {
             ref event,
             window_id,
-        } if window_id == render_state.window().id() => {
-            if !render_state.input(event) {
-                match event {
-                    WindowEvent::CursorMoved {
-                        device_id: _,
-                        position,
-                    } => {
-                        //last_cursor_position = Some(*position);
-
-                        // make 0,0 camera left, down , render_state.width and height being left
-                        // and right and interpolate otherwise
-
-                        let new_position: Vec2 = Vec2::new(
-                            render_state.camera_left
-                                + position.x as f32 / render_state.size.width as f32
-                                    * (render_state.camera_right - render_state.camera_left),
-                            (render_state.camera_up - render_state.camera_down)
-                                + (render_state.camera_down
-                                    - position.y as f32 / render_state.size.height as f32
-                                        * (render_state.camera_up - render_state.camera_down)),
-                        );
-                        last_cursor_position = Some(new_position);
-                        last_cursor_position_test = Some(Vec2::new(
-                            position.x as f32,
-                            render_state.size.height as f32 - position.y as f32,
-                        ));
-
-                        //println!("{}{}", position.x, position.y);
-                        /* println!(
-                            "x: {} y: {}",
-                            last_cursor_position.unwrap().x as f32 / render_state.size.width as f32,
-                            1.0 - last_cursor_position.unwrap().y as f32
-                                / render_state.size.height as f32
-                        ); */
+        } if window_id == render_state.window().id() && !render_state.input(event) => {
+            match event {
+                WindowEvent::CursorMoved {
+                    device_id: _,
+                    position,
+                } => {
+                    // the UI works from the bottom left, the world position is
+                    // worked out on click, see cursor_world
+                    let screen_position = Vec2::new(
+                        position.x as f32,
+                        render_state.size.height as f32 - position.y as f32,
+                    );
+                    if let (true, Some(last)) = (is_dragging, last_screen_cursor_position) {
+                        render_state.drag_camera(screen_position - last);
                     }
-                    WindowEvent::KeyboardInput {
-                        device_id: _,
-                        event,
-                        is_synthetic: _,
-                    } => {
-                        let is_pressed = event.state == ElementState::Pressed;
-                        match event.physical_key {
-                            winit::keyboard::PhysicalKey::Code(KeyCode::Tab) => {
-                                render_state.reset_instances();
-                            }
-                            winit::keyboard::PhysicalKey::Code(KeyCode::Space) => {
-                                if render_state.game_state.board.is_empty() {
-                                    let mut new_board: Vec<InstanceRaw>;
-                                    (new_board, render_state.game_state) =
-                                        create_minefield(render_state.sprites.clone());
-                                    render_state.create_instance("Tiles", &mut new_board);
-                                }
-                            }
-                            winit::keyboard::PhysicalKey::Code(KeyCode::PageUp) => {
-                                render_state.is_pgup_pressed = is_pressed;
-                            }
-                            winit::keyboard::PhysicalKey::Code(KeyCode::PageDown) => {
-                                render_state.is_pgdown_pressed = is_pressed;
-                            }
-                            winit::keyboard::PhysicalKey::Code(KeyCode::ArrowUp) => {
-                                render_state.is_up_pressed = is_pressed;
-                            }
-                            winit::keyboard::PhysicalKey::Code(KeyCode::ArrowDown) => {
-                                render_state.is_down_pressed = is_pressed;
-                            }
-                            winit::keyboard::PhysicalKey::Code(KeyCode::ArrowLeft) => {
-                                render_state.is_left_pressed = is_pressed;
-                            }
-                            winit::keyboard::PhysicalKey::Code(KeyCode::ArrowRight) => {
-                                render_state.is_right_pressed = is_pressed;
-                            }
-                            winit::keyboard::PhysicalKey::Code(KeyCode::ShiftRight) => {
-                                render_state.camera_left = 0.0;
-                                render_state.camera_up = 1.0;
-                                render_state.camera_right = 1.0;
-                                render_state.camera_down = 0.0;
-                                render_state.update_camera();
-                            }
-                            _ => {}
+                    last_screen_cursor_position = Some(screen_position);
+                    if let Some(menu) = render_state.menu.as_mut() {
+                        if menu.hover(last_screen_cursor_position.unwrap()) {
+                            render_state.refresh_menu();
                         }
                     }
 
-                    WindowEvent::MouseInput {
-                        device_id: _,
-                        state,
-                        button,
-                    } => {
-                        if *button == MouseButton::Left && *state == ElementState::Pressed {
-                            println!("Left Mouse");
-                            println!(
-                                "original: {},{} |  new: {},{}",
-                                last_cursor_position_test.unwrap().x,
-                                last_cursor_position_test.unwrap().y,
-                                last_cursor_position.unwrap().x,
-                                last_cursor_position.unwrap().y
-                            );
-                            let mut board_position: Option<Vec2> = None;
-                            let mut unclicked_count: u32 = 0;
-                            let mut flagged_list: Option<Vec<usize>> = None;
-
-                            for tiles in render_state.game_state.board.iter() {
-                                if tiles.is_clicked(last_cursor_position.unwrap()) {
-                                    if !tiles.clicked {
-                                        if !tiles.flagged {
-                                            board_position = Some(tiles.board_position);
-                                        }
-                                        unclicked_count += 1;
-                                    } else {
-                                        let mut non_flag_list: Vec<usize> = Vec::new();
-
-                                        for index in
-                                            nearby_possible_positions(tiles.board_position).iter()
-                                        {
-                                            if !render_state
-                                                .game_state
-                                                .board
-                                                .get(*index)
-                                                .unwrap()
-                                                .flagged
-                                                && !render_state
-                                                    .game_state
-                                                    .board
-                                                    .get(*index)
-                                                    .unwrap()
-                                                    .clicked
-                                            {
-                                                non_flag_list.push(*index);
-                                                println!("{}", index);
-                                            }
-                                        }
-
-                                        flagged_list = Some(non_flag_list);
-                                    }
-                                }
-                            }
-                            if flagged_list.is_some() {
-                                for index in flagged_list.unwrap().iter() {
-                                    render_state.click_tile(*index);
-                                }
+                    //println!("{}{}", position.x, position.y);
+                    /* println!(
+                        "x: {} y: {}",
+                        last_cursor_position.unwrap().x as f32 / render_state.size.width as f32,
+                        1.0 - last_cursor_position.unwrap().y as f32
+                            / render_state.size.height as f32
+                    ); */
+                }
+                WindowEvent::KeyboardInput {
+                    device_id: _,
+                    event,
+                    is_synthetic: _,
+                } => {
+                    let is_pressed = event.state == ElementState::Pressed;
+                    if render_state.screens.top() == Screen::Editor && is_pressed {
+                        // the rest, like the camera keys, work as they do in a game
+                        if let Some(action) = match event.physical_key {
+                            winit::keyboard::PhysicalKey::Code(key) => editor::key_action(key),
+                            _ => None,
+                        } {
+                            render_state.editor_action(action);
+                            return;
+                        }
+                    }
+                    if render_state.screens.top() == Screen::Replay {
+                        if let (true, winit::keyboard::PhysicalKey::Code(key)) =
+                            (is_pressed, event.physical_key)
+                        {
+                            render_state.replay_key(key);
+                        }
+                        return;
+                    }
+                    if let Some(menu) = render_state.menu.as_mut() {
+                        if let (true, winit::keyboard::PhysicalKey::Code(key)) =
+                            (is_pressed, event.physical_key)
+                        {
+                            let (action, changed) = menu.key(key, &mut render_state.game_config);
+                            if changed {
+                                render_state.refresh_menu();
                             }
-                            if board_position.is_some() {
-                                let board_position = board_position.unwrap();
-                                render_state.click_tile(
-                                    (board_position.x + board_position.y * BOARD_WIDTH as f32)
-                                        as usize,
-                                );
-                                let new_clicked_tile = render_state
-                                    .game_state
-                                    .board
-                                    .get(
-                                        (board_position.x + board_position.y * BOARD_WIDTH as f32)
-                                            as usize,
-                                    )
-                                    .expect("idefk");
-                                // println!("{}", unclicked_count);
-                                if unclicked_count == BOARD_WIDTH * BOARD_LENGTH
-                                    && new_clicked_tile.has_mine()
-                                {
-                                    let mut board_not_ready = true;
-                                    while board_not_ready {
-                                        let mut new_board: Vec<InstanceRaw>;
-                                        (new_board, render_state.game_state) =
-                                            create_minefield(render_state.sprites.clone());
-                                        render_state.create_instance("Tiles", &mut new_board);
-                                        println!("hi");
-                                        if let Some(tile) = render_state.game_state.board.get(
-                                            (board_position.x
-                                                + board_position.y * BOARD_WIDTH as f32)
-                                                as usize,
-                                        ) {
-                                            if !tile.has_mine() {
-                                                board_not_ready = false;
-                                                println!(
-                                                    "{}",
-                                                    render_state.find_nearby_mines(board_position)
-                                                )
-                                            }
-                                        }
-                                    }
+                            if let Some(action) = action {
+                                if render_state.apply_menu_action(action) {
+                                    control_flow.exit();
                                 }
                             }
                         }
-                        if *button == MouseButton::Right && *state == ElementState::Pressed {
-                            println!("Right Mouse");
-                            let mut clicked_tile: Option<(Vec2, f32, Vec2)> = None;
-                            let mut new_flag = true;
-
-                            for tiles in render_state.game_state.board.iter_mut() {
-                                if !tiles.clicked && tiles.is_clicked(last_cursor_position.unwrap())
-                                {
-                                    if tiles.flagged {
-                                        tiles.flagged = false;
-                                        new_flag = false;
-                                    } else {
-                                        tiles.flagged = true;
-                                    }
-                                    clicked_tile = Some((
-                                        Vec2::new(
-                                            tiles.position.x + 0.55 * tiles.size,
-                                            tiles.position.y + 0.55 * tiles.size,
-                                        ),
-                                        tiles.size,
-                                        tiles.board_position,
-                                    ));
-                                }
+                        return;
+                    }
+                    match event.physical_key {
+                        winit::keyboard::PhysicalKey::Code(KeyCode::Space) if is_pressed => {
+                            render_state.skip_animations();
+                        }
+                        winit::keyboard::PhysicalKey::Code(KeyCode::Escape) if is_pressed => {
+                            render_state.screens.push(Screen::Paused);
+                            render_state.sync_screen();
+                        }
+                        winit::keyboard::PhysicalKey::Code(KeyCode::PageUp) => {
+                            render_state.is_pgup_pressed = is_pressed;
+                        }
+                        winit::keyboard::PhysicalKey::Code(KeyCode::PageDown) => {
+                            render_state.is_pgdown_pressed = is_pressed;
+                        }
+                        winit::keyboard::PhysicalKey::Code(KeyCode::ArrowUp) => {
+                            render_state.is_up_pressed = is_pressed;
+                        }
+                        winit::keyboard::PhysicalKey::Code(KeyCode::ArrowDown) => {
+                            render_state.is_down_pressed = is_pressed;
+                        }
+                        winit::keyboard::PhysicalKey::Code(KeyCode::ArrowLeft) => {
+                            render_state.is_left_pressed = is_pressed;
+                        }
+                        winit::keyboard::PhysicalKey::Code(KeyCode::ArrowRight) => {
+                            render_state.is_right_pressed = is_pressed;
+                        }
+                        winit::keyboard::PhysicalKey::Code(KeyCode::ShiftRight) => {
+                            render_state.view.fit();
+                        }
+                        winit::keyboard::PhysicalKey::Code(KeyCode::KeyZ) if is_pressed => {
+                            render_state.step_history(false);
+                        }
+                        winit::keyboard::PhysicalKey::Code(KeyCode::KeyY) if is_pressed => {
+                            render_state.step_history(true);
+                        }
+                        _ => {}
+                    }
+                }
+
+                WindowEvent::MouseInput {
+                    device_id: _,
+                    state,
+                    button,
+                } => {
+                    if *button == MouseButton::Middle {
+                        is_dragging =
+                            *state == ElementState::Pressed && render_state.menu.is_none();
+                        if !is_dragging {
+                            render_state.view.end_drag();
+                        }
+                        return;
+                    }
+                    if let Some(menu) = render_state.menu.as_mut() {
+                        if let (ElementState::Pressed, Some(screen_cursor)) =
+                            (state, last_screen_cursor_position)
+                        {
+                            let forwards = *button != MouseButton::Right;
+                            let (action, changed) =
+                                menu.click(screen_cursor, forwards, &mut render_state.game_config);
+                            if changed {
+                                render_state.refresh_menu();
                             }
-                            if clicked_tile.is_some() && new_flag {
-                                render_state.create_instance(
-                                    "Flags",
-                                    &mut vec![Instance::to_raw(
-                                        clicked_tile.unwrap().0,
-                                        0.0,
-                                        Vec2::new(clicked_tile.unwrap().1, clicked_tile.unwrap().1),
-                                        Z_MINE,
-                                        *render_state.sprites.get("Flags").expect("no flag :c"),
-                                        0,
-                                    )],
-                                );
-                            } else if clicked_tile.is_some() && !new_flag {
-                                let mut flags_vec = render_state
-                                    .instances_hash
-                                    .get("Flags")
-                                    .expect(
-                                        "checking for removing flag when flags doesnt exist wtf?",
-                                    )
-                                    .clone();
-                                let mut flag_index: Option<usize> = None;
-                                for (index, flags_instances) in flags_vec.iter().enumerate() {
-                                    if flags_instances
-                                        == &Instance::to_raw(
-                                            clicked_tile.unwrap().0,
-                                            0.0,
-                                            Vec2::new(
-                                                clicked_tile.unwrap().1,
-                                                clicked_tile.unwrap().1,
-                                            ),
-                                            Z_MINE,
-                                            *render_state.sprites.get("Flags").expect("no flag :c"),
-                                            0,
-                                        )
-                                    {
-                                        flag_index = Some(index);
-                                    }
-                                }
-                                if let Some(i) = flag_index {
-                                    flags_vec.remove(i);
-                                    render_state.instances_hash.remove_entry("Flags");
-                                    render_state
-                                        .instances_hash
-                                        .insert(String::from("Flags"), flags_vec);
-                                    render_state.update_instance_buffer();
+                            if let Some(action) = action {
+                                if render_state.apply_menu_action(action) {
+                                    control_flow.exit();
                                 }
                             }
                         }
+                        return;
                     }
-                    WindowEvent::MouseWheel {
-                        device_id: _,
-                        delta,
-                        phase: _,
-                    } => render_state.camera_zoom(*delta),
-                    WindowEvent::RedrawRequested => {
-                        render_state.update();
-                        render_state.window().request_redraw();
-
-                        match render_state.render() {
-                            Ok(_) => {}
-                            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
-                                render_state.resize(render_state.size)
-                            }
-                            Err(wgpu::SurfaceError::OutOfMemory | wgpu::SurfaceError::Other) => {
-                                log::error!("OutOfMemory");
-                                control_flow.exit();
+                    if render_state.screens.top() == Screen::Editor {
+                        if let (ElementState::Pressed, Some(screen_cursor)) =
+                            (state, last_screen_cursor_position)
+                        {
+                            render_state.editor_click(screen_cursor, *button == MouseButton::Right);
+                        }
+                        return;
+                    }
+                    // replays play themselves
+                    if !render_state.screens.top().is_playing() {
+                        return;
+                    }
+                    if *button == MouseButton::Left && *state == ElementState::Pressed {
+                        println!("Left Mouse");
+                        let Some(screen_cursor) = last_screen_cursor_position else {
+                            return;
+                        };
+                        let cursor = render_state.cursor_world(screen_cursor);
+                        println!(
+                            "original: {},{} |  new: {},{}",
+                            screen_cursor.x, screen_cursor.y, cursor.x, cursor.y
+                        );
+                        if let Some(action) = render_state.hud.hit_test(screen_cursor) {
+                            match action {
+                                HudAction::Restart => render_state.restart(),
                             }
-
-                            Err(wgpu::SurfaceError::Timeout) => {
-                                log::warn!("Surface timeout")
+                            return;
+                        }
+                        if render_state.hud.covers(screen_cursor)
+                            || render_state.game_state.phase().is_over()
+                        {
+                            return;
+                        }
+                        let action = render_state.game_state.tile_at(cursor).and_then(|index| {
+                            let tile = &render_state.game_state.board[index];
+                            if tile.clicked {
+                                Some(Action::Chord(index))
+                            } else if !tile.flagged {
+                                Some(Action::Reveal(index))
+                            } else {
+                                None
                             }
+                        });
+                        if let Some(action) = action {
+                            render_state.perform(action);
                         }
                     }
-                    WindowEvent::CloseRequested => control_flow.exit(),
-                    WindowEvent::Resized(physical_size) => {
-                        render_state.resize(*physical_size);
+                    if *button == MouseButton::Right && *state == ElementState::Pressed {
+                        println!("Right Mouse");
+                        let Some(screen_cursor) = last_screen_cursor_position else {
+                            return;
+                        };
+                        let cursor = render_state.cursor_world(screen_cursor);
+                        if render_state.hud.covers(screen_cursor)
+                            || render_state.game_state.phase().is_over()
+                        {
+                            return;
+                        }
+                        let flag_index = render_state
+                            .game_state
+                            .tile_at(cursor)
+                            .filter(|index| !render_state.game_state.board[*index].clicked);
+                        if let Some(index) = flag_index {
+                            render_state.perform(Action::Flag(index));
+                        }
                     }
-                    _ => {}
                 }
+                WindowEvent::MouseWheel {
+                    device_id: _,
+                    delta,
+                    phase: _,
+                } if render_state.screens.top().shows_board() => {
+                    let anchor = last_screen_cursor_position.map_or(Vec2::splat(0.5), |cursor| {
+                        cursor
+                            / Vec2::new(
+                                render_state.size.width as f32,
+                                render_state.size.height as f32,
+                            )
+                    });
+                    render_state.camera_zoom(*delta, anchor);
+                }
+                WindowEvent::RedrawRequested => {
+                    render_state.update();
+                    render_state.window().request_redraw();
+
+                    match render_state.render() {
+                        Ok(_) => {}
+                        Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                            render_state.resize(render_state.size)
+                        }
+                        Err(wgpu::SurfaceError::OutOfMemory | wgpu::SurfaceError::Other) => {
+                            log::error!("OutOfMemory");
+                            control_flow.exit();
+                        }
+
+                        Err(wgpu::SurfaceError::Timeout) => {
+                            log::warn!("Surface timeout")
+                        }
+                    }
+                }
+                WindowEvent::CloseRequested => {
+                    render_state.autosave();
+                    control_flow.exit();
+                }
+                WindowEvent::Resized(physical_size) => {
+                    render_state.resize(*physical_size);
+                }
+                _ => {}
             }
         }
         _ => {}
@@ -0,0 +1,204 @@
+use crate::instance::{Instance, InstanceRaw};
+use crate::text::{text_instances, text_size};
+use crate::{GamePhase, GameState};
+use glam::{Vec2, Vec4};
+use std::collections::HashMap;
+
+pub const HUD_HEIGHT: f32 = 56.0;
+const HUD_MARGIN: f32 = 12.0;
+const HUD_TEXT_HEIGHT: f32 = 20.0;
+const PHASE_TEXT_HEIGHT: f32 = 12.0;
+const FACE_SIZE: f32 = 40.0;
+const Z_HUD_BACK: f32 = 0.3;
+const Z_HUD_TEXT: f32 = 0.4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HudAction {
+    Restart,
+}
+
+/// Axis aligned box in screen pixels, (0, 0) is the bottom left of the window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScreenRect {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl ScreenRect {
+    pub fn from_center(center: Vec2, size: Vec2) -> ScreenRect {
+        Self {
+            min: center - size / 2.0,
+            max: center + size / 2.0,
+        }
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        point.x >= self.min.x
+            && point.x <= self.max.x
+            && point.y >= self.min.y
+            && point.y <= self.max.y
+    }
+
+    pub fn center(&self) -> Vec2 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn size(&self) -> Vec2 {
+        self.max - self.min
+    }
+}
+
+// what the HUD showed last time, so it is only rebuilt when something changes
+#[derive(Clone, PartialEq)]
+struct HudSnapshot {
+    remaining_mines: i64,
+    seconds: u64,
+    phase: GamePhase,
+    note: Option<String>,
+    screen_size: (u32, u32),
+}
+
+/// Mine counter, timer, phase and restart face along the top of the window.
+pub struct Hud {
+    restart_button: ScreenRect,
+    bar: ScreenRect,
+    last_snapshot: Option<HudSnapshot>,
+}
+
+impl Hud {
+    pub fn new() -> Hud {
+        Self {
+            restart_button: ScreenRect::from_center(Vec2::ZERO, Vec2::ZERO),
+            bar: ScreenRect::from_center(Vec2::ZERO, Vec2::ZERO),
+            last_snapshot: None,
+        }
+    }
+
+    /// Forces a rebuild on the next update, for when the HUD layer was cleared.
+    pub fn invalidate(&mut self) {
+        self.last_snapshot = None;
+    }
+
+    /// HUD elements are checked before the board, clicks on the bar never reach the tiles.
+    pub fn hit_test(&self, screen_position: Vec2) -> Option<HudAction> {
+        if self.restart_button.contains(screen_position) {
+            return Some(HudAction::Restart);
+        }
+        None
+    }
+
+    pub fn covers(&self, screen_position: Vec2) -> bool {
+        self.bar.contains(screen_position)
+    }
+
+    /// Returns new instances when the HUD needs redrawing, None if nothing changed.
+    /// `note` goes under the phase, like undo charges left or a puzzle's mistakes.
+    pub fn update(
+        &mut self,
+        game_state: &GameState,
+        note: Option<String>,
+        screen_size: winit::dpi::PhysicalSize<u32>,
+        sprites: &HashMap<String, Vec4>,
+    ) -> Option<Vec<InstanceRaw>> {
+        let snapshot = HudSnapshot {
+            remaining_mines: game_state.remaining_mines(),
+            seconds: game_state.elapsed().as_secs(),
+            phase: game_state.phase(),
+            note,
+            screen_size: (screen_size.width, screen_size.height),
+        };
+        if self.last_snapshot.as_ref() == Some(&snapshot) {
+            return None;
+        }
+
+        let width = screen_size.width as f32;
+        let top = screen_size.height as f32;
+        self.bar = ScreenRect {
+            min: Vec2::new(0.0, top - HUD_HEIGHT),
+            max: Vec2::new(width, top),
+        };
+        let middle = top - HUD_HEIGHT / 2.0;
+        self.restart_button =
+            ScreenRect::from_center(Vec2::new(width / 2.0, middle), Vec2::splat(FACE_SIZE));
+
+        let mut instances = vec![
+            Instance::to_raw(
+                self.bar.center(),
+                0.0,
+                self.bar.size(),
+                Z_HUD_BACK,
+                *sprites.get("Tiles").expect("No Tiles :c"),
+                0,
+            ),
+            Instance::to_raw(
+                self.restart_button.center(),
+                0.0,
+                self.restart_button.size(),
+                Z_HUD_BACK + 0.01,
+                *sprites.get("Tiles").expect("No Tiles :c"),
+                0,
+            ),
+        ];
+
+        let text_baseline = middle - HUD_TEXT_HEIGHT / 2.0;
+        let mines_text = format!("{:03}", snapshot.remaining_mines.clamp(-99, 999));
+        instances.extend(text_instances(
+            &mines_text,
+            Vec2::new(HUD_MARGIN, text_baseline),
+            HUD_TEXT_HEIGHT,
+            Z_HUD_TEXT,
+            sprites,
+        ));
+        let phase_left = HUD_MARGIN + text_size("0000", HUD_TEXT_HEIGHT).x;
+        instances.extend(text_instances(
+            snapshot.phase.label(),
+            Vec2::new(phase_left, middle - PHASE_TEXT_HEIGHT / 2.0),
+            PHASE_TEXT_HEIGHT,
+            Z_HUD_TEXT,
+            sprites,
+        ));
+        if let Some(note) = &snapshot.note {
+            instances.extend(text_instances(
+                note,
+                Vec2::new(phase_left, middle - PHASE_TEXT_HEIGHT * 2.0),
+                PHASE_TEXT_HEIGHT,
+                Z_HUD_TEXT,
+                sprites,
+            ));
+        }
+
+        let face = match snapshot.phase {
+            GamePhase::Ready | GamePhase::Playing => ":)",
+            GamePhase::Won => "B)",
+            GamePhase::Lost => "X(",
+        };
+        let face_size = text_size(face, HUD_TEXT_HEIGHT);
+        instances.extend(text_instances(
+            face,
+            self.restart_button.center() - face_size / 2.0,
+            HUD_TEXT_HEIGHT,
+            Z_HUD_TEXT,
+            sprites,
+        ));
+
+        let timer_text = format!("{:03}", snapshot.seconds.min(999));
+        instances.extend(text_instances(
+            &timer_text,
+            Vec2::new(
+                width - HUD_MARGIN - text_size(&timer_text, HUD_TEXT_HEIGHT).x,
+                text_baseline,
+            ),
+            HUD_TEXT_HEIGHT,
+            Z_HUD_TEXT,
+            sprites,
+        ));
+        self.last_snapshot = Some(snapshot);
+        Some(instances)
+    }
+}
+
+impl Default for Hud {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,4 +1,5 @@
 use glam::{Mat4, Vec2, Vec4};
+use std::ops::Range;
 
 pub struct Instance {}
 
@@ -21,6 +22,8 @@ impl Instance {
             texture_index,
             tex_coords_bounds: tex_coords_bounds.into(),
             z_index,
+            color: [1.0; 4],
+            digit: 0,
         }
     }
 }
@@ -32,9 +35,29 @@ pub struct InstanceRaw {
     tex_coords_bounds: [f32; 4],
     texture_index: u32,
     pub z_index: f32,
+    // multiplied with the sprite, white leaves it as is
+    color: [f32; 4],
+    // 1 to 8 on number tiles so the palette can recolor them, 0 for everything else
+    digit: u32,
 }
 
 impl InstanceRaw {
+    pub fn blank() -> InstanceRaw {
+        Instance::to_raw(Vec2::ZERO, 0.0, Vec2::ZERO, 0.0, Vec4::ZERO, 0)
+    }
+
+    /// Tints the sprite, alpha fades it out.
+    pub fn with_color(mut self, color: Vec4) -> InstanceRaw {
+        self.color = color.into();
+        self
+    }
+
+    /// Marks a revealed number tile, see `Palette`.
+    pub fn with_digit(mut self, digit: u8) -> InstanceRaw {
+        self.digit = digit as u32;
+        self
+    }
+
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
         use std::mem;
         wgpu::VertexBufferLayout {
@@ -73,7 +96,171 @@ impl InstanceRaw {
                     shader_location: 10,
                     format: wgpu::VertexFormat::Uint32,
                 },
+                // color, after z_index which the shader doesn't need
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 22]>() as wgpu::BufferAddress,
+                    shader_location: 11,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                // digit
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 26]>() as wgpu::BufferAddress,
+                    shader_location: 12,
+                    format: wgpu::VertexFormat::Uint32,
+                },
             ],
         }
     }
 }
+
+/// CPU copy of an instance buffer split into fixed size layers, one slot per tile.
+/// Writes only mark a dirty range, the GPU copy is updated once per frame by the owner.
+pub struct InstanceSlots {
+    instances: Vec<InstanceRaw>,
+    layers: Vec<(String, Range<usize>)>,
+    dirty: Option<Range<usize>>,
+    // set when the layout changed and the GPU buffer has to be rebuilt
+    resized: bool,
+}
+
+impl InstanceSlots {
+    pub fn new() -> InstanceSlots {
+        Self {
+            instances: Vec::new(),
+            layers: Vec::new(),
+            dirty: None,
+            resized: true,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.instances.clear();
+        self.layers.clear();
+        self.dirty = None;
+        self.resized = true;
+    }
+
+    /// Layers are drawn in the order they are added, so add them back to front.
+    pub fn add_layer(&mut self, key: &str, slots: usize) {
+        let start = self.instances.len();
+        self.instances.resize(start + slots, InstanceRaw::blank());
+        self.layers.push((String::from(key), start..start + slots));
+        self.resized = true;
+    }
+
+    /// Where a layer's slots sit in the instance buffer.
+    pub fn layer_range(&self, key: &str) -> Range<usize> {
+        self.layers
+            .iter()
+            .find(|(layer, _)| layer == key)
+            .map(|(_, range)| range.clone())
+            .unwrap_or_else(|| panic!("no instance layer called {}", key))
+    }
+
+    fn slot_index(&self, key: &str, slot: usize) -> usize {
+        let range = self.layer_range(key);
+        assert!(slot < range.len(), "slot {} out of range for {}", slot, key);
+        range.start + slot
+    }
+
+    pub fn set(&mut self, key: &str, slot: usize, instance: InstanceRaw) {
+        let index = self.slot_index(key, slot);
+        if self.instances[index] == instance {
+            return;
+        }
+        self.instances[index] = instance;
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(index)..dirty.end.max(index + 1),
+            None => index..index + 1,
+        });
+    }
+
+    /// Hides a slot, blank instances have no size so they draw nothing.
+    pub fn clear_slot(&mut self, key: &str, slot: usize) {
+        self.set(key, slot, InstanceRaw::blank());
+    }
+
+    pub fn as_slice(&self) -> &[InstanceRaw] {
+        &self.instances
+    }
+
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    /// What changed since the last call, `Full` means the buffer has to be recreated.
+    pub fn take_changes(&mut self) -> SlotChanges {
+        if self.resized {
+            self.resized = false;
+            self.dirty = None;
+            return SlotChanges::Full;
+        }
+        match self.dirty.take() {
+            Some(range) => SlotChanges::Range(range),
+            None => SlotChanges::None,
+        }
+    }
+}
+
+impl Default for InstanceSlots {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SlotChanges {
+    None,
+    Range(Range<usize>),
+    Full,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile(x: f32) -> InstanceRaw {
+        Instance::to_raw(Vec2::new(x, 0.0), 0.0, Vec2::ONE, 0.0, Vec4::ONE, 0)
+    }
+
+    #[test]
+    fn far_apart_writes_merge_into_one_range() {
+        let mut slots = InstanceSlots::new();
+        slots.add_layer("Tiles", 100);
+        slots.add_layer("Overlays", 10);
+        // the new layers have to be uploaded whole, once
+        assert_eq!(slots.take_changes(), SlotChanges::Full);
+        assert_eq!(slots.take_changes(), SlotChanges::None);
+
+        slots.set("Tiles", 90, tile(1.0));
+        slots.set("Tiles", 3, tile(2.0));
+        slots.set("Overlays", 2, tile(3.0));
+        assert_eq!(slots.take_changes(), SlotChanges::Range(3..103));
+        assert_eq!(slots.take_changes(), SlotChanges::None);
+        // writing what's already there changes nothing
+        slots.set("Tiles", 90, tile(1.0));
+        assert_eq!(slots.take_changes(), SlotChanges::None);
+    }
+
+    #[test]
+    fn resizing_a_layer_uploads_everything() {
+        let mut slots = InstanceSlots::new();
+        slots.add_layer("Tiles", 4);
+        slots.take_changes();
+        slots.set("Tiles", 1, tile(1.0));
+        // a new layer wins over the dirty range, it's in the full upload
+        slots.add_layer("Particles", 8);
+        assert_eq!(slots.take_changes(), SlotChanges::Full);
+        assert_eq!(slots.take_changes(), SlotChanges::None);
+        assert_eq!(slots.layer_range("Particles"), 4..12);
+
+        slots.clear();
+        assert!(slots.is_empty());
+        assert_eq!(slots.take_changes(), SlotChanges::Full);
+        assert_eq!(slots.take_changes(), SlotChanges::None);
+    }
+}
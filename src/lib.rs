@@ -0,0 +1,546 @@
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+pub mod analysis;
+pub mod animation;
+pub mod assets;
+pub mod board_format;
+pub mod board_mask;
+pub mod camera;
+pub mod cli;
+pub mod config;
+pub mod editor;
+pub mod game_objects;
+pub mod graphics_and_window;
+pub mod hud;
+pub mod instance;
+pub mod palette;
+pub mod particles;
+pub mod puzzle;
+pub mod replay;
+pub mod save;
+pub mod screens;
+pub mod solver;
+pub mod stats;
+pub mod text;
+pub mod texture;
+pub mod theme;
+pub mod tui;
+pub mod undo;
+
+const CAMERA_MOVE_SPEED: f32 = 0.000000001;
+const BOARD_WIDTH: u32 = 15;
+const BOARD_LENGTH: u32 = 15;
+const MINE_COUNT: u32 = 30;
+// ascii or png mask for irregular boards, overrides BOARD_WIDTH/BOARD_LENGTH
+const BOARD_MASK: Option<&str> = None;
+
+trait MineActiveTrait {
+    fn set_active(&mut self, active: bool);
+}
+
+pub struct CommonMineState {
+    active: bool,
+    mine_index: u32,
+}
+
+impl MineActiveTrait for CommonMineState {
+    fn set_active(&mut self, active: bool) {
+        self.active = active;
+    }
+}
+
+pub enum Mines {
+    Default(CommonMineState),
+}
+
+impl Mines {
+    pub fn activate(&mut self) {
+        match self {
+            Mines::Default(state) => state.set_active(true),
+        }
+    }
+
+    fn get_index(&self) -> u32 {
+        match self {
+            Mines::Default(state) => state.mine_index,
+        }
+    }
+}
+pub struct Tiles {
+    board_position: Vec2,
+    position: Vec2,
+    size: f32,
+    mine: Option<Mines>,
+    pub clicked: bool,
+    pub flagged: bool,
+    pub questioned: bool,
+}
+
+impl Tiles {
+    pub fn new(board_position: Vec2, position: Vec2, mine: Option<Mines>, size: f32) -> Tiles {
+        Self {
+            board_position,
+            position,
+            mine,
+            size,
+            clicked: false,
+            flagged: false,
+            questioned: false,
+        }
+    }
+
+    pub fn is_clicked(&self, mouse_pos: Vec2) -> bool {
+        if self.position.x <= mouse_pos.x
+            && self.position.x > mouse_pos.x - self.size
+            && self.position.y <= mouse_pos.y
+            && self.position.y > mouse_pos.y - self.size
+        {
+            return true;
+        }
+        false
+    }
+
+    pub fn board_position(&self) -> Vec2 {
+        self.board_position
+    }
+
+    pub fn has_mine(&self) -> bool {
+        self.mine.is_some()
+    }
+
+    pub fn set_mine(&mut self, mine: Option<Mines>) {
+        self.mine = mine;
+    }
+
+    pub fn get_mine_index(&self) -> Option<u32> {
+        self.mine.as_ref().map(|mine| mine.get_index())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GamePhase {
+    Ready,
+    Playing,
+    Won,
+    Lost,
+}
+
+impl GamePhase {
+    pub fn is_over(&self) -> bool {
+        matches!(self, GamePhase::Won | GamePhase::Lost)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            GamePhase::Ready => "READY",
+            GamePhase::Playing => "PLAYING",
+            GamePhase::Won => "WON",
+            GamePhase::Lost => "LOST",
+        }
+    }
+}
+
+/// What a tile looks like, worked out from the game state so it never drifts from it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileVisual {
+    Covered,
+    Flagged,
+    Questioned,
+    Revealed(u8),
+    // the mine that was clicked
+    Exploded,
+    // the other mines, once the game is lost
+    Mine,
+    // a flag on a safe tile, once the game is lost
+    WrongFlag,
+}
+
+pub struct GameState {
+    board: Vec<Tiles>,
+    width: u32,
+    length: u32,
+    // board index of every grid cell, None where a mask cut a hole
+    lookup: Vec<Option<usize>>,
+    phase: GamePhase,
+    mine_count: u32,
+    flag_count: u32,
+    revealed_count: u32,
+    started_at: Option<Instant>,
+    ended_at: Option<Instant>,
+    paused_at: Option<Instant>,
+    seed: u64,
+    // every click that touched the board, for efficiency
+    clicks: u32,
+    // right click goes through a question mark after the flag
+    question_marks: bool,
+}
+
+impl GameState {
+    pub fn new(board: Vec<Tiles>, width: u32, length: u32, seed: u64) -> GameState {
+        let mut lookup = vec![None; (width * length) as usize];
+        for (index, tile) in board.iter().enumerate() {
+            let cell = tile.board_position.x as u32 + tile.board_position.y as u32 * width;
+            lookup[cell as usize] = Some(index);
+        }
+        let mine_count = board.iter().filter(|tile| tile.has_mine()).count() as u32;
+        Self {
+            board,
+            width,
+            length,
+            lookup,
+            phase: GamePhase::Ready,
+            mine_count,
+            flag_count: 0,
+            revealed_count: 0,
+            started_at: None,
+            ended_at: None,
+            paused_at: None,
+            seed,
+            clicks: 0,
+            question_marks: false,
+        }
+    }
+
+    pub fn empty() -> GameState {
+        Self::new(Vec::new(), 0, 0, 0)
+    }
+
+    pub fn tile_index(&self, board_position: Vec2) -> Option<usize> {
+        if board_position.x < 0.0
+            || board_position.y < 0.0
+            || board_position.x >= self.width as f32
+            || board_position.y >= self.length as f32
+        {
+            return None;
+        }
+        let cell = board_position.x as u32 + board_position.y as u32 * self.width;
+        self.lookup[cell as usize]
+    }
+
+    /// The tile under a world position, the inverse of how create_minefield lays tiles out.
+    pub fn tile_at(&self, world_position: Vec2) -> Option<usize> {
+        let size = self.board.first()?.size;
+        self.tile_index((world_position / size).floor())
+    }
+
+    /// Board index ranges of the tiles inside a world space rectangle, one per row
+    /// and merged where rows run into each other, for drawing only what's on screen.
+    pub fn visible_ranges(&self, min: Vec2, max: Vec2) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        let Some(size) = self.board.first().map(|tile| tile.size) else {
+            return ranges;
+        };
+        let clamp_col = |x: f32| ((x / size).floor().max(0.0) as u32).min(self.width - 1);
+        let clamp_row = |y: f32| ((y / size).floor().max(0.0) as u32).min(self.length - 1);
+        let (board_width, board_length) = (self.width as f32 * size, self.length as f32 * size);
+        if max.x < 0.0 || max.y < 0.0 || min.x >= board_width || min.y >= board_length {
+            return ranges;
+        }
+        let (first_col, last_col) = (clamp_col(min.x), clamp_col(max.x));
+        for row in clamp_row(min.y)..=clamp_row(max.y) {
+            let cells = &self.lookup
+                [(row * self.width + first_col) as usize..=(row * self.width + last_col) as usize];
+            let (Some(first), Some(last)) = (
+                cells.iter().find_map(|cell| *cell),
+                cells.iter().rev().find_map(|cell| *cell),
+            ) else {
+                continue;
+            };
+            match ranges.last_mut() {
+                Some(range) if range.end == first => range.end = last + 1,
+                _ => ranges.push(first..last + 1),
+            }
+        }
+        ranges
+    }
+
+    /// World space corners of the board grid and its tile size, None for an empty board.
+    pub fn bounds(&self) -> Option<(Vec2, Vec2, f32)> {
+        let size = self.board.first()?.size;
+        Some((
+            Vec2::ZERO,
+            Vec2::new(self.width as f32, self.length as f32) * size,
+            size,
+        ))
+    }
+
+    /// Board indices of the (up to 8) tiles around a position, holes are skipped.
+    pub fn neighbors(&self, board_position: Vec2) -> Vec<usize> {
+        let mut neighbors = Vec::with_capacity(8);
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if dx == 0 && dy == 0 {
+                    continue;
+                }
+                let offset = Vec2::new(dx as f32, dy as f32);
+                if let Some(index) = self.tile_index(board_position + offset) {
+                    neighbors.push(index);
+                }
+            }
+        }
+        neighbors
+    }
+
+    pub fn board(&self) -> &[Tiles] {
+        &self.board
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn clicks(&self) -> u32 {
+        self.clicks
+    }
+
+    pub fn count_click(&mut self) {
+        self.clicks += 1;
+    }
+
+    pub fn set_question_marks(&mut self, question_marks: bool) {
+        self.question_marks = question_marks;
+    }
+
+    pub fn phase(&self) -> GamePhase {
+        self.phase
+    }
+
+    pub fn mine_count(&self) -> u32 {
+        self.mine_count
+    }
+
+    pub fn flag_count(&self) -> u32 {
+        self.flag_count
+    }
+
+    /// Mines minus flags, negative when the player over-flags.
+    pub fn remaining_mines(&self) -> i64 {
+        self.mine_count as i64 - self.flag_count as i64
+    }
+
+    /// Time since the first reveal, frozen once the game is over.
+    pub fn elapsed(&self) -> Duration {
+        match (self.started_at, self.ended_at.or(self.paused_at)) {
+            (Some(started), Some(ended)) => ended - started,
+            (Some(started), None) => started.elapsed(),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Stops the timer while a menu is open, the paused time is skipped on resume.
+    pub fn set_paused(&mut self, paused: bool) {
+        match (paused, self.paused_at) {
+            (true, None) => self.paused_at = Some(Instant::now()),
+            (false, Some(paused_at)) => {
+                if let Some(started) = self.started_at.as_mut() {
+                    *started += paused_at.elapsed();
+                }
+                self.paused_at = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Holds the timer on a given time, for replays that run on their own clock.
+    pub fn freeze_timer(&mut self, elapsed: Duration) {
+        if self.phase == GamePhase::Ready {
+            return;
+        }
+        let now = Instant::now();
+        let started = now.checked_sub(elapsed).unwrap_or(now);
+        self.started_at = Some(started);
+        if self.phase.is_over() {
+            self.ended_at = Some(started + elapsed);
+        } else {
+            self.paused_at = Some(started + elapsed);
+        }
+    }
+
+    pub fn adjacent_mines(&self, index: usize) -> u8 {
+        self.neighbors(self.board[index].board_position)
+            .iter()
+            .filter(|neighbor| self.board[**neighbor].has_mine())
+            .count() as u8
+    }
+
+    /// Marks a tile as revealed and moves the game to its next phase.
+    /// Returns true if the tile was a mine.
+    pub fn reveal(&mut self, index: usize) -> bool {
+        if self.phase == GamePhase::Ready {
+            self.phase = GamePhase::Playing;
+            self.started_at = Some(Instant::now());
+        }
+        let tile = &mut self.board[index];
+        if tile.clicked {
+            return tile.has_mine();
+        }
+        tile.clicked = true;
+        if let Some(mine) = tile.mine.as_mut() {
+            mine.activate();
+            self.end(GamePhase::Lost);
+            return true;
+        }
+        self.revealed_count += 1;
+        if self.revealed_count == self.board.len() as u32 - self.mine_count {
+            self.end(GamePhase::Won);
+        }
+        false
+    }
+
+    /// Reveals a tile and, if it has no mines around it, the whole opening with a
+    /// flood fill. Flagged tiles are left alone. Returns every tile it revealed.
+    pub fn open(&mut self, index: usize) -> Vec<usize> {
+        let mut revealed = Vec::new();
+        let mut stack = vec![index];
+        while let Some(index) = stack.pop() {
+            if self.board[index].clicked {
+                continue;
+            }
+            revealed.push(index);
+            if self.reveal(index) || self.adjacent_mines(index) > 0 {
+                continue;
+            }
+            for neighbor in self.neighbors(self.board[index].board_position) {
+                let tile = &self.board[neighbor];
+                if !tile.clicked && !tile.flagged {
+                    stack.push(neighbor);
+                }
+            }
+        }
+        revealed
+    }
+
+    /// Covered, unflagged tiles around a revealed tile, what clicking it opens.
+    pub fn chord_targets(&self, index: usize) -> Vec<usize> {
+        let tile = &self.board[index];
+        if !tile.clicked {
+            return Vec::new();
+        }
+        self.neighbors(tile.board_position)
+            .into_iter()
+            .filter(|neighbor| {
+                let neighbor = &self.board[*neighbor];
+                !neighbor.flagged && !neighbor.clicked
+            })
+            .collect()
+    }
+
+    /// Cycles covered, flagged and (if enabled) question marked.
+    /// Returns the new flagged state, revealed tiles can't be flagged.
+    pub fn toggle_flag(&mut self, index: usize) -> bool {
+        let tile = &mut self.board[index];
+        if tile.clicked {
+            return false;
+        }
+        if tile.flagged {
+            tile.flagged = false;
+            tile.questioned = self.question_marks;
+            self.flag_count -= 1;
+        } else if tile.questioned {
+            tile.questioned = false;
+        } else {
+            tile.flagged = true;
+            self.flag_count += 1;
+        }
+        tile.flagged
+    }
+
+    /// Flags a covered tile whatever mark it had, for mines found the hard way.
+    pub fn place_flag(&mut self, index: usize) {
+        let tile = &mut self.board[index];
+        if tile.clicked || tile.flagged {
+            return;
+        }
+        tile.flagged = true;
+        tile.questioned = false;
+        self.flag_count += 1;
+    }
+
+    pub fn tile_visual(&self, index: usize) -> TileVisual {
+        let tile = &self.board[index];
+        let lost = self.phase == GamePhase::Lost;
+        match (tile.clicked, tile.has_mine()) {
+            (true, true) => TileVisual::Exploded,
+            (true, false) => TileVisual::Revealed(self.adjacent_mines(index)),
+            (false, true) if lost && !tile.flagged => TileVisual::Mine,
+            (false, false) if lost && tile.flagged => TileVisual::WrongFlag,
+            _ if tile.flagged => TileVisual::Flagged,
+            _ if tile.questioned => TileVisual::Questioned,
+            _ => TileVisual::Covered,
+        }
+    }
+
+    fn end(&mut self, phase: GamePhase) {
+        if !self.phase.is_over() {
+            self.phase = phase;
+            self.ended_at = Some(Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_mask::BoardMask;
+    use crate::game_objects::minefield_from_layout;
+
+    #[test]
+    fn tile_at_edges_and_outside() {
+        // 4x2 tiles of 0.25, index is col + row * 4
+        let game_state = minefield_from_layout(4, 2, None, &[], 0);
+        assert_eq!(game_state.tile_at(Vec2::new(0.0, 0.0)), Some(0));
+        assert_eq!(game_state.tile_at(Vec2::new(0.2499, 0.2499)), Some(0));
+        assert_eq!(game_state.tile_at(Vec2::new(0.25, 0.0)), Some(1));
+        assert_eq!(game_state.tile_at(Vec2::new(0.25, 0.25)), Some(5));
+        assert_eq!(game_state.tile_at(Vec2::new(0.9999, 0.4999)), Some(7));
+        assert_eq!(game_state.tile_at(Vec2::new(-0.0001, 0.1)), None);
+        assert_eq!(game_state.tile_at(Vec2::new(0.1, -0.0001)), None);
+        assert_eq!(game_state.tile_at(Vec2::new(1.0, 0.1)), None);
+        assert_eq!(game_state.tile_at(Vec2::new(0.1, 0.5)), None);
+        assert_eq!(GameState::empty().tile_at(Vec2::ZERO), None);
+    }
+
+    #[test]
+    fn flood_stops_at_numbers_and_flags() {
+        // a mine in the middle of the right column, its numbers fence the zeros in
+        let mut game_state = minefield_from_layout(5, 3, None, &[(4, 1)], 0);
+        game_state.toggle_flag(10);
+        let mut opened = game_state.open(0);
+        opened.sort();
+        assert_eq!(opened, vec![0, 1, 2, 3, 5, 6, 7, 8, 11, 12, 13]);
+        assert!(game_state.board()[10].flagged && !game_state.board()[10].clicked);
+        assert!(!game_state.board()[4].clicked && !game_state.board()[14].clicked);
+
+        // deep enough that a recursive flood would run out of stack
+        let mut game_state = minefield_from_layout(500, 500, None, &[], 0);
+        assert_eq!(game_state.open(0).len(), 250_000);
+        assert_eq!(game_state.phase(), GamePhase::Won);
+    }
+
+    #[test]
+    fn visible_ranges_are_clipped_to_the_board() {
+        // 4x4 tiles of 0.25
+        let game_state = minefield_from_layout(4, 4, None, &[], 0);
+        let ranges = |min: Vec2, max: Vec2| game_state.visible_ranges(min, max);
+        assert_eq!(
+            ranges(Vec2::splat(0.3), Vec2::splat(0.6)),
+            vec![5..7, 9..11]
+        );
+        // whole rows run into each other
+        assert_eq!(ranges(Vec2::splat(-1.0), Vec2::splat(2.0)), vec![0..16]);
+        assert_eq!(ranges(Vec2::splat(0.9), Vec2::splat(5.0)), vec![15..16]);
+        assert!(ranges(Vec2::splat(-2.0), Vec2::splat(-1.0)).is_empty());
+        assert!(ranges(Vec2::splat(1.5), Vec2::splat(2.0)).is_empty());
+        assert!(ranges(Vec2::new(1.0, 0.0), Vec2::new(2.0, 1.0)).is_empty());
+
+        // rows that are all holes on screen are skipped
+        let mask = BoardMask::from_ascii("####\n#..#\n####\n####\n").unwrap();
+        let game_state = minefield_from_layout(4, 4, Some(&mask), &[], 0);
+        assert_eq!(
+            game_state.visible_ranges(Vec2::new(0.3, 0.3), Vec2::new(0.6, 0.9)),
+            vec![5..7, 11..13]
+        );
+    }
+}
@@ -0,0 +1,120 @@
+/// Colors for the numbers on revealed tiles, applied in the shader so they work with any theme.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Palette {
+    // whatever the theme's number sprites look like
+    Classic,
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+    // black numbers on white tiles, best with the number patterns on
+    HighContrast,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 5] = [
+        Palette::Classic,
+        Palette::Deuteranopia,
+        Palette::Protanopia,
+        Palette::Tritanopia,
+        Palette::HighContrast,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Palette::Classic => "CLASSIC",
+            Palette::Deuteranopia => "DEUTERANOPIA",
+            Palette::Protanopia => "PROTANOPIA",
+            Palette::Tritanopia => "TRITANOPIA",
+            Palette::HighContrast => "HIGH CONTRAST",
+        }
+    }
+
+    // sRGB colors of 1 to 8, None keeps the sprite colors
+    pub(crate) fn digit_colors(&self) -> Option<[[u8; 3]; 8]> {
+        match self {
+            Palette::Classic => None,
+            // blue/orange and light/dark instead of red/green
+            Palette::Deuteranopia => Some([
+                [0, 114, 178],
+                [230, 159, 0],
+                [120, 40, 140],
+                [0, 45, 110],
+                [150, 80, 0],
+                [86, 180, 233],
+                [0, 0, 0],
+                [128, 128, 128],
+            ]),
+            // like deuteranopia but without reds, they look almost black
+            Palette::Protanopia => Some([
+                [0, 90, 200],
+                [240, 200, 0],
+                [90, 40, 160],
+                [0, 30, 90],
+                [140, 110, 0],
+                [60, 170, 230],
+                [0, 0, 0],
+                [120, 120, 120],
+            ]),
+            // red/teal instead of blue/yellow
+            Palette::Tritanopia => Some([
+                [0, 130, 130],
+                [220, 40, 60],
+                [120, 0, 80],
+                [0, 70, 70],
+                [240, 120, 150],
+                [90, 200, 200],
+                [0, 0, 0],
+                [128, 128, 128],
+            ]),
+            Palette::HighContrast => Some([[0, 0, 0]; 8]),
+        }
+    }
+
+    // tile color behind the numbers, None keeps the sprite's
+    fn background(&self) -> Option<[u8; 3]> {
+        match self {
+            Palette::HighContrast => Some([255, 255, 255]),
+            _ => None,
+        }
+    }
+}
+
+/// Palette as the shader sees it, see `PaletteUniform` in shader.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PaletteUniform {
+    // linear colors, alpha 0 keeps the sprite color
+    digits: [[f32; 4]; 8],
+    background: [f32; 4],
+    // draws a different pattern behind each number
+    patterns: u32,
+    _padding: [u32; 3],
+}
+
+impl PaletteUniform {
+    pub fn new(palette: Palette, patterns: bool) -> PaletteUniform {
+        let digits = match palette.digit_colors() {
+            Some(colors) => colors.map(linear),
+            None => [[0.0; 4]; 8],
+        };
+        Self {
+            digits,
+            background: palette.background().map_or([0.0; 4], linear),
+            patterns: patterns as u32,
+            _padding: [0; 3],
+        }
+    }
+}
+
+// the atlas is sampled as sRGB, so the shader works in linear colors
+fn linear(color: [u8; 3]) -> [f32; 4] {
+    let [r, g, b] = color.map(|channel| {
+        let c = channel as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    [r, g, b, 1.0]
+}
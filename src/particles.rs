@@ -0,0 +1,188 @@
+use crate::instance::{Instance, InstanceRaw};
+use glam::{Vec2, Vec4};
+use rand::Rng;
+use std::collections::HashMap;
+
+/// Most particles alive at once, also the size of their instance layer.
+pub const MAX_PARTICLES: usize = 2048;
+// new particles allowed per frame, a chord that blows up several mines stays cheap
+const SPAWN_BUDGET: usize = 512;
+const Z_PARTICLES: f32 = 0.04;
+// world units per second squared, the board is one unit tall
+const GRAVITY: f32 = -2.5;
+
+struct Particle {
+    position: Vec2,
+    velocity: Vec2,
+    rotation: f32,
+    spin: f32,
+    size: f32,
+    color: Vec4,
+    sprite: &'static str,
+    // fraction of gravity it feels, confetti drifts and sparks fall
+    weight: f32,
+    age: f32,
+    lifetime: f32,
+}
+
+/// CPU simulated particles drawn as tinted quads through the normal instance pipeline.
+pub struct Particles {
+    particles: Vec<Particle>,
+    spawned_this_frame: usize,
+}
+
+impl Particles {
+    pub fn new() -> Particles {
+        Self {
+            particles: Vec::new(),
+            spawned_this_frame: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+
+    // drops the particle when this frame's budget or the pool is used up
+    fn spawn(&mut self, particle: Particle) {
+        if self.spawned_this_frame >= SPAWN_BUDGET || self.particles.len() >= MAX_PARTICLES {
+            return;
+        }
+        self.spawned_this_frame += 1;
+        self.particles.push(particle);
+    }
+
+    /// Sparks and smoke flying out of a mine, `size` is the tile size.
+    pub fn explosion(&mut self, center: Vec2, size: f32) {
+        let mut rng = rand::rng();
+        for _ in 0..96 {
+            let angle = rng.random_range(0.0..std::f32::consts::TAU);
+            let speed = rng.random_range(2.0..9.0) * size;
+            let heat = rng.random_range(0.0..1.0);
+            self.spawn(Particle {
+                position: center,
+                velocity: Vec2::from_angle(angle) * speed,
+                rotation: angle,
+                spin: rng.random_range(-6.0..6.0),
+                size: rng.random_range(0.08..0.25) * size,
+                color: Vec4::new(1.0, 0.3 + 0.6 * heat, 0.1 * heat, 1.0),
+                sprite: "Square",
+                weight: 0.3,
+                age: 0.0,
+                lifetime: rng.random_range(0.4..1.0),
+            });
+        }
+        for _ in 0..16 {
+            let angle = rng.random_range(0.0..std::f32::consts::TAU);
+            let grey = rng.random_range(0.2..0.5);
+            self.spawn(Particle {
+                position: center,
+                velocity: Vec2::from_angle(angle) * size,
+                rotation: 0.0,
+                spin: 0.0,
+                size: rng.random_range(0.5..1.2) * size,
+                color: Vec4::new(grey, grey, grey, 0.6),
+                sprite: "Circle",
+                weight: -0.05,
+                age: 0.0,
+                lifetime: rng.random_range(0.8..1.4),
+            });
+        }
+    }
+
+    /// Confetti raining down from the top edge of the view.
+    pub fn confetti(&mut self, view_min: Vec2, view_max: Vec2) {
+        let mut rng = rand::rng();
+        let height = view_max.y - view_min.y;
+        for _ in 0..SPAWN_BUDGET {
+            let hue = rng.random_range(0..6);
+            let color = [
+                Vec4::new(0.95, 0.3, 0.3, 1.0),
+                Vec4::new(0.95, 0.8, 0.2, 1.0),
+                Vec4::new(0.3, 0.85, 0.4, 1.0),
+                Vec4::new(0.3, 0.6, 0.95, 1.0),
+                Vec4::new(0.75, 0.4, 0.9, 1.0),
+                Vec4::new(0.95, 0.55, 0.8, 1.0),
+            ][hue];
+            self.spawn(Particle {
+                position: Vec2::new(
+                    rng.random_range(view_min.x..view_max.x),
+                    view_max.y + rng.random_range(0.0..0.3) * height,
+                ),
+                velocity: Vec2::new(rng.random_range(-0.2..0.2), rng.random_range(-0.4..0.0))
+                    * height,
+                rotation: rng.random_range(0.0..std::f32::consts::TAU),
+                spin: rng.random_range(-8.0..8.0),
+                size: rng.random_range(0.008..0.016) * height,
+                color,
+                sprite: "Square",
+                weight: 0.1,
+                age: 0.0,
+                lifetime: rng.random_range(2.5..4.0),
+            });
+        }
+    }
+
+    /// A few twinkles around a point, for treasure once the roguelike has some.
+    pub fn sparkle(&mut self, center: Vec2, size: f32) {
+        let mut rng = rand::rng();
+        for _ in 0..24 {
+            let angle = rng.random_range(0.0..std::f32::consts::TAU);
+            self.spawn(Particle {
+                position: center + Vec2::from_angle(angle) * rng.random_range(0.0..0.5) * size,
+                velocity: Vec2::new(0.0, rng.random_range(0.5..1.5) * size),
+                rotation: std::f32::consts::FRAC_PI_4,
+                spin: 0.0,
+                size: rng.random_range(0.05..0.15) * size,
+                color: Vec4::new(1.0, 0.95, 0.6, 1.0),
+                sprite: "Square",
+                weight: 0.0,
+                age: 0.0,
+                lifetime: rng.random_range(0.4..0.9),
+            });
+        }
+    }
+
+    pub fn update(&mut self, delta: f32) {
+        self.spawned_this_frame = 0;
+        for particle in self.particles.iter_mut() {
+            particle.velocity.y += GRAVITY * particle.weight * delta;
+            particle.position += particle.velocity * delta;
+            particle.rotation += particle.spin * delta;
+            particle.age += delta;
+        }
+        self.particles
+            .retain(|particle| particle.age < particle.lifetime);
+    }
+
+    pub fn instances(&self, sprites: &HashMap<String, Vec4>) -> Vec<InstanceRaw> {
+        self.particles
+            .iter()
+            .map(|particle| {
+                // fade out over the last half of its life
+                let fade = (2.0 * (1.0 - particle.age / particle.lifetime)).min(1.0);
+                Instance::to_raw(
+                    particle.position,
+                    particle.rotation,
+                    Vec2::splat(particle.size),
+                    Z_PARTICLES,
+                    *sprites
+                        .get(particle.sprite)
+                        .expect("no sprite for particle"),
+                    0,
+                )
+                .with_color(particle.color * Vec4::new(1.0, 1.0, 1.0, fade))
+            })
+            .collect()
+    }
+}
+
+impl Default for Particles {
+    fn default() -> Self {
+        Self::new()
+    }
+}
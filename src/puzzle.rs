@@ -0,0 +1,298 @@
+use crate::assets::locator;
+use crate::board_format::{self, BoardFormat, FormatError};
+use crate::solver::solve_revealed;
+use crate::GameState;
+use anyhow::{anyhow, bail, Context, Result};
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Where puzzle packs are looked for, the built in ones and the asset folder's.
+pub const PUZZLE_DIR: &str = "puzzles";
+/// Which puzzles were solved and how well.
+pub const PROGRESS_FILE: &str = "puzzle_progress.toml";
+/// Bumped whenever the progress format changes.
+pub const PROGRESS_VERSION: u32 = 1;
+/// How many packs the puzzles screen lists.
+pub const LISTED_PACKS: usize = 5;
+/// Puzzles per page of a pack.
+pub const LISTED_PUZZLES: usize = 5;
+// a safe tile that starts revealed, everything else is the grid board format
+const GIVEN: char = 'o';
+
+/// A toml file of puzzles, see `puzzles/starter.toml`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PuzzlePack {
+    pub title: String,
+    pub author: String,
+    pub puzzles: Vec<Puzzle>,
+}
+
+/// A board with some safe tiles already open, the rest can be worked out without guessing.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Puzzle {
+    pub title: String,
+    // the pack's author when it's missing
+    #[serde(default)]
+    pub author: Option<String>,
+    pub difficulty: String,
+    // a grid of '*' mines, '.' covered tiles, 'o' revealed ones and '_' holes
+    pub board: String,
+}
+
+impl PuzzlePack {
+    /// Reads a pack and checks every puzzle in it can be solved by logic alone.
+    pub fn load(path: &str) -> Result<PuzzlePack> {
+        let text = locator().read_to_string(path)?;
+        let pack: PuzzlePack =
+            toml::from_str(&text).with_context(|| format!("reading '{}'", path))?;
+        if pack.puzzles.is_empty() {
+            bail!("'{}' has no puzzles", path);
+        }
+        for puzzle in &pack.puzzles {
+            puzzle
+                .check()
+                .with_context(|| format!("'{}' in '{}'", puzzle.title, path))?;
+        }
+        Ok(pack)
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{} BY {}, {} PUZZLES",
+            self.title,
+            self.author,
+            self.puzzles.len()
+        )
+    }
+}
+
+impl Puzzle {
+    /// The board with its given tiles revealed. Errors point at the line and column
+    /// of the board text.
+    pub fn game_state(&self) -> Result<GameState, FormatError> {
+        let mut game_state =
+            board_format::parse(&self.board.replace(GIVEN, "."), BoardFormat::Grid)?;
+        // the same rows the grid format reads, top row first
+        let rows: Vec<&str> = self
+            .board
+            .lines()
+            .map(|line| line.trim_end())
+            .filter(|line| !line.is_empty())
+            .collect();
+        for (row_index, row) in rows.iter().enumerate() {
+            let board_row = (rows.len() - 1 - row_index) as f32;
+            for (col, cell) in row.chars().enumerate() {
+                if cell != GIVEN {
+                    continue;
+                }
+                if let Some(index) = game_state.tile_index(Vec2::new(col as f32, board_row)) {
+                    game_state.open(index);
+                }
+            }
+        }
+        Ok(game_state)
+    }
+
+    /// Whether the puzzle is fair: something to start from, something left to do and
+    /// no guesses on the way.
+    pub fn check(&self) -> Result<()> {
+        let game_state = self.game_state()?;
+        if !game_state.board().iter().any(|tile| tile.clicked) {
+            bail!("no tiles are revealed, mark some with '{}'", GIVEN);
+        }
+        if game_state.phase().is_over() {
+            bail!("every safe tile is already revealed");
+        }
+        let solution = solve_revealed(&game_state);
+        if solution.guesses > 0 {
+            bail!("it needs {} guesses", solution.guesses);
+        }
+        Ok(())
+    }
+}
+
+/// Pack files, built in ones first.
+pub fn pack_paths() -> Vec<String> {
+    locator()
+        .list(PUZZLE_DIR)
+        .into_iter()
+        .filter(|path| path.ends_with(".toml"))
+        .collect()
+}
+
+/// The puzzle being played, until it's solved or left.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PuzzleRun {
+    // index into pack_paths()
+    pub pack: usize,
+    pub index: usize,
+    pub title: String,
+    pub has_next: bool,
+    // mines clicked, they cost a mistake rather than the game
+    pub mistakes: u32,
+    // filled in when it's solved
+    pub progress: Option<PuzzleProgress>,
+}
+
+impl PuzzleRun {
+    pub fn new(pack: usize, index: usize, puzzles: &PuzzlePack) -> PuzzleRun {
+        Self {
+            pack,
+            index,
+            title: puzzles.puzzles[index].title.clone(),
+            has_next: index + 1 < puzzles.puzzles.len(),
+            mistakes: 0,
+            progress: None,
+        }
+    }
+
+    pub fn score(&self) -> String {
+        match self.mistakes {
+            0 => String::from("PERFECT, NO MISTAKES"),
+            1 => String::from("1 MISTAKE"),
+            mistakes => format!("{} MISTAKES", mistakes),
+        }
+    }
+}
+
+/// How one puzzle has gone so far.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PuzzleProgress {
+    pub solved: u32,
+    #[serde(default)]
+    pub best_mistakes: Option<u32>,
+}
+
+impl PuzzleProgress {
+    pub fn label(&self) -> String {
+        match self.best_mistakes {
+            None => String::from("UNSOLVED"),
+            Some(0) => String::from("PERFECT"),
+            Some(mistakes) => format!("BEST {} MISTAKES", mistakes),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProgressFile {
+    version: u32,
+    // "pack title/puzzle title", so reordering a pack keeps its progress
+    #[serde(default)]
+    puzzles: BTreeMap<String, PuzzleProgress>,
+}
+
+/// Progress of every puzzle played, rewritten whole since it stays small.
+pub struct ProgressDb {
+    path: PathBuf,
+    puzzles: BTreeMap<String, PuzzleProgress>,
+}
+
+impl ProgressDb {
+    /// Reads the progress at `path`, nothing solved if there's no file yet.
+    pub fn open(path: impl AsRef<Path>) -> Result<ProgressDb> {
+        let path = path.as_ref().to_path_buf();
+        let puzzles = match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                let file: ProgressFile = toml::from_str(&text)
+                    .with_context(|| format!("reading '{}'", path.display()))?;
+                if file.version > PROGRESS_VERSION {
+                    return Err(anyhow!(
+                        "'{}' is version {}, this game reads up to {}",
+                        path.display(),
+                        file.version,
+                        PROGRESS_VERSION
+                    ));
+                }
+                file.puzzles
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("reading '{}'", path.display())),
+        };
+        Ok(Self { path, puzzles })
+    }
+
+    pub fn get(&self, pack: &PuzzlePack, puzzle: &Puzzle) -> PuzzleProgress {
+        self.puzzles
+            .get(&key(pack, puzzle))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Counts a solve and keeps the fewest mistakes. Returns the progress after it.
+    pub fn record(
+        &mut self,
+        pack: &PuzzlePack,
+        puzzle: &Puzzle,
+        mistakes: u32,
+    ) -> Result<PuzzleProgress> {
+        let progress = self.puzzles.entry(key(pack, puzzle)).or_default();
+        progress.solved += 1;
+        progress.best_mistakes = Some(
+            progress
+                .best_mistakes
+                .map_or(mistakes, |best| best.min(mistakes)),
+        );
+        let progress = *progress;
+        let text = toml::to_string(&ProgressFile {
+            version: PROGRESS_VERSION,
+            puzzles: self.puzzles.clone(),
+        })?;
+        std::fs::write(&self.path, text)
+            .with_context(|| format!("writing '{}'", self.path.display()))?;
+        Ok(progress)
+    }
+}
+
+fn key(pack: &PuzzlePack, puzzle: &Puzzle) -> String {
+    format!("{}/{}", pack.title, puzzle.title)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn puzzle(board: &str) -> Puzzle {
+        Puzzle {
+            title: String::from("TEST"),
+            author: None,
+            difficulty: String::from("EASY"),
+            board: String::from(board),
+        }
+    }
+
+    #[test]
+    fn built_in_packs_are_fair() {
+        let paths = pack_paths();
+        assert!(!paths.is_empty());
+        for path in paths {
+            if let Err(e) = PuzzlePack::load(&path) {
+                panic!("{:?}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn givens_are_revealed_and_guesses_rejected() {
+        let fair = puzzle("o..\n.*.\n...\n");
+        let game_state = fair.game_state().unwrap();
+        // the top left, row 2 from the bottom
+        assert!(game_state.board()[6].clicked);
+        assert_eq!(
+            game_state
+                .board()
+                .iter()
+                .filter(|tile| tile.clicked)
+                .count(),
+            1
+        );
+        assert!(fair.check().is_err());
+
+        let error = puzzle("oo.\no*.\nx..\n").game_state().err().unwrap();
+        assert_eq!((error.line, error.column), (3, 1));
+
+        assert!(puzzle("ooo\no*o\nooo\n").check().is_err());
+        assert!(puzzle("o.o\no*o\nooo\n").check().is_ok());
+    }
+}
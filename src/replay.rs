@@ -0,0 +1,544 @@
+use crate::board_mask::BoardMask;
+use crate::config::{GameConfig, Rules};
+use crate::game_objects::{create_minefield, prepare_first_click};
+use crate::GameState;
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Bumped whenever the replay format changes.
+pub const REPLAY_VERSION: u32 = 2;
+const REPLAY_HEADER: &str = "minesweeper replay";
+const REPLAY_DIR: &str = "replays";
+const REPLAY_EXTENSION: &str = "replay";
+/// How many replays the replays screen lists, newest first.
+pub const LISTED_REPLAYS: usize = 5;
+// playback speeds, up and down step through them
+const SPEEDS: [f32; 7] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+const NORMAL_SPEED: usize = 2;
+const SEEK_STEP: Duration = Duration::from_secs(5);
+
+/// Something the player did to the board. There are no items yet, they get a
+/// variant here once there are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Reveal(usize),
+    // click on a revealed tile, opens the covered ones around it
+    Chord(usize),
+    Flag(usize),
+}
+
+impl Action {
+    fn code(&self) -> (char, usize) {
+        match self {
+            Action::Reveal(tile) => ('r', *tile),
+            Action::Chord(tile) => ('c', *tile),
+            Action::Flag(tile) => ('f', *tile),
+        }
+    }
+}
+
+/// An action and when it happened on the game clock, so pauses don't show up in replays.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Step {
+    pub time: Duration,
+    pub action: Action,
+}
+
+/// Everything needed to play a game back: the seed and settings it was dealt with
+/// and every action in order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub width: u32,
+    pub length: u32,
+    pub mines: u32,
+    pub shape: Option<String>,
+    pub question_marks: bool,
+    // where the first click moves mines to, version 1 replays are classic
+    pub rules: Rules,
+    pub steps: Vec<Step>,
+}
+
+impl Replay {
+    /// Starts recording a game dealt with these settings.
+    pub fn new(config: &GameConfig, seed: u64) -> Replay {
+        Self {
+            seed,
+            width: config.width,
+            length: config.length,
+            mines: config.mines,
+            shape: config.shape.clone(),
+            question_marks: config.question_marks,
+            rules: config.rules,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Adds an action that was just applied to `game_state`.
+    pub fn record(&mut self, game_state: &GameState, action: Action) {
+        // whole milliseconds, that's what the file keeps
+        let time = Duration::from_millis(game_state.elapsed().as_millis() as u64);
+        self.steps.push(Step { time, action });
+    }
+
+    /// Game clock time of the last action.
+    pub fn duration(&self) -> Duration {
+        self.steps.last().map_or(Duration::ZERO, |step| step.time)
+    }
+
+    /// The board before the first action.
+    pub fn deal(&self) -> Result<GameState> {
+        let config = GameConfig {
+            width: self.width,
+            length: self.length,
+            mines: self.mines,
+            shape: self.shape.clone(),
+            ..Default::default()
+        };
+        let mask = match &self.shape {
+            Some(path) => Some(BoardMask::load(path)?),
+            None => None,
+        };
+        let mut game_state = create_minefield(&config, mask.as_ref(), self.seed);
+        game_state.set_question_marks(self.question_marks);
+        Ok(game_state)
+    }
+
+    /// The game as it was after every action up to `time`.
+    pub fn simulate(&self, time: Duration) -> Result<GameState> {
+        let mut game_state = self.deal()?;
+        for step in self.steps.iter().take_while(|step| step.time <= time) {
+            if let Action::Reveal(tile) = step.action {
+                if tile < game_state.board().len() {
+                    prepare_first_click(&mut game_state, self.rules, tile);
+                }
+            }
+            apply(&mut game_state, step.action)?;
+        }
+        Ok(game_state)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{} {}\n", REPLAY_HEADER, REPLAY_VERSION);
+        text += &format!("seed {:016x}\n", self.seed);
+        text += &format!("board {} {} {}\n", self.width, self.length, self.mines);
+        if let Some(shape) = &self.shape {
+            text += &format!("shape {}\n", shape);
+        }
+        text += &format!("question_marks {}\n", self.question_marks);
+        text += &format!("rules {}\n", rules_name(self.rules));
+        for step in &self.steps {
+            let (code, tile) = step.action.code();
+            text += &format!("{} {} {}\n", step.time.as_millis(), code, tile);
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Replay> {
+        let mut lines = text.lines().enumerate();
+        let version = lines
+            .next()
+            .and_then(|(_, line)| line.strip_prefix(REPLAY_HEADER))
+            .context("not a replay")?;
+        let version: u32 = version.trim().parse().context("line 1: bad version")?;
+        if version > REPLAY_VERSION {
+            bail!(
+                "replay is version {}, this build reads up to {}",
+                version,
+                REPLAY_VERSION
+            );
+        }
+        let mut replay = Replay {
+            seed: 0,
+            width: 0,
+            length: 0,
+            mines: 0,
+            shape: None,
+            question_marks: false,
+            rules: Rules::Classic,
+            steps: Vec::new(),
+        };
+        let mut has_board = false;
+        for (number, line) in lines {
+            let line_error = || format!("line {}: '{}'", number + 1, line);
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {}
+                ["seed", seed] => {
+                    replay.seed = u64::from_str_radix(seed, 16).with_context(line_error)?
+                }
+                ["board", width, length, mines] => {
+                    replay.width = width.parse().with_context(line_error)?;
+                    replay.length = length.parse().with_context(line_error)?;
+                    replay.mines = mines.parse().with_context(line_error)?;
+                    has_board = true;
+                }
+                ["shape", ..] => {
+                    replay.shape = Some(line["shape".len()..].trim().to_string());
+                }
+                ["question_marks", value] => {
+                    replay.question_marks = value.parse().with_context(line_error)?;
+                }
+                ["rules", name] => {
+                    replay.rules = match *name {
+                        "classic" => Rules::Classic,
+                        "safe-opening" => Rules::SafeOpening,
+                        _ => bail!("{}: unknown rules", line_error()),
+                    };
+                }
+                [time, code, tile] => {
+                    let time = Duration::from_millis(time.parse().with_context(line_error)?);
+                    let tile: usize = tile.parse().with_context(line_error)?;
+                    let action = match *code {
+                        "r" => Action::Reveal(tile),
+                        "c" => Action::Chord(tile),
+                        "f" => Action::Flag(tile),
+                        _ => bail!("{}: unknown action", line_error()),
+                    };
+                    if replay.steps.last().is_some_and(|last| last.time > time) {
+                        bail!("{}: goes back in time", line_error());
+                    }
+                    replay.steps.push(Step { time, action });
+                }
+                _ => bail!("{}: can't read it", line_error()),
+            }
+        }
+        if !has_board {
+            bail!("replay has no board line");
+        }
+        Ok(replay)
+    }
+
+    /// Writes the replay to the replays folder, returns where it went.
+    pub fn save(&self) -> Result<PathBuf> {
+        std::fs::create_dir_all(REPLAY_DIR).context("creating the replays folder")?;
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = PathBuf::from(REPLAY_DIR)
+            .join(format!("{}-{:016x}.{}", stamp, self.seed, REPLAY_EXTENSION));
+        std::fs::write(&path, self.to_text())
+            .with_context(|| format!("writing '{}'", path.display()))?;
+        Ok(path)
+    }
+
+    pub fn load(path: &Path) -> Result<Replay> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading '{}'", path.display()))?;
+        Self::from_text(&text).with_context(|| format!("reading '{}'", path.display()))
+    }
+
+    /// One line for the menus, e.g. "16X16 40 MINES 01:23".
+    pub fn describe(&self) -> String {
+        let seconds = self.duration().as_secs();
+        format!(
+            "{}X{} {} MINES {:02}:{:02}",
+            self.width,
+            self.length,
+            self.mines,
+            seconds / 60,
+            seconds % 60
+        )
+    }
+}
+
+fn rules_name(rules: Rules) -> &'static str {
+    match rules {
+        Rules::Classic => "classic",
+        Rules::SafeOpening => "safe-opening",
+    }
+}
+
+/// Runs an action through the engine, the same way a click does. Returns the tiles
+/// it changed.
+pub fn apply(game_state: &mut GameState, action: Action) -> Result<Vec<usize>> {
+    let (_, tile) = action.code();
+    if tile >= game_state.board().len() {
+        bail!(
+            "action on tile {}, the board has {}",
+            tile,
+            game_state.board().len()
+        );
+    }
+    game_state.count_click();
+    let changed = match action {
+        Action::Reveal(tile) => game_state.open(tile),
+        Action::Chord(tile) => game_state
+            .chord_targets(tile)
+            .into_iter()
+            .flat_map(|neighbor| game_state.open(neighbor))
+            .collect(),
+        Action::Flag(tile) => {
+            game_state.toggle_flag(tile);
+            vec![tile]
+        }
+    };
+    Ok(changed)
+}
+
+/// Saved replays, newest first.
+pub fn saved_replays() -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(REPLAY_DIR) else {
+        return Vec::new();
+    };
+    let mut replays: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == REPLAY_EXTENSION))
+        .collect();
+    // file names start with the time they were saved
+    replays.sort();
+    replays.reverse();
+    replays
+}
+
+/// A replay being watched: its own clock, speed and pause.
+pub struct Playback {
+    pub replay: Replay,
+    time: Duration,
+    // index of the first step that hasn't been applied
+    next_step: usize,
+    speed: usize,
+    paused: bool,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Playback {
+        Self {
+            replay,
+            time: Duration::ZERO,
+            next_step: 0,
+            speed: NORMAL_SPEED,
+            paused: false,
+        }
+    }
+
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+
+    /// Pauses or plays. Playing from the end starts over, then the game is
+    /// rebuilt at the start and returned like rewind does.
+    pub fn toggle_pause(&mut self) -> Result<Option<GameState>> {
+        let from_the_end = self.paused && self.time >= self.replay.duration();
+        self.paused = !self.paused;
+        if from_the_end {
+            return self.restart_at(Duration::ZERO).map(Some);
+        }
+        Ok(None)
+    }
+
+    /// Steps through the speeds, positive is faster.
+    pub fn change_speed(&mut self, step: i32) {
+        self.speed = self
+            .speed
+            .saturating_add_signed(step as isize)
+            .min(SPEEDS.len() - 1);
+    }
+
+    /// Moves the clock along, returns the actions that came due.
+    pub fn advance(&mut self, delta: f32) -> Vec<Action> {
+        if self.paused {
+            return Vec::new();
+        }
+        self.time += Duration::from_secs_f32(delta * SPEEDS[self.speed]);
+        if self.time >= self.replay.duration() {
+            self.time = self.replay.duration();
+            self.paused = true;
+        }
+        let due: Vec<Action> = self.replay.steps[self.next_step..]
+            .iter()
+            .take_while(|step| step.time <= self.time)
+            .map(|step| step.action)
+            .collect();
+        self.next_step += due.len();
+        due
+    }
+
+    /// Jumps forwards (positive) or backwards by the seek step. Returns the game
+    /// at the new time, rebuilt from the start of the replay.
+    pub fn seek(&mut self, direction: i32) -> Result<GameState> {
+        self.time = if direction < 0 {
+            self.time.saturating_sub(SEEK_STEP)
+        } else {
+            (self.time + SEEK_STEP).min(self.replay.duration())
+        };
+        self.restart_at(self.time)
+    }
+
+    /// Jumps to a fraction of the way through, 0 is the start and 1 the end.
+    pub fn seek_to(&mut self, fraction: f32) -> Result<GameState> {
+        let time = self.replay.duration().mul_f32(fraction.clamp(0.0, 1.0));
+        self.restart_at(time)
+    }
+
+    // rebuilds the game at a time and carries on from there
+    fn restart_at(&mut self, time: Duration) -> Result<GameState> {
+        self.time = time;
+        self.next_step = self
+            .replay
+            .steps
+            .iter()
+            .take_while(|step| step.time <= time)
+            .count();
+        self.replay.simulate(time)
+    }
+
+    /// Back to the start, playing.
+    pub fn rewind(&mut self) -> Result<GameState> {
+        self.paused = false;
+        self.restart_at(Duration::ZERO)
+    }
+
+    pub fn status(&self) -> String {
+        format!(
+            "REPLAY {} / {}  X{}{}",
+            clock(self.time),
+            clock(self.replay.duration()),
+            SPEEDS[self.speed],
+            if self.paused { "  PAUSED" } else { "" }
+        )
+    }
+}
+
+fn clock(time: Duration) -> String {
+    format!(
+        "{:02}:{:02}.{}",
+        time.as_secs() / 60,
+        time.as_secs() % 60,
+        time.subsec_millis() / 100
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GamePhase;
+
+    // plays a game by always revealing the first safe covered tile
+    fn recorded_game() -> (Replay, GameState) {
+        let config = GameConfig {
+            rules: Rules::SafeOpening,
+            ..Default::default()
+        };
+        let mut game_state = create_minefield(&config, None, 11);
+        let mut replay = Replay::new(&config, 11);
+        // the first click is on a mine, the replay has to move it the same way
+        let first = (0..game_state.board().len())
+            .find(|index| game_state.board()[*index].has_mine())
+            .unwrap();
+        assert!(prepare_first_click(&mut game_state, config.rules, first));
+        apply(&mut game_state, Action::Reveal(first)).unwrap();
+        replay.record(&game_state, Action::Reveal(first));
+        let flag = (0..game_state.board().len())
+            .find(|index| game_state.board()[*index].has_mine())
+            .unwrap();
+        apply(&mut game_state, Action::Flag(flag)).unwrap();
+        replay.record(&game_state, Action::Flag(flag));
+        while game_state.phase() != GamePhase::Won {
+            let next = (0..game_state.board().len())
+                .find(|index| {
+                    let tile = &game_state.board()[*index];
+                    !tile.clicked && !tile.has_mine()
+                })
+                .unwrap();
+            apply(&mut game_state, Action::Reveal(next)).unwrap();
+            replay.record(&game_state, Action::Reveal(next));
+        }
+        (replay, game_state)
+    }
+
+    // what the window does with an action while a replay plays
+    fn perform(game_state: &mut GameState, rules: Rules, action: Action) {
+        if let Action::Reveal(tile) = action {
+            prepare_first_click(game_state, rules, tile);
+        }
+        apply(game_state, action).unwrap();
+    }
+
+    fn revealed(game_state: &GameState) -> Vec<(bool, bool)> {
+        game_state
+            .board()
+            .iter()
+            .map(|tile| (tile.clicked, tile.flagged))
+            .collect()
+    }
+
+    #[test]
+    fn text_round_trips() {
+        let (mut replay, _) = recorded_game();
+        replay.shape = Some(String::from("masks/heart.txt"));
+        assert_eq!(Replay::from_text(&replay.to_text()).unwrap(), replay);
+    }
+
+    #[test]
+    fn simulating_gives_back_the_same_game() {
+        let (replay, game_state) = recorded_game();
+        let simulated = replay.simulate(replay.duration()).unwrap();
+        assert_eq!(simulated.phase(), GamePhase::Won);
+        assert_eq!(revealed(&simulated), revealed(&game_state));
+        assert_eq!(simulated.clicks(), game_state.clicks());
+    }
+
+    #[test]
+    fn seeking_to_a_fraction_rebuilds_the_game_there() {
+        let (mut replay, _) = recorded_game();
+        for (second, step) in replay.steps.iter_mut().enumerate() {
+            step.time = Duration::from_secs(second as u64);
+        }
+        let mut playback = Playback::new(replay.clone());
+        let halfway = playback.seek_to(0.5).unwrap();
+        assert_eq!(playback.time(), replay.duration() / 2);
+        let expected = replay.simulate(replay.duration() / 2).unwrap();
+        assert_eq!(revealed(&halfway), revealed(&expected));
+        assert!(halfway.phase() != GamePhase::Won);
+
+        // the steps after that point are still to come
+        let rest = playback.advance(replay.duration().as_secs_f32());
+        let halfway_time = replay.duration() / 2;
+        let later = replay
+            .steps
+            .iter()
+            .filter(|step| step.time > halfway_time)
+            .count();
+        assert_eq!(rest.len(), later);
+        assert_eq!(playback.seek_to(2.0).unwrap().phase(), GamePhase::Won);
+    }
+
+    #[test]
+    fn playing_from_the_end_starts_over() {
+        let (mut replay, _) = recorded_game();
+        for (second, step) in replay.steps.iter_mut().enumerate() {
+            step.time = Duration::from_secs(second as u64);
+        }
+        let mut playback = Playback::new(replay.clone());
+        let mut game_state = replay.deal().unwrap();
+        for action in playback.advance(replay.duration().as_secs_f32() + 1.0) {
+            perform(&mut game_state, replay.rules, action);
+        }
+        assert_eq!(game_state.phase(), GamePhase::Won);
+
+        let mut game_state = playback.toggle_pause().unwrap().unwrap();
+        assert_eq!(playback.time(), Duration::ZERO);
+        let start = replay.simulate(Duration::ZERO).unwrap();
+        assert_eq!(revealed(&game_state), revealed(&start));
+        for action in playback.advance(2.5) {
+            perform(&mut game_state, replay.rules, action);
+        }
+        let expected = replay.simulate(playback.time()).unwrap();
+        assert_eq!(revealed(&game_state), revealed(&expected));
+        assert_eq!(game_state.clicks(), expected.clicks());
+        // pausing part way through keeps the game as it is
+        assert!(playback.toggle_pause().unwrap().is_none());
+    }
+
+    #[test]
+    fn bad_lines_say_where_they_are() {
+        let (replay, _) = recorded_game();
+        let text = replay.to_text().replacen(" r ", " x ", 1);
+        let error = format!("{:#}", Replay::from_text(&text).unwrap_err());
+        assert!(error.contains("line"), "{}", error);
+    }
+}
@@ -0,0 +1,401 @@
+use crate::config::{GameConfig, Rules};
+use crate::{CommonMineState, GamePhase, GameState, Mines, Tiles};
+use anyhow::{bail, Context, Result};
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Bumped whenever the save format changes, older saves get upgraded in `from_str`.
+pub const SAVE_VERSION: u32 = 1;
+/// Written when the window closes mid game, offered as CONTINUE on the next launch.
+pub const AUTOSAVE: &str = "autosave";
+/// Manual save slots, picked from the pause menu.
+pub const SLOTS: [&str; 3] = ["slot1", "slot2", "slot3"];
+const SAVE_DIR: &str = "saves";
+
+// one char per tile in SaveFile::rows
+const HOLE: char = ' ';
+const COVERED: char = '#';
+const FLAGGED: char = 'F';
+const QUESTIONED: char = '?';
+const REVEALED: char = 'o';
+
+// what a save file looks like, mines are kept apart from the rows so a save
+// doesn't give them away at a glance
+#[derive(Serialize, Deserialize)]
+struct SaveFile {
+    version: u32,
+    // hex, a u64 doesn't always fit in a toml integer
+    seed: String,
+    width: u32,
+    length: u32,
+    phase: GamePhase,
+    elapsed_nanos: u64,
+    clicks: u32,
+    question_marks: bool,
+    // one string per board row from the bottom up
+    rows: Vec<String>,
+    settings: SavedSettings,
+    #[serde(default)]
+    mines: Vec<SavedMine>,
+}
+
+// the settings the game was dealt with, so a restart deals the same kind of board
+#[derive(Clone, Serialize, Deserialize)]
+struct SavedSettings {
+    width: u32,
+    length: u32,
+    mines: u32,
+    shape: Option<String>,
+    rules: Rules,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind")]
+enum SavedMine {
+    Default {
+        // board index of the tile it's under
+        tile: usize,
+        index: u32,
+        active: bool,
+    },
+}
+
+/// A game read back from a save. The timer starts paused on the saved time.
+pub struct SavedGame {
+    pub game_state: GameState,
+    settings: SavedSettings,
+}
+
+impl SavedGame {
+    /// Puts back the settings the game was played with.
+    pub fn apply_settings(&self, config: &mut GameConfig) {
+        config.width = self.settings.width;
+        config.length = self.settings.length;
+        config.mines = self.settings.mines;
+        config.shape = self.settings.shape.clone();
+        config.rules = self.settings.rules;
+        config.question_marks = self.game_state.question_marks;
+        config.match_difficulty();
+    }
+}
+
+pub fn to_string(game_state: &GameState, config: &GameConfig) -> Result<String> {
+    let mut rows: Vec<String> = Vec::with_capacity(game_state.length as usize);
+    for row in 0..game_state.length {
+        let cells = &game_state.lookup
+            [(row * game_state.width) as usize..((row + 1) * game_state.width) as usize];
+        rows.push(
+            cells
+                .iter()
+                .map(|cell| match cell.map(|index| &game_state.board[index]) {
+                    None => HOLE,
+                    Some(tile) if tile.clicked => REVEALED,
+                    Some(tile) if tile.flagged => FLAGGED,
+                    Some(tile) if tile.questioned => QUESTIONED,
+                    Some(_) => COVERED,
+                })
+                .collect(),
+        );
+    }
+    let mines = game_state
+        .board
+        .iter()
+        .enumerate()
+        .filter_map(|(tile, state)| match state.mine.as_ref()? {
+            Mines::Default(mine) => Some(SavedMine::Default {
+                tile,
+                index: mine.mine_index,
+                active: mine.active,
+            }),
+        })
+        .collect();
+    let file = SaveFile {
+        version: SAVE_VERSION,
+        seed: format!("{:016x}", game_state.seed),
+        width: game_state.width,
+        length: game_state.length,
+        phase: game_state.phase,
+        elapsed_nanos: game_state.elapsed().as_nanos() as u64,
+        clicks: game_state.clicks,
+        question_marks: game_state.question_marks,
+        rows,
+        settings: SavedSettings {
+            width: config.width,
+            length: config.length,
+            mines: config.mines,
+            shape: config.shape.clone(),
+            rules: config.rules,
+        },
+        mines,
+    };
+    Ok(toml::to_string(&file)?)
+}
+
+pub fn from_str(text: &str) -> Result<SavedGame> {
+    let version: u32 = toml::from_str::<toml::Table>(text)?
+        .get("version")
+        .and_then(|version| version.as_integer())
+        .context("save has no version")?
+        .try_into()?;
+    if version > SAVE_VERSION {
+        bail!(
+            "save is version {}, this build reads up to {}",
+            version,
+            SAVE_VERSION
+        );
+    }
+    // older versions get upgraded here once there are any
+    let file: SaveFile = toml::from_str(text)?;
+    let seed = u64::from_str_radix(&file.seed, 16).context("seed isn't hex")?;
+    if file.rows.len() != file.length as usize {
+        bail!("{} rows for a board {} long", file.rows.len(), file.length);
+    }
+
+    let size = 1.0 / file.width.max(file.length) as f32;
+    let mut board: Vec<Tiles> = Vec::new();
+    for (row, cells) in file.rows.iter().enumerate() {
+        if cells.chars().count() != file.width as usize {
+            bail!("row {} isn't {} tiles wide", row + 1, file.width);
+        }
+        for (col, cell) in cells.chars().enumerate() {
+            if cell == HOLE {
+                continue;
+            }
+            let mut tile = Tiles::new(
+                Vec2::new(col as f32, row as f32),
+                Vec2::new(col as f32 * size, row as f32 * size),
+                None,
+                size,
+            );
+            match cell {
+                COVERED => {}
+                REVEALED => tile.clicked = true,
+                FLAGGED => tile.flagged = true,
+                QUESTIONED => tile.questioned = true,
+                _ => bail!("unknown tile '{}' in row {}", cell, row + 1),
+            }
+            board.push(tile);
+        }
+    }
+    for mine in file.mines {
+        let SavedMine::Default {
+            tile,
+            index,
+            active,
+        } = mine;
+        let Some(tile) = board.get_mut(tile) else {
+            bail!("mine under tile {}, the board has {}", tile, board.len());
+        };
+        if tile.has_mine() {
+            bail!("two mines under one tile");
+        }
+        tile.set_mine(Some(Mines::Default(CommonMineState {
+            active,
+            mine_index: index,
+        })));
+    }
+
+    let mut game_state = GameState::new(board, file.width, file.length, seed);
+    game_state.phase = file.phase;
+    game_state.clicks = file.clicks;
+    game_state.question_marks = file.question_marks;
+    game_state.flag_count = game_state.board.iter().filter(|tile| tile.flagged).count() as u32;
+    game_state.revealed_count = game_state
+        .board
+        .iter()
+        .filter(|tile| tile.clicked && !tile.has_mine())
+        .count() as u32;
+    // the timer picks up where it was, paused until the game is resumed
+    game_state.freeze_timer(Duration::from_nanos(file.elapsed_nanos));
+    Ok(SavedGame {
+        game_state,
+        settings: file.settings,
+    })
+}
+
+fn slot_path(slot: &str) -> PathBuf {
+    PathBuf::from(SAVE_DIR).join(format!("{}.toml", slot))
+}
+
+pub fn save(slot: &str, game_state: &GameState, config: &GameConfig) -> Result<()> {
+    let path = slot_path(slot);
+    std::fs::create_dir_all(SAVE_DIR).context("creating the saves folder")?;
+    // write next to it and swap it in, so a crash mid write keeps the old save
+    let temp = path.with_extension("toml.tmp");
+    std::fs::write(&temp, to_string(game_state, config)?)
+        .with_context(|| format!("writing '{}'", temp.display()))?;
+    std::fs::rename(&temp, &path).with_context(|| format!("writing '{}'", path.display()))?;
+    Ok(())
+}
+
+pub fn load(slot: &str) -> Result<SavedGame> {
+    let path = slot_path(slot);
+    let text =
+        std::fs::read_to_string(&path).with_context(|| format!("reading '{}'", path.display()))?;
+    from_str(&text).with_context(|| format!("reading '{}'", path.display()))
+}
+
+pub fn exists(slot: &str) -> bool {
+    slot_path(slot).is_file()
+}
+
+pub fn delete(slot: &str) -> Result<()> {
+    match std::fs::remove_file(slot_path(slot)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// One line about what's in a slot for the menus, e.g. "16X16 40 MINES 01:23 PLAYING".
+pub fn describe(slot: &str) -> String {
+    if !exists(slot) {
+        return String::from("EMPTY");
+    }
+    match load(slot) {
+        Ok(saved) => {
+            let seconds = saved.game_state.elapsed().as_secs();
+            format!(
+                "{}X{} {} MINES {:02}:{:02} {}",
+                saved.game_state.width,
+                saved.game_state.length,
+                saved.game_state.mine_count,
+                seconds / 60,
+                seconds % 60,
+                saved.game_state.phase.label()
+            )
+        }
+        Err(_) => String::from("UNREADABLE"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_mask::BoardMask;
+    use crate::game_objects::create_minefield;
+
+    // everything a save has to keep, as plain values
+    fn snapshot(game_state: &GameState) -> Vec<String> {
+        let mut lines = vec![
+            format!(
+                "{} {} {}",
+                game_state.width, game_state.length, game_state.seed
+            ),
+            format!("{:?} {}", game_state.phase, game_state.clicks),
+            format!(
+                "{} {} {}",
+                game_state.mine_count, game_state.flag_count, game_state.revealed_count
+            ),
+            format!("{:?} {}", game_state.elapsed(), game_state.question_marks),
+        ];
+        for tile in &game_state.board {
+            let mine = tile.mine.as_ref().map(|mine| match mine {
+                Mines::Default(state) => (state.mine_index, state.active),
+            });
+            lines.push(format!(
+                "{} {} {} {} {} {} {} {:?}",
+                tile.board_position,
+                tile.position,
+                tile.size,
+                tile.clicked,
+                tile.flagged,
+                tile.questioned,
+                tile.has_mine(),
+                mine
+            ));
+        }
+        lines
+    }
+
+    fn round_trip(game_state: &mut GameState) {
+        let config = GameConfig::default();
+        // a saved game comes back paused, compare against a paused one
+        game_state.set_paused(true);
+        let text = to_string(game_state, &config).unwrap();
+        let saved = from_str(&text).unwrap();
+        assert_eq!(snapshot(&saved.game_state), snapshot(game_state));
+        assert_eq!(to_string(&saved.game_state, &config).unwrap(), text);
+    }
+
+    fn first_safe(game_state: &GameState) -> usize {
+        (0..game_state.board.len())
+            .find(|index| !game_state.board[*index].has_mine())
+            .unwrap()
+    }
+
+    #[test]
+    fn fresh_board_round_trips() {
+        let mut game_state = create_minefield(&GameConfig::default(), None, u64::MAX);
+        round_trip(&mut game_state);
+    }
+
+    #[test]
+    fn game_in_progress_round_trips() {
+        let mut game_state = create_minefield(&GameConfig::default(), None, 42);
+        game_state.set_question_marks(true);
+        game_state.open(first_safe(&game_state));
+        let covered: Vec<usize> = (0..game_state.board.len())
+            .filter(|index| !game_state.board[*index].clicked)
+            .collect();
+        game_state.toggle_flag(covered[0]);
+        game_state.toggle_flag(covered[1]);
+        game_state.toggle_flag(covered[1]);
+        game_state.count_click();
+        round_trip(&mut game_state);
+    }
+
+    #[test]
+    fn lost_game_keeps_its_active_mine() {
+        let mut game_state = create_minefield(&GameConfig::default(), None, 3);
+        let mine = (0..game_state.board.len())
+            .find(|index| game_state.board[*index].has_mine())
+            .unwrap();
+        game_state.open(mine);
+        assert_eq!(game_state.phase(), GamePhase::Lost);
+        round_trip(&mut game_state);
+    }
+
+    #[test]
+    fn masked_board_round_trips() {
+        let mask = BoardMask::from_ascii("..##..\n.####.\n######\n.####.\n..##..").unwrap();
+        let mut game_state = create_minefield(&GameConfig::default(), Some(&mask), 9);
+        game_state.open(first_safe(&game_state));
+        round_trip(&mut game_state);
+    }
+
+    #[test]
+    fn settings_come_back() {
+        let config = GameConfig {
+            rules: Rules::SafeOpening,
+            shape: Some(String::from("masks/heart.txt")),
+            ..Default::default()
+        };
+        let game_state = create_minefield(&config, None, 1);
+        let saved = from_str(&to_string(&game_state, &config).unwrap()).unwrap();
+        let mut restored = GameConfig::default();
+        saved.apply_settings(&mut restored);
+        assert_eq!(restored, config);
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let game_state = create_minefield(&GameConfig::default(), None, 1);
+        let text = to_string(&game_state, &GameConfig::default())
+            .unwrap()
+            .replace(
+                &format!("version = {}", SAVE_VERSION),
+                &format!("version = {}", SAVE_VERSION + 1),
+            );
+        assert!(from_str(&text).is_err());
+    }
+
+    #[test]
+    fn broken_rows_are_refused() {
+        let game_state = create_minefield(&GameConfig::default(), None, 1);
+        let text = to_string(&game_state, &GameConfig::default()).unwrap();
+        assert!(from_str(&text.replacen('#', "X", 1)).is_err());
+        assert!(from_str(&text.replacen("###", "##", 1)).is_err());
+    }
+}
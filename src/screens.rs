@@ -0,0 +1,638 @@
+use crate::analysis::GameStats;
+use crate::board_format::{saved_boards, LISTED_BOARDS};
+use crate::config::{GameConfig, Setting};
+use crate::hud::ScreenRect;
+use crate::instance::{Instance, InstanceRaw};
+use crate::puzzle::{
+    pack_paths, ProgressDb, PuzzlePack, PuzzleRun, LISTED_PACKS, LISTED_PUZZLES, PROGRESS_FILE,
+};
+use crate::replay::{saved_replays, Replay, LISTED_REPLAYS};
+use crate::save::{self, AUTOSAVE, SLOTS};
+use crate::stats::{StatsDb, CATEGORIES, STATS_FILE};
+use crate::text::{text_instances, text_size};
+use crate::{GamePhase, GameState};
+use glam::{Vec2, Vec4};
+use std::collections::HashMap;
+use winit::keyboard::KeyCode;
+
+const TITLE_HEIGHT: f32 = 36.0;
+const ITEM_HEIGHT: f32 = 20.0;
+const ITEM_SPACING: f32 = 16.0;
+const LABEL_HEIGHT: f32 = 14.0;
+const Z_MENU_BACK: f32 = 0.6;
+const Z_MENU_TEXT: f32 = 0.7;
+const SLOT_LABELS: [&str; SLOTS.len()] = ["SLOT 1", "SLOT 2", "SLOT 3"];
+const REPLAY_LABELS: [&str; LISTED_REPLAYS] =
+    ["REPLAY 1", "REPLAY 2", "REPLAY 3", "REPLAY 4", "REPLAY 5"];
+const BOARD_LABELS: [&str; LISTED_BOARDS] = ["BOARD 1", "BOARD 2", "BOARD 3", "BOARD 4", "BOARD 5"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Screen {
+    MainMenu,
+    Playing,
+    Paused,
+    Settings,
+    GameOver,
+    SaveGame,
+    LoadGame,
+    Replays,
+    ImportBoard,
+    PuzzlePacks,
+    // index into puzzle::pack_paths() and the first puzzle listed
+    Puzzles(usize, usize),
+    PuzzleSolved,
+    // index into stats::CATEGORIES
+    Stats(usize),
+    // a replay playing on the board, it has its own keys instead of a menu
+    Replay,
+    // drawing a board, it has a toolbar instead of a menu
+    Editor,
+}
+
+impl Screen {
+    /// Whether clicks and keys go to the board rather than a menu.
+    pub fn is_playing(&self) -> bool {
+        *self == Screen::Playing
+    }
+
+    /// Whether the board is drawn, under menus too.
+    pub fn shows_board(&self) -> bool {
+        matches!(self, Screen::Playing | Screen::Replay | Screen::Editor)
+    }
+}
+
+/// Screens opened on top of each other, the bottom one is never popped.
+pub struct ScreenStack {
+    screens: Vec<Screen>,
+}
+
+impl ScreenStack {
+    pub fn new(root: Screen) -> ScreenStack {
+        Self {
+            screens: vec![root],
+        }
+    }
+
+    pub fn top(&self) -> Screen {
+        *self.screens.last().expect("screen stack is never empty")
+    }
+
+    pub fn push(&mut self, screen: Screen) {
+        self.screens.push(screen);
+    }
+
+    pub fn pop(&mut self) -> Option<Screen> {
+        if self.screens.len() > 1 {
+            self.screens.pop()
+        } else {
+            None
+        }
+    }
+
+    /// Drops every screen and starts over from a new root.
+    pub fn reset(&mut self, root: Screen) {
+        self.screens = vec![root];
+    }
+
+    pub fn contains(&self, screen: Screen) -> bool {
+        self.screens.contains(&screen)
+    }
+
+    /// Whether any screen in the stack draws the board.
+    pub fn shows_board(&self) -> bool {
+        self.screens.iter().any(|screen| screen.shows_board())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuAction {
+    StartGame,
+    Resume,
+    Restart,
+    RetrySeed,
+    OpenSettings,
+    // picks up the game that was open when the window closed
+    Continue,
+    OpenSave,
+    OpenLoad,
+    // index into save::SLOTS
+    SaveSlot(usize),
+    LoadSlot(usize),
+    // the game that just ended
+    ViewReplay,
+    SaveReplay,
+    OpenReplays,
+    // index into saved_replays()
+    WatchReplay(usize),
+    OpenImport,
+    OpenEditor,
+    OpenPuzzlePacks,
+    // pack and first puzzle of the page, see Screen::Puzzles
+    OpenPuzzles(usize, usize),
+    // pack and puzzle
+    PlayPuzzle(usize, usize),
+    // index into board_format::saved_boards()
+    ImportBoard(usize),
+    // writes the current layout to the boards folder
+    ExportBoard,
+    // takes back the action that ended the game
+    Undo,
+    // index into stats::CATEGORIES
+    ShowStats(usize),
+    Back,
+    MainMenu,
+    Quit,
+}
+
+enum MenuItem {
+    Button(&'static str, MenuAction),
+    Setting(Setting),
+    // plain text, skipped by keyboard navigation
+    Label(String),
+}
+
+// a button per save slot with what's in it underneath
+fn slot_items(action: fn(usize) -> MenuAction) -> Vec<MenuItem> {
+    let mut items = Vec::new();
+    for (index, slot) in SLOTS.iter().enumerate() {
+        items.push(MenuItem::Button(SLOT_LABELS[index], action(index)));
+        items.push(MenuItem::Label(save::describe(slot)));
+    }
+    items.push(MenuItem::Button("BACK", MenuAction::Back));
+    items
+}
+
+// one category of the stats database, NEXT steps to the next one
+fn stats_items(category: usize) -> Vec<MenuItem> {
+    let name = CATEGORIES[category % CATEGORIES.len()];
+    let mut items = vec![MenuItem::Label(String::from(name))];
+    match StatsDb::open(STATS_FILE) {
+        Ok(db) => items.extend(db.summary(name).lines().into_iter().map(MenuItem::Label)),
+        Err(_) => items.push(MenuItem::Label(String::from("UNREADABLE"))),
+    }
+    items.push(MenuItem::Button(
+        "NEXT",
+        MenuAction::ShowStats((category + 1) % CATEGORIES.len()),
+    ));
+    items.push(MenuItem::Button("BACK", MenuAction::Back));
+    items
+}
+
+// the newest saved replays with what's in them underneath
+fn replay_items() -> Vec<MenuItem> {
+    let mut items = Vec::new();
+    for (index, path) in saved_replays().iter().take(LISTED_REPLAYS).enumerate() {
+        let description = match Replay::load(path) {
+            Ok(replay) => replay.describe(),
+            Err(_) => String::from("UNREADABLE"),
+        };
+        items.push(MenuItem::Button(
+            REPLAY_LABELS[index],
+            MenuAction::WatchReplay(index),
+        ));
+        items.push(MenuItem::Label(description));
+    }
+    if items.is_empty() {
+        items.push(MenuItem::Label(String::from("NO SAVED REPLAYS")));
+    }
+    items.push(MenuItem::Button("BACK", MenuAction::Back));
+    items
+}
+
+// the first board files with their names underneath
+fn board_items() -> Vec<MenuItem> {
+    let mut items = Vec::new();
+    for (index, path) in saved_boards().iter().take(LISTED_BOARDS).enumerate() {
+        let name = path
+            .file_name()
+            .map_or(String::new(), |name| name.to_string_lossy().to_uppercase());
+        items.push(MenuItem::Button(
+            BOARD_LABELS[index],
+            MenuAction::ImportBoard(index),
+        ));
+        items.push(MenuItem::Label(name));
+    }
+    if items.is_empty() {
+        items.push(MenuItem::Label(String::from("NO BOARDS IN BOARDS/")));
+    }
+    items.push(MenuItem::Button("BACK", MenuAction::Back));
+    items
+}
+
+// the first puzzle packs with what's in them above
+fn pack_items() -> Vec<MenuItem> {
+    let mut items = Vec::new();
+    for (index, path) in pack_paths().iter().take(LISTED_PACKS).enumerate() {
+        match PuzzlePack::load(path) {
+            Ok(pack) => {
+                items.push(MenuItem::Label(pack.describe()));
+                items.push(MenuItem::Button("OPEN", MenuAction::OpenPuzzles(index, 0)));
+            }
+            Err(e) => {
+                eprintln!("Error loading puzzle pack '{}': {:?}", path, e);
+                items.push(MenuItem::Label(format!("{} IS BROKEN", path)));
+            }
+        }
+    }
+    if items.is_empty() {
+        items.push(MenuItem::Label(String::from("NO PUZZLE PACKS")));
+    }
+    items.push(MenuItem::Button("BACK", MenuAction::Back));
+    items
+}
+
+// a page of a pack's puzzles with how each has gone, NEXT PAGE steps through the rest
+fn puzzle_items(pack_index: usize, first: usize) -> Vec<MenuItem> {
+    let pack = match pack_paths()
+        .get(pack_index)
+        .map(|path| PuzzlePack::load(path))
+    {
+        Some(Ok(pack)) => pack,
+        _ => {
+            return vec![
+                MenuItem::Label(String::from("UNREADABLE")),
+                MenuItem::Button("BACK", MenuAction::Back),
+            ]
+        }
+    };
+    let progress = ProgressDb::open(PROGRESS_FILE).ok();
+    let mut items = Vec::new();
+    for (index, puzzle) in pack
+        .puzzles
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(LISTED_PUZZLES)
+    {
+        let done = progress
+            .as_ref()
+            .map_or(String::from("-"), |db| db.get(&pack, puzzle).label());
+        items.push(MenuItem::Label(format!(
+            "{}. {} - {} - {}",
+            index + 1,
+            puzzle.title,
+            puzzle.difficulty,
+            done
+        )));
+        items.push(MenuItem::Button(
+            "PLAY",
+            MenuAction::PlayPuzzle(pack_index, index),
+        ));
+    }
+    if first + LISTED_PUZZLES < pack.puzzles.len() {
+        items.push(MenuItem::Button(
+            "NEXT PAGE",
+            MenuAction::OpenPuzzles(pack_index, first + LISTED_PUZZLES),
+        ));
+    }
+    items.push(MenuItem::Button("BACK", MenuAction::Back));
+    items
+}
+
+// what a solved puzzle scored, and on to the next one
+fn solved_items(run: Option<&PuzzleRun>) -> Vec<MenuItem> {
+    let mut items = Vec::new();
+    if let Some(run) = run {
+        items.push(MenuItem::Label(run.title.to_uppercase()));
+        items.push(MenuItem::Label(run.score()));
+        if let Some(progress) = run.progress {
+            items.push(MenuItem::Label(format!(
+                "SOLVED {} TIMES, {}",
+                progress.solved,
+                progress.label()
+            )));
+        }
+        if run.has_next {
+            items.push(MenuItem::Button(
+                "NEXT PUZZLE",
+                MenuAction::PlayPuzzle(run.pack, run.index + 1),
+            ));
+        }
+        items.push(MenuItem::Button(
+            "TRY AGAIN",
+            MenuAction::PlayPuzzle(run.pack, run.index),
+        ));
+        items.push(MenuItem::Button(
+            "PUZZLES",
+            MenuAction::OpenPuzzles(run.pack, run.index / LISTED_PUZZLES * LISTED_PUZZLES),
+        ));
+    }
+    items.push(MenuItem::Button("MAIN MENU", MenuAction::MainMenu));
+    items
+}
+
+impl MenuItem {
+    fn height(&self) -> f32 {
+        match self {
+            MenuItem::Label(_) => LABEL_HEIGHT,
+            _ => ITEM_HEIGHT,
+        }
+    }
+}
+
+/// What the menus need to know about the game besides the screen.
+pub struct MenuContext<'a> {
+    pub game_state: &'a GameState,
+    // worked out once when the game ended, the board analysis isn't free
+    pub results: Option<&'a GameStats>,
+    // the game that just ended was recorded
+    pub has_replay: bool,
+    // its last action can still be taken back
+    pub can_undo: bool,
+    // it was the fastest win of its category
+    pub personal_best: bool,
+    // the puzzle being played, if it is one
+    pub puzzle: Option<&'a PuzzleRun>,
+}
+
+/// A column of buttons and settings, driven by either the mouse or the keyboard.
+pub struct Menu {
+    title: String,
+    items: Vec<MenuItem>,
+    selected: usize,
+    // filled in by `instances`, used for mouse hit testing
+    item_rects: Vec<ScreenRect>,
+    back_action: Option<MenuAction>,
+}
+
+impl Menu {
+    /// The menu shown for a screen, `Playing` and `Replay` have none.
+    pub fn for_screen(screen: Screen, context: &MenuContext) -> Option<Menu> {
+        let (title, items, back_action) = match screen {
+            Screen::Playing | Screen::Replay | Screen::Editor => return None,
+            Screen::MainMenu => {
+                let mut items = Vec::new();
+                if save::exists(AUTOSAVE) {
+                    items.push(MenuItem::Button("CONTINUE", MenuAction::Continue));
+                }
+                items.extend([
+                    MenuItem::Button("START", MenuAction::StartGame),
+                    MenuItem::Button("LOAD", MenuAction::OpenLoad),
+                    MenuItem::Button("REPLAYS", MenuAction::OpenReplays),
+                    MenuItem::Button("IMPORT", MenuAction::OpenImport),
+                    MenuItem::Button("PUZZLES", MenuAction::OpenPuzzlePacks),
+                    MenuItem::Button("EDITOR", MenuAction::OpenEditor),
+                    MenuItem::Button("STATS", MenuAction::ShowStats(0)),
+                    MenuItem::Button("SETTINGS", MenuAction::OpenSettings),
+                    MenuItem::Button("QUIT", MenuAction::Quit),
+                ]);
+                ("MINESWEEPER", items, None)
+            }
+            Screen::Paused => (
+                "PAUSED",
+                vec![
+                    MenuItem::Button("RESUME", MenuAction::Resume),
+                    MenuItem::Button("RESTART", MenuAction::Restart),
+                    MenuItem::Button("SAVE", MenuAction::OpenSave),
+                    MenuItem::Button("EXPORT", MenuAction::ExportBoard),
+                    MenuItem::Button("SETTINGS", MenuAction::OpenSettings),
+                    MenuItem::Button("MAIN MENU", MenuAction::MainMenu),
+                ],
+                Some(MenuAction::Resume),
+            ),
+            Screen::Settings => (
+                "SETTINGS",
+                vec![
+                    MenuItem::Setting(Setting::Difficulty),
+                    MenuItem::Setting(Setting::Width),
+                    MenuItem::Setting(Setting::Length),
+                    MenuItem::Setting(Setting::Mines),
+                    MenuItem::Setting(Setting::Shape),
+                    MenuItem::Setting(Setting::Rules),
+                    MenuItem::Setting(Setting::QuestionMarks),
+                    MenuItem::Setting(Setting::Animations),
+                    MenuItem::Setting(Setting::Theme),
+                    MenuItem::Setting(Setting::Palette),
+                    MenuItem::Setting(Setting::NumberPatterns),
+                    MenuItem::Setting(Setting::Undo),
+                    MenuItem::Button("BACK", MenuAction::Back),
+                ],
+                Some(MenuAction::Back),
+            ),
+            Screen::GameOver => {
+                let stats = match context.results {
+                    Some(stats) => stats.clone(),
+                    None => GameStats::from_game(context.game_state),
+                };
+                return Some(Self::results(&stats, context));
+            }
+            Screen::SaveGame => (
+                "SAVE",
+                slot_items(MenuAction::SaveSlot),
+                Some(MenuAction::Back),
+            ),
+            Screen::LoadGame => (
+                "LOAD",
+                slot_items(MenuAction::LoadSlot),
+                Some(MenuAction::Back),
+            ),
+            Screen::Replays => ("REPLAYS", replay_items(), Some(MenuAction::Back)),
+            Screen::ImportBoard => ("IMPORT", board_items(), Some(MenuAction::Back)),
+            Screen::PuzzlePacks => ("PUZZLES", pack_items(), Some(MenuAction::Back)),
+            Screen::Puzzles(pack, first) => {
+                ("PUZZLES", puzzle_items(pack, first), Some(MenuAction::Back))
+            }
+            Screen::PuzzleSolved => ("SOLVED", solved_items(context.puzzle), None),
+            Screen::Stats(category) => ("STATS", stats_items(category), Some(MenuAction::Back)),
+        };
+        Some(Self::new(String::from(title), items, back_action))
+    }
+
+    /// Modal shown when a game ends.
+    pub fn results(stats: &GameStats, context: &MenuContext) -> Menu {
+        let title = match stats.outcome {
+            GamePhase::Won => "YOU WON",
+            _ => "YOU LOST",
+        };
+        let mut items: Vec<MenuItem> = stats.lines().into_iter().map(MenuItem::Label).collect();
+        if context.personal_best {
+            items.insert(0, MenuItem::Label(String::from("NEW PERSONAL BEST!")));
+        }
+        // a misclick can be taken back, the game carries on
+        if context.can_undo {
+            items.push(MenuItem::Button("UNDO", MenuAction::Undo));
+        }
+        if context.has_replay {
+            items.push(MenuItem::Button("VIEW REPLAY", MenuAction::ViewReplay));
+            items.push(MenuItem::Button("SAVE REPLAY", MenuAction::SaveReplay));
+        }
+        items.push(MenuItem::Button("RETRY SEED", MenuAction::RetrySeed));
+        items.push(MenuItem::Button("NEW GAME", MenuAction::Restart));
+        items.push(MenuItem::Button("MAIN MENU", MenuAction::MainMenu));
+        Self::new(String::from(title), items, None)
+    }
+
+    fn new(title: String, items: Vec<MenuItem>, back_action: Option<MenuAction>) -> Menu {
+        let selected = items
+            .iter()
+            .position(|item| !matches!(item, MenuItem::Label(_)))
+            .unwrap_or(0);
+        Self {
+            title,
+            items,
+            selected,
+            item_rects: Vec::new(),
+            back_action,
+        }
+    }
+
+    fn step_selection(&mut self, step: usize) {
+        for _ in 0..self.items.len() {
+            self.selected = (self.selected + step) % self.items.len();
+            if !matches!(self.items[self.selected], MenuItem::Label(_)) {
+                break;
+            }
+        }
+    }
+
+    /// Returns the action to run, and whether the menu needs redrawing.
+    pub fn key(&mut self, key: KeyCode, config: &mut GameConfig) -> (Option<MenuAction>, bool) {
+        match key {
+            KeyCode::ArrowUp | KeyCode::KeyW => {
+                self.step_selection(self.items.len() - 1);
+                (None, true)
+            }
+            KeyCode::ArrowDown | KeyCode::KeyS => {
+                self.step_selection(1);
+                (None, true)
+            }
+            KeyCode::ArrowLeft | KeyCode::KeyA => (None, self.adjust_selected(config, -1)),
+            KeyCode::ArrowRight | KeyCode::KeyD => (None, self.adjust_selected(config, 1)),
+            KeyCode::Enter | KeyCode::Space => match self.items[self.selected] {
+                MenuItem::Button(_, action) => (Some(action), false),
+                MenuItem::Setting(_) => (None, self.adjust_selected(config, 1)),
+                MenuItem::Label(_) => (None, false),
+            },
+            KeyCode::Escape => (self.back_action, false),
+            _ => (None, false),
+        }
+    }
+
+    /// Hovering selects an item, returns true if the selection changed.
+    pub fn hover(&mut self, screen_position: Vec2) -> bool {
+        match self.item_at(screen_position) {
+            Some(index)
+                if index != self.selected && !matches!(self.items[index], MenuItem::Label(_)) =>
+            {
+                self.selected = index;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Left clicks step settings forwards and right clicks step them backwards.
+    pub fn click(
+        &mut self,
+        screen_position: Vec2,
+        forwards: bool,
+        config: &mut GameConfig,
+    ) -> (Option<MenuAction>, bool) {
+        let Some(index) = self.item_at(screen_position) else {
+            return (None, false);
+        };
+        if matches!(self.items[index], MenuItem::Label(_)) {
+            return (None, false);
+        }
+        self.selected = index;
+        match self.items[index] {
+            MenuItem::Button(_, action) if forwards => (Some(action), true),
+            MenuItem::Button(..) => (None, true),
+            MenuItem::Setting(_) => (
+                None,
+                self.adjust_selected(config, if forwards { 1 } else { -1 }),
+            ),
+            MenuItem::Label(_) => (None, false),
+        }
+    }
+
+    fn adjust_selected(&mut self, config: &mut GameConfig, step: i32) -> bool {
+        match self.items[self.selected] {
+            MenuItem::Setting(setting) => {
+                config.adjust(setting, step);
+                true
+            }
+            MenuItem::Button(..) | MenuItem::Label(_) => false,
+        }
+    }
+
+    fn item_at(&self, screen_position: Vec2) -> Option<usize> {
+        self.item_rects
+            .iter()
+            .position(|rect| rect.contains(screen_position))
+    }
+
+    pub fn instances(
+        &mut self,
+        config: &GameConfig,
+        screen_size: winit::dpi::PhysicalSize<u32>,
+        sprites: &HashMap<String, Vec4>,
+    ) -> Vec<InstanceRaw> {
+        let screen = Vec2::new(screen_size.width as f32, screen_size.height as f32);
+        let labels: Vec<String> = self
+            .items
+            .iter()
+            .map(|item| match item {
+                MenuItem::Button(label, _) => String::from(*label),
+                MenuItem::Setting(setting) => config.setting_label(*setting),
+                MenuItem::Label(text) => text.clone(),
+            })
+            .collect();
+
+        let block_height = TITLE_HEIGHT
+            + ITEM_SPACING * 2.0
+            + self
+                .items
+                .iter()
+                .map(|item| item.height() + ITEM_SPACING)
+                .sum::<f32>();
+        let block_width = labels
+            .iter()
+            .zip(self.items.iter())
+            .map(|(label, item)| text_size(&format!("> {}", label), item.height()).x)
+            .fold(text_size(&self.title, TITLE_HEIGHT).x, f32::max);
+        let panel = ScreenRect::from_center(
+            screen / 2.0,
+            Vec2::new(block_width, block_height) + Vec2::splat(ITEM_SPACING * 2.0),
+        );
+
+        let mut instances = vec![Instance::to_raw(
+            panel.center(),
+            0.0,
+            panel.size(),
+            Z_MENU_BACK,
+            *sprites.get("Tiles").expect("No Tiles :c"),
+            0,
+        )];
+        let mut top = panel.max.y - ITEM_SPACING;
+        let title_width = text_size(&self.title, TITLE_HEIGHT).x;
+        instances.extend(text_instances(
+            &self.title,
+            Vec2::new(screen.x / 2.0 - title_width / 2.0, top - TITLE_HEIGHT),
+            TITLE_HEIGHT,
+            Z_MENU_TEXT,
+            sprites,
+        ));
+        top -= TITLE_HEIGHT + ITEM_SPACING * 2.0;
+
+        self.item_rects.clear();
+        for (index, (label, item)) in labels.iter().zip(self.items.iter()).enumerate() {
+            let height = item.height();
+            let label = if index == self.selected {
+                format!("> {}", label)
+            } else {
+                label.clone()
+            };
+            let size = text_size(&label, height);
+            let origin = Vec2::new(screen.x / 2.0 - size.x / 2.0, top - height);
+            instances.extend(text_instances(&label, origin, height, Z_MENU_TEXT, sprites));
+            // the whole row is clickable, not just the glyphs
+            self.item_rects.push(ScreenRect {
+                min: Vec2::new(panel.min.x, origin.y - ITEM_SPACING / 2.0),
+                max: Vec2::new(panel.max.x, origin.y + height + ITEM_SPACING / 2.0),
+            });
+            top -= height + ITEM_SPACING;
+        }
+        instances
+    }
+}
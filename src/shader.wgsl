@@ -8,6 +8,8 @@ struct InstanceInput {
     @location(8) model_matrix_3: vec4<f32>,
     @location(9) tex_coord_bounds: vec4<f32>,
     @location(10) texture_index: u32,
+    @location(11) color: vec4<f32>,
+    @location(12) digit: u32,
 };
 
 
@@ -20,6 +22,10 @@ struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
     @location(1) @interpolate(flat) texture_index: u32,
+    @location(2) color: vec4<f32>,
+    @location(3) @interpolate(flat) digit: u32,
+    // 0 to 1 across the quad, for the number patterns
+    @location(4) local: vec2<f32>,
 };
 
 
@@ -47,6 +53,9 @@ fn vs_main(
     out.tex_coords = instance.tex_coord_bounds.xy + model.tex_coords * (instance.tex_coord_bounds.zw - instance.tex_coord_bounds.xy);
     out.clip_position = camera.projection *  model_matrix * vec4<f32>(model.position.xy, model.position.z, 1.0); 
     out.texture_index = instance.texture_index;
+    out.color = instance.color;
+    out.digit = instance.digit;
+    out.local = model.tex_coords;
     return out;
 }
 
@@ -58,7 +67,58 @@ var t_diffuse: texture_2d_array<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
 
+@group(2) @binding(0)
+var<uniform> palette: PaletteUniform;
+struct PaletteUniform {
+    // alpha 0 keeps the sprite color
+    digits: array<vec4<f32>, 8>,
+    background: vec4<f32>,
+    patterns: u32,
+};
+
+// 1 where a number's pattern is drawn, every number gets a different one
+fn pattern(digit: u32, local: vec2<f32>) -> f32 {
+    let cells = local * 6.0;
+    switch digit {
+        case 1u: { return step(0.5, fract(cells.y)); }
+        case 2u: { return step(0.5, fract(cells.x)); }
+        case 3u: { return step(0.5, fract(cells.x + cells.y)); }
+        case 4u: { return step(0.5, fract(cells.x - cells.y)); }
+        case 5u: { return step(0.5, fract((floor(cells.x) + floor(cells.y)) * 0.5)); }
+        case 6u: { return 1.0 - step(0.25, length(fract(cells) - 0.5)); }
+        case 7u: { return max(step(0.75, fract(cells.x)), step(0.75, fract(cells.y))); }
+        default: { return step(0.5, fract(length(local - 0.5) * 8.0)); }
+    }
+}
+
+// number sprites are a colored digit on a light tile, so anything far from white is ink
+fn number_tile(sampled: vec4<f32>, digit: u32, local: vec2<f32>) -> vec4<f32> {
+    let ink = smoothstep(0.05, 0.35, 1.0 - min(sampled.r, min(sampled.g, sampled.b)));
+    var background = sampled.rgb;
+    if palette.background.a > 0.0 {
+        background = palette.background.rgb;
+    }
+    if palette.patterns != 0u {
+        background = background * (1.0 - 0.25 * pattern(digit, local));
+    }
+    var ink_color = sampled.rgb;
+    let digit_color = palette.digits[digit - 1u];
+    if digit_color.a > 0.0 {
+        ink_color = digit_color.rgb;
+    }
+    return vec4<f32>(mix(background, ink_color, ink), sampled.a);
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords, in.texture_index);
+    var sampled = textureSample(t_diffuse, s_diffuse, in.tex_coords, in.texture_index);
+    if in.digit > 0u && in.digit <= 8u {
+        sampled = number_tile(sampled, in.digit, in.local);
+    }
+    let color = sampled * in.color;
+    // fully see through pixels would still write depth and hide what's behind them
+    if color.a < 0.01 {
+        discard;
+    }
+    return color;
 }
@@ -0,0 +1,355 @@
+use crate::GameState;
+
+/// How much a board makes the player think, from how the solver got through it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rating {
+    // every tile follows from a single number
+    Easy,
+    // needs two numbers at once somewhere, no guesses
+    Medium,
+    // a guess or two
+    Hard,
+    // more luck than logic
+    Luck,
+}
+
+impl Rating {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Rating::Easy => "EASY",
+            Rating::Medium => "MEDIUM",
+            Rating::Hard => "HARD",
+            Rating::Luck => "LUCK",
+        }
+    }
+}
+
+/// How the solver got through a board.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Solution {
+    // times it ran out of logic and had to pick a tile, the first click isn't one
+    pub guesses: u32,
+    // times it needed the subset rule, two numbers compared
+    pub subset_steps: u32,
+    // tiles it opened or flagged by logic
+    pub deductions: u32,
+}
+
+impl Solution {
+    pub fn rating(&self) -> Rating {
+        match (self.guesses, self.subset_steps) {
+            (0, 0) => Rating::Easy,
+            (0, _) => Rating::Medium,
+            (1..=2, _) => Rating::Hard,
+            _ => Rating::Luck,
+        }
+    }
+}
+
+// what the solver knows about a tile
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Known {
+    Covered,
+    Open,
+    Mine,
+}
+
+/// Plays the mine layout of a game from scratch with the single number and subset
+/// rules, starting on `first_click` (or the first zero, like the safe opening rule).
+/// When logic runs out it counts a guess and opens a safe tile, so it always finishes.
+pub fn solve(game_state: &GameState, first_click: Option<usize>) -> Solution {
+    let mut solver = Solver::new(game_state);
+    let Some(start) = first_click.or_else(|| solver.first_zero()) else {
+        return solver.solution;
+    };
+    solver.open(start);
+    solver.finish()
+}
+
+/// Plays on from the tiles already revealed in `game_state`, for puzzles that start
+/// part way through. With nothing revealed the first tile it opens is a guess.
+pub fn solve_revealed(game_state: &GameState) -> Solution {
+    let mut solver = Solver::new(game_state);
+    for (index, tile) in game_state.board().iter().enumerate() {
+        if tile.clicked && !tile.has_mine() {
+            solver.open(index);
+        }
+    }
+    solver.finish()
+}
+
+struct Solver<'a> {
+    game_state: &'a GameState,
+    known: Vec<Known>,
+    numbers: Vec<u8>,
+    // open numbers that may still tell something on their own
+    pending: Vec<usize>,
+    // open numbers that changed since the subset rule last looked at them
+    dirty: Vec<usize>,
+    is_dirty: Vec<bool>,
+    // open numbers in the order they were opened, guesses go next to the newest
+    edge: Vec<usize>,
+    safe_left: usize,
+    // no safe covered tile is before this, for guesses away from the edge
+    guess_cursor: usize,
+    solution: Solution,
+}
+
+impl<'a> Solver<'a> {
+    fn new(game_state: &'a GameState) -> Solver<'a> {
+        let board = game_state.board();
+        Self {
+            game_state,
+            known: vec![Known::Covered; board.len()],
+            numbers: (0..board.len())
+                .map(|index| game_state.adjacent_mines(index))
+                .collect(),
+            pending: Vec::new(),
+            dirty: Vec::new(),
+            is_dirty: vec![false; board.len()],
+            edge: Vec::new(),
+            safe_left: board.iter().filter(|tile| !tile.has_mine()).count(),
+            guess_cursor: 0,
+            solution: Solution::default(),
+        }
+    }
+
+    // logic until it runs out, then a guess, until every safe tile is open
+    fn finish(mut self) -> Solution {
+        while self.safe_left > 0 {
+            if self.single_steps() || self.subset_step() {
+                continue;
+            }
+            self.solution.guesses += 1;
+            let guess = self.next_safe();
+            self.open(guess);
+        }
+        self.solution
+    }
+
+    fn neighbors(&self, index: usize) -> Vec<usize> {
+        self.game_state
+            .neighbors(self.game_state.board()[index].board_position())
+    }
+
+    fn first_zero(&self) -> Option<usize> {
+        let board = self.game_state.board();
+        (0..board.len())
+            .find(|index| !board[*index].has_mine() && self.numbers[*index] == 0)
+            .or_else(|| (0..board.len()).find(|index| !board[*index].has_mine()))
+    }
+
+    // the solver only opens tiles it knows are safe, so this never hits a mine
+    fn open(&mut self, index: usize) {
+        let mut stack = vec![index];
+        while let Some(index) = stack.pop() {
+            if self.known[index] != Known::Covered {
+                continue;
+            }
+            self.known[index] = Known::Open;
+            self.safe_left -= 1;
+            self.changed(index);
+            if self.numbers[index] == 0 {
+                stack.extend(self.neighbors(index));
+            } else {
+                self.pending.push(index);
+                self.edge.push(index);
+            }
+        }
+    }
+
+    fn flag(&mut self, index: usize) {
+        if self.known[index] != Known::Covered {
+            return;
+        }
+        self.solution.deductions += 1;
+        self.known[index] = Known::Mine;
+        self.changed(index);
+    }
+
+    // the open numbers around a tile that just changed may be done now
+    fn changed(&mut self, index: usize) {
+        for neighbor in self.neighbors(index).into_iter().chain([index]) {
+            if self.known[neighbor] != Known::Open || self.numbers[neighbor] == 0 {
+                continue;
+            }
+            if neighbor != index {
+                self.pending.push(neighbor);
+            }
+            if !self.is_dirty[neighbor] {
+                self.is_dirty[neighbor] = true;
+                self.dirty.push(neighbor);
+            }
+        }
+    }
+
+    // covered neighbors and how many of them are mines
+    fn constraint(&self, index: usize) -> (Vec<usize>, usize) {
+        let mut covered = Vec::new();
+        let mut mines = self.numbers[index] as usize;
+        for neighbor in self.neighbors(index) {
+            match self.known[neighbor] {
+                Known::Covered => covered.push(neighbor),
+                Known::Mine => mines -= 1,
+                Known::Open => {}
+            }
+        }
+        (covered, mines)
+    }
+
+    /// Numbers whose mines are all flagged or whose covered tiles are all mines.
+    /// Returns true if it found anything.
+    fn single_steps(&mut self) -> bool {
+        let mut progress = false;
+        while let Some(index) = self.pending.pop() {
+            let (covered, mines) = self.constraint(index);
+            if covered.is_empty() {
+                continue;
+            }
+            if mines == 0 {
+                for tile in covered {
+                    self.solution.deductions += 1;
+                    self.open(tile);
+                }
+                progress = true;
+            } else if mines == covered.len() {
+                for tile in covered {
+                    self.flag(tile);
+                }
+                progress = true;
+            }
+        }
+        progress
+    }
+
+    /// Two numbers where one's covered tiles are inside the other's: the tiles only
+    /// the bigger one sees hold the difference in mines. Only pairs with a number
+    /// that changed since the last look are tried. Returns true if it helped.
+    fn subset_step(&mut self) -> bool {
+        while let Some(changed) = self.dirty.pop() {
+            self.is_dirty[changed] = false;
+            let (tiles, mines) = self.constraint(changed);
+            if tiles.is_empty() {
+                continue;
+            }
+            // numbers that share a tile are at most two apart
+            let position = self.game_state.board()[changed].board_position();
+            for dy in -2..=2 {
+                for dx in -2..=2 {
+                    let offset = glam::Vec2::new(dx as f32, dy as f32);
+                    let Some(other) = self.game_state.tile_index(position + offset) else {
+                        continue;
+                    };
+                    if other == changed
+                        || self.known[other] != Known::Open
+                        || self.numbers[other] == 0
+                    {
+                        continue;
+                    }
+                    let (other_tiles, other_mines) = self.constraint(other);
+                    let found = self.subset(&tiles, mines, &other_tiles, other_mines)
+                        || self.subset(&other_tiles, other_mines, &tiles, mines);
+                    if found {
+                        // it may have more to give once the new tiles settle
+                        if !self.is_dirty[changed] {
+                            self.is_dirty[changed] = true;
+                            self.dirty.push(changed);
+                        }
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    // applies the subset rule when `small` is inside `big`
+    fn subset(
+        &mut self,
+        small: &[usize],
+        small_mines: usize,
+        big: &[usize],
+        big_mines: usize,
+    ) -> bool {
+        if small.is_empty() || !small.iter().all(|tile| big.contains(tile)) {
+            return false;
+        }
+        let rest: Vec<usize> = big
+            .iter()
+            .copied()
+            .filter(|tile| !small.contains(tile))
+            .collect();
+        let rest_mines = big_mines.saturating_sub(small_mines);
+        if rest.is_empty() || (rest_mines != 0 && rest_mines != rest.len()) {
+            return false;
+        }
+        self.solution.subset_steps += 1;
+        for tile in rest {
+            if rest_mines == 0 {
+                self.solution.deductions += 1;
+                self.open(tile);
+            } else {
+                self.flag(tile);
+            }
+        }
+        true
+    }
+
+    // a covered safe tile, next to the newest open number like a player would pick
+    fn next_safe(&mut self) -> usize {
+        let board = self.game_state.board();
+        while let Some(&number) = self.edge.last() {
+            let safe = self.neighbors(number).into_iter().find(|neighbor| {
+                self.known[*neighbor] == Known::Covered && !board[*neighbor].has_mine()
+            });
+            match safe {
+                Some(tile) => return tile,
+                None => {
+                    self.edge.pop();
+                }
+            }
+        }
+        while self.known[self.guess_cursor] != Known::Covered || board[self.guess_cursor].has_mine()
+        {
+            self.guess_cursor += 1;
+        }
+        self.guess_cursor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GameConfig;
+    use crate::game_objects::create_minefield;
+
+    #[test]
+    fn solves_every_board_and_is_repeatable() {
+        let config = GameConfig {
+            width: 16,
+            length: 16,
+            mines: 40,
+            shape: None,
+            ..Default::default()
+        };
+        for seed in 0..20 {
+            let game_state = create_minefield(&config, None, seed);
+            let solution = solve(&game_state, None);
+            assert_eq!(solution, solve(&game_state, None));
+            assert!(solution.deductions > 0);
+        }
+    }
+
+    #[test]
+    fn an_empty_board_is_easy() {
+        let config = GameConfig {
+            width: 9,
+            length: 9,
+            mines: 1,
+            shape: None,
+            ..Default::default()
+        };
+        let game_state = create_minefield(&config, None, 3);
+        assert_eq!(solve(&game_state, None).rating(), Rating::Easy);
+    }
+}
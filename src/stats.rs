@@ -0,0 +1,362 @@
+use crate::analysis::GameStats;
+use crate::config::{GameConfig, Rules};
+use crate::GamePhase;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Bumped whenever the stats format changes.
+pub const STATS_VERSION: u32 = 1;
+/// Where every finished game is kept.
+pub const STATS_FILE: &str = "stats.toml";
+/// Categories the stats screen steps through, custom boards and shapes share one.
+pub const CATEGORIES: [&str; 4] = ["BEGINNER", "INTERMEDIATE", "EXPERT", "CUSTOM"];
+const BEST_TIMES: usize = 3;
+const HISTOGRAM_BARS: usize = 5;
+const HISTOGRAM_WIDTH: usize = 20;
+
+/// One finished game as it's stored. There are no hints yet, they get a
+/// field here once there are.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GameRecord {
+    // unix seconds
+    pub finished_at: u64,
+    pub category: String,
+    pub width: u32,
+    pub length: u32,
+    pub mines: u32,
+    pub shape: Option<String>,
+    pub rules: Rules,
+    pub outcome: GamePhase,
+    pub time_ms: u64,
+    pub bbbv: u32,
+    pub bbbv_solved: u32,
+    pub clicks: u32,
+    pub efficiency: f32,
+    #[serde(default)]
+    pub undos: u32,
+    // hex, a u64 doesn't always fit in a toml integer
+    pub seed: String,
+}
+
+impl GameRecord {
+    pub fn new(stats: &GameStats, config: &GameConfig, undos: u32) -> GameRecord {
+        let category = if config.shape.is_some() {
+            "CUSTOM"
+        } else {
+            config.difficulty.label()
+        };
+        Self {
+            finished_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs()),
+            category: String::from(category),
+            width: config.width,
+            length: config.length,
+            mines: config.mines,
+            shape: config.shape.clone(),
+            rules: config.rules,
+            outcome: stats.outcome,
+            time_ms: stats.time.as_millis() as u64,
+            bbbv: stats.bbbv.total,
+            bbbv_solved: stats.bbbv.solved,
+            clicks: stats.clicks,
+            efficiency: stats.efficiency(),
+            undos,
+            seed: format!("{:016x}", stats.seed),
+        }
+    }
+
+    pub fn won(&self) -> bool {
+        self.outcome == GamePhase::Won
+    }
+
+    pub fn time(&self) -> Duration {
+        Duration::from_millis(self.time_ms)
+    }
+}
+
+// what the stats file looks like, games are appended as [[games]] tables
+#[derive(Serialize, Deserialize)]
+struct StatsFile {
+    version: u32,
+    #[serde(default)]
+    games: Vec<GameRecord>,
+}
+
+#[derive(Serialize)]
+struct AppendedGame<'a> {
+    games: [&'a GameRecord; 1],
+}
+
+/// Every finished game, kept in a toml file that only ever grows at the end,
+/// so a crash can lose at most the game being written.
+pub struct StatsDb {
+    path: PathBuf,
+    games: Vec<GameRecord>,
+}
+
+impl StatsDb {
+    /// Reads the stats at `path`, an empty database if there's no file yet.
+    pub fn open(path: impl AsRef<Path>) -> Result<StatsDb> {
+        let path = path.as_ref().to_path_buf();
+        let games = match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                let file: StatsFile = toml::from_str(&text)
+                    .with_context(|| format!("reading '{}'", path.display()))?;
+                file.games
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e).with_context(|| format!("reading '{}'", path.display())),
+        };
+        Ok(Self { path, games })
+    }
+
+    pub fn games(&self) -> &[GameRecord] {
+        &self.games
+    }
+
+    /// Adds a game to the file. Returns true if it's the fastest win of its category.
+    pub fn record(&mut self, record: GameRecord) -> Result<bool> {
+        let mut text = String::new();
+        if !self.path.exists() {
+            text += &format!("version = {}\n", STATS_VERSION);
+        }
+        text += "\n";
+        text += &toml::to_string(&AppendedGame { games: [&record] })?;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("opening '{}'", self.path.display()))?;
+        file.write_all(text.as_bytes())
+            .with_context(|| format!("writing '{}'", self.path.display()))?;
+
+        let personal_best = record.won()
+            && self
+                .games
+                .iter()
+                .filter(|game| game.won() && game.category == record.category)
+                .all(|game| game.time_ms > record.time_ms);
+        self.games.push(record);
+        Ok(personal_best)
+    }
+
+    pub fn summary(&self, category: &str) -> Summary {
+        Summary::new(
+            self.games
+                .iter()
+                .filter(|game| game.category == category)
+                .collect(),
+        )
+    }
+}
+
+/// Stats of one category of games.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Summary {
+    pub played: u32,
+    pub won: u32,
+    pub best_times: Vec<Duration>,
+    pub average_win: Option<Duration>,
+    pub average_bbbv_per_second: f32,
+    pub average_efficiency: f32,
+    pub streak: u32,
+    pub best_streak: u32,
+    // (bucket start, wins in it), buckets are the same width
+    pub histogram: Vec<(Duration, u32)>,
+}
+
+impl Summary {
+    fn new(games: Vec<&GameRecord>) -> Summary {
+        let wins: Vec<&GameRecord> = games.iter().copied().filter(|game| game.won()).collect();
+        let mut times: Vec<Duration> = wins.iter().map(|game| game.time()).collect();
+        times.sort();
+
+        let mut streak = 0;
+        let mut best_streak = 0;
+        for game in &games {
+            streak = if game.won() { streak + 1 } else { 0 };
+            best_streak = best_streak.max(streak);
+        }
+
+        let average = |value: fn(&GameRecord) -> f32| {
+            if wins.is_empty() {
+                0.0
+            } else {
+                wins.iter().map(|game| value(game)).sum::<f32>() / wins.len() as f32
+            }
+        };
+        Self {
+            played: games.len() as u32,
+            won: wins.len() as u32,
+            best_times: times.iter().take(BEST_TIMES).copied().collect(),
+            average_win: (!times.is_empty())
+                .then(|| times.iter().sum::<Duration>() / times.len() as u32),
+            average_bbbv_per_second: average(|game| {
+                let seconds = game.time().as_secs_f32();
+                if seconds > 0.0 {
+                    game.bbbv_solved as f32 / seconds
+                } else {
+                    0.0
+                }
+            }),
+            average_efficiency: average(|game| game.efficiency),
+            streak,
+            best_streak,
+            histogram: histogram(&times),
+        }
+    }
+
+    pub fn win_rate(&self) -> f32 {
+        if self.played > 0 {
+            self.won as f32 / self.played as f32 * 100.0
+        } else {
+            0.0
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        if self.played == 0 {
+            return vec![String::from("NO GAMES YET")];
+        }
+        let best: Vec<String> = self.best_times.iter().map(|time| seconds(*time)).collect();
+        let mut lines = vec![
+            format!(
+                "PLAYED: {}  WON: {}  WIN RATE: {:.0}%",
+                self.played,
+                self.won,
+                self.win_rate()
+            ),
+            format!("STREAK: {}  BEST STREAK: {}", self.streak, self.best_streak),
+            format!(
+                "BEST TIMES: {}",
+                if best.is_empty() {
+                    String::from("-")
+                } else {
+                    best.join("  ")
+                }
+            ),
+            format!(
+                "AVERAGE WIN: {}",
+                self.average_win.map_or(String::from("-"), seconds)
+            ),
+            format!(
+                "AVERAGE 3BV/S: {:.2}  EFFICIENCY: {:.0}%",
+                self.average_bbbv_per_second, self.average_efficiency
+            ),
+        ];
+        let most = self
+            .histogram
+            .iter()
+            .map(|(_, wins)| *wins)
+            .max()
+            .unwrap_or(0);
+        for (start, wins) in &self.histogram {
+            let bar = (*wins as usize * HISTOGRAM_WIDTH).div_ceil(most.max(1) as usize);
+            lines.push(format!(
+                "{:>5}S {} {}",
+                start.as_secs(),
+                "#".repeat(bar),
+                wins
+            ));
+        }
+        lines
+    }
+}
+
+// win times split into equal buckets of whole seconds, from the fastest to the slowest
+fn histogram(sorted_times: &[Duration]) -> Vec<(Duration, u32)> {
+    let (Some(fastest), Some(slowest)) = (sorted_times.first(), sorted_times.last()) else {
+        return Vec::new();
+    };
+    let first = fastest.as_secs();
+    let width = ((slowest.as_secs() - first) / HISTOGRAM_BARS as u64 + 1).max(1);
+    let mut bars: Vec<(Duration, u32)> = (0..HISTOGRAM_BARS as u64)
+        .map(|bar| (Duration::from_secs(first + bar * width), 0))
+        .collect();
+    for time in sorted_times {
+        let bar = ((time.as_secs() - first) / width) as usize;
+        bars[bar.min(HISTOGRAM_BARS - 1)].1 += 1;
+    }
+    bars
+}
+
+fn seconds(time: Duration) -> String {
+    format!("{:.2}S", time.as_secs_f32())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(category: &str, won: bool, time_ms: u64) -> GameRecord {
+        GameRecord {
+            finished_at: 0,
+            category: String::from(category),
+            width: 9,
+            length: 9,
+            mines: 10,
+            shape: None,
+            rules: Rules::Classic,
+            outcome: if won { GamePhase::Won } else { GamePhase::Lost },
+            time_ms,
+            bbbv: 20,
+            bbbv_solved: if won { 20 } else { 5 },
+            clicks: 25,
+            efficiency: 80.0,
+            undos: 0,
+            seed: String::from("0000000000000007"),
+        }
+    }
+
+    fn temp_db(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "minesweeper-stats-{}-{}.toml",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn games_survive_reopening_and_flag_personal_bests() {
+        let path = temp_db("reopen");
+        let mut db = StatsDb::open(&path).unwrap();
+        assert!(db.record(record("BEGINNER", true, 9000)).unwrap());
+        assert!(!db.record(record("BEGINNER", true, 9500)).unwrap());
+        assert!(!db.record(record("BEGINNER", false, 2000)).unwrap());
+        assert!(db.record(record("EXPERT", true, 90000)).unwrap());
+        assert!(db.record(record("BEGINNER", true, 8000)).unwrap());
+
+        let reopened = StatsDb::open(&path).unwrap();
+        assert_eq!(reopened.games(), db.games());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn summary_counts_streaks_and_times() {
+        let path = temp_db("summary");
+        let mut db = StatsDb::open(&path).unwrap();
+        for (won, time) in [(true, 12000), (true, 10000), (false, 3000), (true, 30000)] {
+            db.record(record("BEGINNER", won, time)).unwrap();
+        }
+        let summary = db.summary("BEGINNER");
+        assert_eq!(summary.played, 4);
+        assert_eq!(summary.won, 3);
+        assert_eq!(summary.win_rate(), 75.0);
+        assert_eq!(summary.streak, 1);
+        assert_eq!(summary.best_streak, 2);
+        assert_eq!(summary.best_times[0], Duration::from_secs(10));
+        assert_eq!(summary.average_win, Some(Duration::from_secs(52) / 3));
+        assert_eq!(
+            summary.histogram.iter().map(|(_, wins)| wins).sum::<u32>(),
+            3
+        );
+        assert_eq!(db.summary("EXPERT"), Summary::default());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
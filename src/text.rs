@@ -0,0 +1,227 @@
+use crate::graphics_and_window::tex_from_grid;
+use crate::instance::{Instance, InstanceRaw};
+use glam::{Vec2, Vec4};
+use std::collections::HashMap;
+
+// glyphs are 5x7 pixels inside a 6x8 box, the spare column/row is the spacing
+pub const GLYPH_ASPECT: f32 = 6.0 / 8.0;
+pub const LINE_SPACING: f32 = 1.25;
+// the sprite holding the cell of the first glyph, the others follow it row by row
+const GLYPHS_SPRITE: &str = "Glyphs";
+
+// each row is 5 bits, most significant bit is the leftmost pixel
+const FONT: &[(char, [u8; 7])] = &[
+    (' ', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('!', [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04]),
+    ('"', [0x0A, 0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00]),
+    ('#', [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A]),
+    ('%', [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03]),
+    ('\'', [0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00]),
+    ('(', [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02]),
+    (')', [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08]),
+    ('*', [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00]),
+    ('+', [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00]),
+    (',', [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08]),
+    ('-', [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C]),
+    ('/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+    ('0', [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E]),
+    ('1', [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('2', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F]),
+    ('3', [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E]),
+    ('4', [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02]),
+    ('5', [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E]),
+    ('6', [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E]),
+    ('7', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E]),
+    ('9', [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C]),
+    (':', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00]),
+    (';', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08]),
+    ('<', [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02]),
+    ('=', [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00]),
+    ('>', [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08]),
+    ('?', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04]),
+    ('A', [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11]),
+    ('B', [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E]),
+    ('C', [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E]),
+    ('D', [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C]),
+    ('E', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F]),
+    ('F', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10]),
+    ('G', [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F]),
+    ('H', [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('I', [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F]),
+    ('M', [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('P', [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10]),
+    ('Q', [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D]),
+    ('R', [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11]),
+    ('S', [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E]),
+    ('T', [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A]),
+    ('X', [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04]),
+    ('Z', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F]),
+    ('[', [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E]),
+    (']', [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E]),
+    ('_', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F]),
+    ('|', [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+];
+
+fn glyph_slot(character: char) -> usize {
+    let character = character.to_ascii_uppercase();
+    FONT.iter()
+        .position(|(glyph, _)| *glyph == character)
+        .unwrap_or_else(|| glyph_slot('?'))
+}
+
+/// Cells the font is drawn into in an atlas cut into a `grid` x `grid` grid, the
+/// bottom rows. [left, top, right, bottom] like a theme's sprites.
+pub fn glyph_cells(grid: u32) -> [u16; 4] {
+    let rows = (FONT.len() as u32).div_ceil(grid);
+    [0, (grid - rows) as u16, grid as u16, grid as u16]
+}
+
+fn glyph_cell(slot: usize, grid: u32) -> (u32, u32) {
+    let first_row = glyph_cells(grid)[1] as u32;
+    (slot as u32 % grid, first_row + slot as u32 / grid)
+}
+
+/// Adds the sprite text_instances finds the glyphs with to a theme's sprites.
+pub fn add_glyph_sprite(sprites: &mut HashMap<String, Vec4>, grid: u32) {
+    let (cell_x, cell_y) = glyph_cell(0, grid);
+    let [x, y] = [cell_x as u16, cell_y as u16];
+    sprites.insert(
+        String::from(GLYPHS_SPRITE),
+        tex_from_grid([x, y, x + 1, y + 1], grid),
+    );
+}
+
+/// Draws the built in font into the bottom rows of the spritesheet so text can
+/// go through the same texture and pipeline as every other sprite.
+pub fn stamp_glyph_atlas(image: &mut image::RgbaImage, grid: u32) {
+    let cell = image.width() / grid;
+    let scale = cell / 8;
+    for (slot, (_, rows)) in FONT.iter().enumerate() {
+        let (cell_x, cell_y) = glyph_cell(slot, grid);
+        for y in 0..cell {
+            for x in 0..cell {
+                image.put_pixel(cell_x * cell + x, cell_y * cell + y, image::Rgba([0; 4]));
+            }
+        }
+        draw_glyph(
+            image,
+            rows,
+            (cell_x * cell, cell_y * cell),
+            scale,
+            image::Rgba([255; 4]),
+        );
+    }
+}
+
+/// Draws one glyph centered in a spritesheet cell on top of whatever is there.
+pub fn stamp_glyph(
+    image: &mut image::RgbaImage,
+    character: char,
+    (cell_x, cell_y): (u32, u32),
+    color: image::Rgba<u8>,
+    grid: u32,
+) {
+    let cell = image.width() / grid;
+    let scale = cell / 8;
+    let (_, rows) = FONT[glyph_slot(character)];
+    let origin = (
+        cell_x * cell + (cell - 5 * scale) / 2,
+        cell_y * cell + (cell - 7 * scale) / 2,
+    );
+    draw_glyph(image, &rows, origin, scale, color);
+}
+
+fn draw_glyph(
+    image: &mut image::RgbaImage,
+    rows: &[u8; 7],
+    (origin_x, origin_y): (u32, u32),
+    scale: u32,
+    color: image::Rgba<u8>,
+) {
+    for (row, bits) in rows.iter().enumerate() {
+        for col in 0..5 {
+            if bits & (0x10 >> col) == 0 {
+                continue;
+            }
+            for y in 0..scale {
+                for x in 0..scale {
+                    image.put_pixel(
+                        origin_x + col * scale + x,
+                        origin_y + row as u32 * scale + y,
+                        color,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Texture coordinates of a glyph, `glyphs` is the cell of the first one.
+pub fn glyph_bounds(character: char, glyphs: Vec4) -> Vec4 {
+    // the atlas is square, so one cell is as wide as it is tall
+    let cell = glyphs.w - glyphs.y;
+    let columns = (1.0 / cell).round() as usize;
+    let slot = glyph_slot(character);
+    let left = glyphs.x + (slot % columns) as f32 * cell;
+    let top = glyphs.y + (slot / columns) as f32 * cell;
+    Vec4::new(left, top, left + GLYPH_ASPECT * cell, top + cell)
+}
+
+/// Width and height of a block of text in the same units as `height`.
+pub fn text_size(text: &str, height: f32) -> Vec2 {
+    let columns = text
+        .lines()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    let lines = text.lines().count().max(1);
+    Vec2::new(
+        columns as f32 * height * GLYPH_ASPECT,
+        height + (lines - 1) as f32 * height * LINE_SPACING,
+    )
+}
+
+/// One instance per glyph, `origin` is the bottom left corner of the first line.
+/// Later lines go downwards, so screen space text wants y to point up.
+pub fn text_instances(
+    text: &str,
+    origin: Vec2,
+    height: f32,
+    z_index: f32,
+    sprites: &HashMap<String, Vec4>,
+) -> Vec<InstanceRaw> {
+    let glyphs = *sprites.get(GLYPHS_SPRITE).expect("theme adds the glyphs");
+    let advance = height * GLYPH_ASPECT;
+    let mut instances = Vec::with_capacity(text.len());
+    for (line_index, line) in text.lines().enumerate() {
+        let baseline = origin.y - line_index as f32 * height * LINE_SPACING;
+        for (col, character) in line.chars().enumerate() {
+            if character == ' ' {
+                continue;
+            }
+            instances.push(Instance::to_raw(
+                Vec2::new(
+                    origin.x + (col as f32 + 0.5) * advance,
+                    baseline + height / 2.0,
+                ),
+                0.0,
+                Vec2::new(advance, height),
+                z_index,
+                glyph_bounds(character, glyphs),
+                0,
+            ));
+        }
+    }
+    instances
+}
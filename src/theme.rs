@@ -0,0 +1,359 @@
+use crate::assets::{locator, normalize, placeholder_atlas, AssetError};
+use crate::game_objects::{add_tile_state_sprites, stamp_tile_states, tile_state_cells};
+use crate::graphics_and_window::tex_from_grid;
+use crate::text::{add_glyph_sprite, glyph_cells, stamp_glyph_atlas};
+use glam::Vec4;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+
+pub const THEME_DIR: &str = "themes";
+pub const DEFAULT_THEME: &str = "themes/default/theme.toml";
+const MANIFEST_NAME: &str = "theme.toml";
+// the placeholder atlas is laid out like the default theme
+const PLACEHOLDER_MANIFEST: &str = include_str!("../themes/default/theme.toml");
+// sprites the game draws with, a theme without them can't be used
+const REQUIRED_SPRITES: [&str; 14] = [
+    "Tiles", "Flags", "Mines", "Square", "Circle", "0", "1", "2", "3", "4", "5", "6", "7", "8",
+];
+// the font and tile states take the bottom 5 rows of a 16 grid, and the cells have
+// to fit the 8x8 pixel glyphs at any atlas size a grid of 256 allows
+const MIN_GRID: u32 = 16;
+const MAX_GRID: u32 = 256;
+
+// what theme.toml looks like, see themes/default/theme.toml
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    name: String,
+    // relative to the manifest
+    atlas: String,
+    // the atlas is cut into grid x grid cells, sprite rects are in cells
+    grid: u32,
+    #[serde(default)]
+    background: [f64; 3],
+    sprites: HashMap<String, [u16; 4]>,
+}
+
+/// A sprite atlas plus the names of the sprites in it, loaded from a theme.toml.
+pub struct Theme {
+    pub name: String,
+    // asset path of the manifest
+    pub manifest: String,
+    // asset path of the atlas, None for the built in placeholder
+    pub atlas: Option<String>,
+    pub background: wgpu::Color,
+    grid: u32,
+    sprites: HashMap<String, Vec4>,
+}
+
+impl Theme {
+    pub fn load(manifest: &str) -> Result<Theme, AssetError> {
+        let text = locator().read_to_string(manifest)?;
+        let parsed = parse_manifest(manifest, &text)?;
+        let folder = Path::new(manifest).parent().unwrap_or(Path::new(""));
+        let atlas = normalize(&folder.join(&parsed.atlas).to_string_lossy());
+        Ok(Self::from_manifest(manifest, parsed, Some(atlas)))
+    }
+
+    /// The theme and its atlas, or the built in placeholder if either is missing or broken.
+    pub fn load_or_placeholder(manifest: &str) -> (Theme, image::RgbaImage) {
+        let theme = Theme::load(manifest).unwrap_or_else(|e| {
+            eprintln!("Error loading theme: {}", e);
+            Theme::placeholder()
+        });
+        match theme.load_atlas() {
+            Ok(atlas) => (theme, atlas),
+            Err(e) => {
+                eprintln!("Error loading atlas for theme '{}': {}", theme.name, e);
+                let theme = Theme::placeholder();
+                let atlas = theme.load_atlas().expect("placeholder atlas is built in");
+                (theme, atlas)
+            }
+        }
+    }
+
+    /// Built in theme that needs no files at all, for when nothing else loads.
+    pub fn placeholder() -> Theme {
+        let parsed = parse_manifest("placeholder", PLACEHOLDER_MANIFEST)
+            .expect("the default theme is checked when it's embedded");
+        let mut theme = Self::from_manifest(DEFAULT_THEME, parsed, None);
+        theme.name = String::from("Placeholder");
+        theme
+    }
+
+    fn from_manifest(manifest: &str, parsed: Manifest, atlas: Option<String>) -> Theme {
+        let mut sprites: HashMap<String, Vec4> = parsed
+            .sprites
+            .iter()
+            .map(|(name, coords)| (name.clone(), tex_from_grid(*coords, parsed.grid)))
+            .collect();
+        add_tile_state_sprites(&mut sprites, parsed.grid);
+        add_glyph_sprite(&mut sprites, parsed.grid);
+        let [r, g, b] = parsed.background;
+        Self {
+            name: parsed.name,
+            manifest: String::from(manifest),
+            atlas,
+            background: wgpu::Color { r, g, b, a: 1.0 },
+            grid: parsed.grid,
+            sprites,
+        }
+    }
+
+    pub fn sprites(&self) -> &HashMap<String, Vec4> {
+        &self.sprites
+    }
+
+    /// The atlas with the font and tile states drawn into it, ready for the GPU.
+    pub fn load_atlas(&self) -> Result<image::RgbaImage, AssetError> {
+        let mut atlas = match &self.atlas {
+            Some(path) => {
+                let bytes = locator().read(path)?;
+                let atlas = image::load_from_memory(&bytes)
+                    .map_err(|e| AssetError::malformed(path, e))?
+                    .to_rgba8();
+                // the font and tile states are drawn into cells, in 8x8 pixel blocks
+                let cells = 8 * self.grid;
+                if atlas.width() != atlas.height() || atlas.width() % cells != 0 {
+                    return Err(AssetError::malformed(
+                        path,
+                        format!(
+                            "atlas is {}x{}, a grid of {} needs it square and a multiple of {}",
+                            atlas.width(),
+                            atlas.height(),
+                            self.grid,
+                            cells
+                        ),
+                    ));
+                }
+                atlas
+            }
+            None => placeholder_atlas(),
+        };
+        stamp_glyph_atlas(&mut atlas, self.grid);
+        stamp_tile_states(&mut atlas, &self.sprites, self.grid);
+        Ok(atlas)
+    }
+}
+
+fn parse_manifest(path: &str, text: &str) -> Result<Manifest, AssetError> {
+    let parsed: Manifest = toml::from_str(text).map_err(|e| AssetError::malformed(path, e))?;
+    let missing: Vec<&str> = REQUIRED_SPRITES
+        .into_iter()
+        .filter(|name| !parsed.sprites.contains_key(*name))
+        .collect();
+    if !missing.is_empty() {
+        return Err(AssetError::malformed(
+            path,
+            format!("missing sprites {:?}", missing),
+        ));
+    }
+    let grid = parsed.grid;
+    if !(MIN_GRID..=MAX_GRID).contains(&grid) {
+        return Err(AssetError::malformed(
+            path,
+            format!(
+                "grid is {}, it goes from {} to {}",
+                grid, MIN_GRID, MAX_GRID
+            ),
+        ));
+    }
+    // cells drawn over when the atlas loads, sprites can't use them
+    let reserved = [
+        ("tile states", tile_state_cells(grid)),
+        ("font", glyph_cells(grid)),
+    ];
+    // sorted so the error is the same every time
+    let mut sprites: Vec<(&String, &[u16; 4])> = parsed.sprites.iter().collect();
+    sprites.sort();
+    for (name, rect) in sprites {
+        let [left, top, right, bottom] = *rect;
+        if left >= right || top >= bottom || right as u32 > grid || bottom as u32 > grid {
+            return Err(AssetError::malformed(
+                path,
+                format!(
+                    "sprite '{}' {:?} isn't a rect inside the {}x{} grid",
+                    name, rect, grid, grid
+                ),
+            ));
+        }
+        let overlaps = |[l, t, r, b]: [u16; 4]| left < r && l < right && top < b && t < bottom;
+        if let Some((what, cells)) = reserved.iter().find(|(_, cells)| overlaps(*cells)) {
+            return Err(AssetError::malformed(
+                path,
+                format!(
+                    "sprite '{}' {:?} overlaps {:?}, the {} are drawn there",
+                    name, rect, cells, what
+                ),
+            ));
+        }
+    }
+    Ok(parsed)
+}
+
+/// Theme manifests in the themes folder, embedded and overridden ones together.
+pub fn available_themes() -> Vec<String> {
+    let mut themes: Vec<String> = locator()
+        .list_folders(THEME_DIR)
+        .into_iter()
+        .map(|folder| format!("{}/{}", folder, MANIFEST_NAME))
+        .collect();
+    themes.retain(|theme| locator().exists(theme));
+    themes
+}
+
+/// Watches a theme's manifest and atlas so artists see their changes without restarting.
+pub struct ThemeWatcher {
+    // dropping the watcher stops it
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    files: Vec<PathBuf>,
+}
+
+impl ThemeWatcher {
+    /// None when nothing of the theme is on disk, embedded files never change.
+    pub fn new(theme: &Theme) -> notify::Result<Option<ThemeWatcher>> {
+        let files: Vec<PathBuf> = std::iter::once(&theme.manifest)
+            .chain(theme.atlas.as_ref())
+            .filter_map(|path| locator().disk_path(path))
+            .map(|path| absolute(&path))
+            .collect();
+        if files.is_empty() {
+            return Ok(None);
+        }
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        // editors often save by replacing the file, so watch the folders instead
+        let mut folders: Vec<&Path> = files.iter().filter_map(|file| file.parent()).collect();
+        folders.dedup();
+        for folder in folders {
+            watcher.watch(folder, RecursiveMode::NonRecursive)?;
+        }
+        Ok(Some(Self {
+            _watcher: watcher,
+            events,
+            files,
+        }))
+    }
+
+    /// True when the manifest or atlas changed since the last call.
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        for event in self.events.try_iter().flatten() {
+            if (event.kind.is_modify() || event.kind.is_create())
+                && event.paths.iter().any(|path| self.files.contains(path))
+            {
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPRITES: &str = "\
+[sprites]
+Square = [0, 0, 1, 1]
+Circle = [1, 0, 2, 1]
+Mines = [0, 1, 1, 2]
+Tiles = [0, 2, 1, 3]
+Flags = [2, 1, 3, 2]
+0 = [0, 0, 1, 1]
+1 = [2, 0, 3, 1]
+2 = [3, 0, 4, 1]
+3 = [4, 0, 5, 1]
+4 = [5, 0, 6, 1]
+5 = [6, 0, 7, 1]
+6 = [7, 0, 8, 1]
+7 = [8, 0, 9, 1]
+8 = [9, 0, 10, 1]
+";
+
+    fn manifest(grid: u32, extra: &str) -> Result<Manifest, AssetError> {
+        parse_manifest(
+            "test",
+            &format!(
+                "name = \"Test\"\natlas = \"atlas.png\"\ngrid = {}\n{}{}",
+                grid, SPRITES, extra
+            ),
+        )
+    }
+
+    #[test]
+    fn built_in_themes_load() {
+        for theme in available_themes() {
+            if let Err(e) = Theme::load(&theme) {
+                panic!("{}", e)
+            }
+        }
+        assert!(manifest(32, "").is_ok());
+    }
+
+    #[test]
+    fn sprites_over_the_reserved_cells_are_rejected() {
+        // a 32 grid has the font in rows 30 and 31 and the tile states in row 29
+        let error = manifest(32, "Art = [1, 29, 2, 30]\n").err().unwrap();
+        assert!(error.to_string().contains("tile states"), "{}", error);
+        let error = manifest(32, "Art = [30, 28, 32, 31]\n").err().unwrap();
+        assert!(error.to_string().contains("font"), "{}", error);
+        // next to them is fine
+        assert!(manifest(32, "Art = [4, 3, 32, 29]\n").is_ok());
+        assert!(manifest(32, "Art = [0, 0, 33, 1]\n").is_err());
+        assert!(manifest(8, "").is_err());
+    }
+
+    #[test]
+    fn missing_themes_and_atlases_fall_back_to_the_placeholder() {
+        let (theme, _) = Theme::load_or_placeholder("themes/nope/theme.toml");
+        assert_eq!(theme.name, "Placeholder");
+
+        let dir = std::env::temp_dir().join(format!("minesweeper-theme-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let manifest = dir.join(MANIFEST_NAME);
+        let text = format!(
+            "name = \"Lost\"\natlas = \"missing.png\"\ngrid = 32\n{}",
+            SPRITES
+        );
+        std::fs::write(&manifest, text).unwrap();
+        let manifest = manifest.to_string_lossy();
+        assert_eq!(Theme::load(&manifest).unwrap().name, "Lost");
+        let (theme, atlas) = Theme::load_or_placeholder(&manifest);
+        assert_eq!(theme.name, "Placeholder");
+        assert_eq!(atlas.dimensions(), placeholder_atlas().dimensions());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_16_grid_theme_loads() {
+        let parsed = manifest(16, "Art = [10, 0, 16, 11]\n").unwrap();
+        let theme = Theme::from_manifest("test", parsed, None);
+        assert_eq!(
+            theme.sprites()["Flags"],
+            Vec4::new(2.0, 1.0, 3.0, 2.0) / 16.0
+        );
+        // the font takes the bottom 4 rows and the tile states the one above
+        assert_eq!(
+            theme.sprites()["Glyphs"],
+            Vec4::new(0.0, 12.0, 1.0, 13.0) / 16.0
+        );
+        assert_eq!(
+            theme.sprites()["Exploded"],
+            Vec4::new(3.0, 11.0, 4.0, 12.0) / 16.0
+        );
+        let error = manifest(16, "Art = [0, 11, 1, 12]\n").err().unwrap();
+        assert!(error.to_string().contains("tile states"), "{}", error);
+        // the placeholder atlas is 1024 pixels wide, cells of 64 fit the glyphs
+        let atlas = theme.load_atlas().unwrap();
+        assert_eq!(atlas.width() / theme.grid, 64);
+    }
+}
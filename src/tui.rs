@@ -0,0 +1,451 @@
+use crate::board_mask::BoardMask;
+use crate::config::{Difficulty, GameConfig};
+use crate::game_objects::{create_minefield, prepare_first_click};
+use crate::palette::Palette;
+use crate::{GameState, TileVisual};
+use anyhow::Result;
+use crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
+    MouseButton, MouseEvent, MouseEventKind,
+};
+use crossterm::style::{
+    Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor,
+};
+use crossterm::{cursor, execute, queue, terminal};
+use glam::Vec2;
+use std::io::Write;
+use std::time::Duration;
+
+// a tile is two columns wide so the board comes out about square
+const CELL_WIDTH: u16 = 2;
+// status and help lines above the board
+const BOARD_TOP: u16 = 2;
+// how often the timer is redrawn when nothing happens
+const TICK: Duration = Duration::from_millis(250);
+// the window takes these from the theme's sprites
+const CLASSIC_DIGITS: [Color; 8] = [
+    Color::Blue,
+    Color::Green,
+    Color::Red,
+    Color::DarkBlue,
+    Color::DarkRed,
+    Color::DarkCyan,
+    Color::DarkMagenta,
+    Color::DarkGrey,
+];
+const HELP: &str =
+    "arrows/hjkl move  space reveal  f flag  c chord  r restart  1-3 difficulty  p palette  q quit";
+
+/// What a key or click asks for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Command {
+    // columns and rows, up is +1 like the board
+    Move(i32, i32),
+    // opens a covered tile or chords an open one, like a left click
+    Reveal,
+    Flag,
+    Chord,
+    Restart,
+    NewGame(Difficulty),
+    Palette,
+    Quit,
+}
+
+fn key_command(key: KeyEvent) -> Option<Command> {
+    match key.code {
+        KeyCode::Left | KeyCode::Char('h') => Some(Command::Move(-1, 0)),
+        KeyCode::Right | KeyCode::Char('l') => Some(Command::Move(1, 0)),
+        KeyCode::Up | KeyCode::Char('k') => Some(Command::Move(0, 1)),
+        KeyCode::Down | KeyCode::Char('j') => Some(Command::Move(0, -1)),
+        KeyCode::Char(' ') | KeyCode::Enter => Some(Command::Reveal),
+        KeyCode::Char('f') => Some(Command::Flag),
+        KeyCode::Char('c') => Some(Command::Chord),
+        KeyCode::Char('r') => Some(Command::Restart),
+        KeyCode::Char('1') => Some(Command::NewGame(Difficulty::Beginner)),
+        KeyCode::Char('2') => Some(Command::NewGame(Difficulty::Intermediate)),
+        KeyCode::Char('3') => Some(Command::NewGame(Difficulty::Expert)),
+        KeyCode::Char('p') => Some(Command::Palette),
+        KeyCode::Char('q') | KeyCode::Esc => Some(Command::Quit),
+        _ => None,
+    }
+}
+
+/// The game in a terminal: a cursor instead of a mouse, the same board and rules as the window.
+struct Tui {
+    config: GameConfig,
+    mask: Option<BoardMask>,
+    game_state: GameState,
+    // (col, row), row 0 at the bottom like the board
+    cursor: (u32, u32),
+    // first column and first row from the top that fit in the terminal
+    scroll: (u32, u32),
+}
+
+impl Tui {
+    // the first board is dealt from `seed` when there is one
+    fn new(config: GameConfig, seed: Option<u64>) -> Tui {
+        let mask = config
+            .shape
+            .as_deref()
+            .and_then(|path| match BoardMask::load(path) {
+                Ok(mask) => Some(mask),
+                Err(e) => {
+                    eprintln!("Error loading board mask '{}': {:?}", path, e);
+                    None
+                }
+            });
+        let mut tui = Self {
+            config,
+            mask,
+            game_state: GameState::empty(),
+            cursor: (0, 0),
+            scroll: (0, 0),
+        };
+        tui.deal(seed.unwrap_or_else(rand::random));
+        tui
+    }
+
+    fn restart(&mut self) {
+        self.deal(rand::random());
+    }
+
+    fn deal(&mut self, seed: u64) {
+        self.game_state = create_minefield(&self.config, self.mask.as_ref(), seed);
+        self.game_state
+            .set_question_marks(self.config.question_marks);
+        self.cursor = (self.width() / 2, self.length() / 2);
+    }
+
+    fn width(&self) -> u32 {
+        self.mask
+            .as_ref()
+            .map_or(self.config.width, |mask| mask.width())
+    }
+
+    fn length(&self) -> u32 {
+        self.mask
+            .as_ref()
+            .map_or(self.config.length, |mask| mask.length())
+    }
+
+    // None on a hole
+    fn cursor_tile(&self) -> Option<usize> {
+        self.game_state
+            .tile_index(Vec2::new(self.cursor.0 as f32, self.cursor.1 as f32))
+    }
+
+    fn apply(&mut self, command: Command) {
+        match command {
+            Command::Move(columns, rows) => {
+                let col = (self.cursor.0 as i32 + columns).clamp(0, self.width() as i32 - 1);
+                let row = (self.cursor.1 as i32 + rows).clamp(0, self.length() as i32 - 1);
+                self.cursor = (col as u32, row as u32);
+            }
+            Command::Reveal => self.reveal(),
+            Command::Flag => self.flag(),
+            Command::Chord => self.chord(),
+            Command::Restart => self.restart(),
+            Command::NewGame(difficulty) => {
+                if let Some((width, length, mines)) = difficulty.preset() {
+                    self.config.width = width;
+                    self.config.length = length;
+                    self.config.mines = mines;
+                    self.config.shape = None;
+                    self.config.match_difficulty();
+                    self.mask = None;
+                }
+                self.restart();
+            }
+            Command::Palette => {
+                let next = Palette::ALL
+                    .iter()
+                    .position(|palette| *palette == self.config.palette)
+                    .map_or(0, |index| (index + 1) % Palette::ALL.len());
+                self.config.palette = Palette::ALL[next];
+            }
+            Command::Quit => {}
+        }
+    }
+
+    fn reveal(&mut self) {
+        let Some(index) = self.cursor_tile() else {
+            return;
+        };
+        if self.game_state.phase().is_over() {
+            return;
+        }
+        let tile = &self.game_state.board()[index];
+        if tile.clicked {
+            self.chord();
+        } else if !tile.flagged {
+            prepare_first_click(&mut self.game_state, self.config.rules, index);
+            self.game_state.count_click();
+            self.game_state.open(index);
+        }
+    }
+
+    fn chord(&mut self) {
+        let Some(index) = self.cursor_tile() else {
+            return;
+        };
+        if self.game_state.phase().is_over() || !self.game_state.board()[index].clicked {
+            return;
+        }
+        self.game_state.count_click();
+        for neighbor in self.game_state.chord_targets(index) {
+            self.game_state.open(neighbor);
+        }
+    }
+
+    fn flag(&mut self) {
+        let Some(index) = self.cursor_tile() else {
+            return;
+        };
+        if self.game_state.phase().is_over() || self.game_state.board()[index].clicked {
+            return;
+        }
+        self.game_state.count_click();
+        self.game_state.toggle_flag(index);
+    }
+
+    /// Moves the cursor to a clicked tile and turns the button into a command.
+    fn mouse(&mut self, mouse: MouseEvent) -> Option<Command> {
+        let command = match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => Command::Reveal,
+            MouseEventKind::Down(MouseButton::Right) => Command::Flag,
+            MouseEventKind::Down(MouseButton::Middle) => Command::Chord,
+            _ => return None,
+        };
+        self.cursor = self.cell_at(mouse.column, mouse.row)?;
+        Some(command)
+    }
+
+    // the board cell drawn at a terminal position
+    fn cell_at(&self, column: u16, line: u16) -> Option<(u32, u32)> {
+        let col = self.scroll.0 + (column / CELL_WIDTH) as u32;
+        let from_top = self.scroll.1 + line.checked_sub(BOARD_TOP)? as u32;
+        if col >= self.width() || from_top >= self.length() {
+            return None;
+        }
+        Some((col, self.length() - 1 - from_top))
+    }
+
+    // keeps the cursor on screen when the board is bigger than the terminal
+    fn follow_cursor(&mut self, columns: u32, lines: u32) {
+        let from_top = self.length() - 1 - self.cursor.1;
+        let follow = |scroll: u32, position: u32, visible: u32| {
+            if position < scroll {
+                position
+            } else if position >= scroll + visible {
+                position + 1 - visible.max(1)
+            } else {
+                scroll
+            }
+        };
+        self.scroll = (
+            follow(self.scroll.0, self.cursor.0, columns),
+            follow(self.scroll.1, from_top, lines),
+        );
+    }
+
+    fn draw(&mut self, out: &mut impl Write) -> Result<()> {
+        let (term_width, term_height) = terminal::size()?;
+        let columns = (term_width / CELL_WIDTH) as u32;
+        let lines = term_height.saturating_sub(BOARD_TOP) as u32;
+        self.follow_cursor(columns, lines);
+
+        let status = format!(
+            "MINES {:03}  TIME {:03}  {}  {}  {}",
+            self.game_state.remaining_mines().clamp(-99, 999),
+            self.game_state.elapsed().as_secs().min(999),
+            self.game_state.phase().label(),
+            self.config.difficulty.label(),
+            self.config.palette.label(),
+        );
+        queue!(
+            out,
+            cursor::MoveTo(0, 0),
+            Print(status),
+            terminal::Clear(terminal::ClearType::UntilNewLine),
+            cursor::MoveTo(0, 1),
+            SetForegroundColor(Color::DarkGrey),
+            Print(HELP),
+            ResetColor,
+            terminal::Clear(terminal::ClearType::UntilNewLine),
+        )?;
+
+        let digits = digit_colors(self.config.palette);
+        for line in 0..lines.min(self.length()) {
+            let from_top = self.scroll.1 + line;
+            if from_top >= self.length() {
+                break;
+            }
+            let row = self.length() - 1 - from_top;
+            queue!(out, cursor::MoveTo(0, BOARD_TOP + line as u16))?;
+            for col in self.scroll.0..(self.scroll.0 + columns).min(self.width()) {
+                let (glyph, foreground, background) = self
+                    .game_state
+                    .tile_index(Vec2::new(col as f32, row as f32))
+                    .map_or((' ', Color::Reset, Color::Reset), |index| {
+                        cell(self.game_state.tile_visual(index), &digits)
+                    });
+                let selected = (col, row) == self.cursor;
+                if selected {
+                    queue!(out, SetAttribute(Attribute::Reverse))?;
+                }
+                queue!(
+                    out,
+                    SetForegroundColor(foreground),
+                    SetBackgroundColor(background),
+                    Print(glyph),
+                    ResetColor,
+                    Print(if selected { "]" } else { " " }),
+                    SetAttribute(Attribute::Reset),
+                )?;
+            }
+            queue!(out, terminal::Clear(terminal::ClearType::UntilNewLine))?;
+        }
+        queue!(out, terminal::Clear(terminal::ClearType::FromCursorDown))?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+fn digit_colors(palette: Palette) -> [Color; 8] {
+    palette.digit_colors().map_or(CLASSIC_DIGITS, |colors| {
+        colors.map(|[r, g, b]| Color::Rgb { r, g, b })
+    })
+}
+
+// character, foreground and background of a tile
+fn cell(visual: TileVisual, digits: &[Color; 8]) -> (char, Color, Color) {
+    match visual {
+        TileVisual::Covered => ('#', Color::DarkGrey, Color::Reset),
+        TileVisual::Flagged => ('F', Color::Red, Color::Reset),
+        TileVisual::Questioned => ('?', Color::Yellow, Color::Reset),
+        TileVisual::Revealed(0) => ('.', Color::DarkGrey, Color::Reset),
+        TileVisual::Revealed(count) => (
+            char::from(b'0' + count),
+            digits[count as usize - 1],
+            Color::Reset,
+        ),
+        TileVisual::Exploded => ('*', Color::White, Color::Red),
+        TileVisual::Mine => ('*', Color::Reset, Color::Reset),
+        TileVisual::WrongFlag => ('X', Color::Red, Color::Reset),
+    }
+}
+
+// raw mode and the alternate screen until it's dropped, a panic puts the terminal back too
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> Result<TerminalGuard> {
+        terminal::enable_raw_mode()?;
+        execute!(
+            std::io::stdout(),
+            terminal::EnterAlternateScreen,
+            EnableMouseCapture,
+            cursor::Hide
+        )?;
+        Ok(TerminalGuard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(
+            std::io::stdout(),
+            cursor::Show,
+            DisableMouseCapture,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Plays in the terminal until q or Escape, the first board from `seed` if there is one.
+pub fn run(config: GameConfig, seed: Option<u64>) -> Result<()> {
+    let mut tui = Tui::new(config, seed);
+    let _terminal = TerminalGuard::enter()?;
+    let mut out = std::io::stdout();
+    loop {
+        tui.draw(&mut out)?;
+        if !event::poll(TICK)? {
+            continue;
+        }
+        let command = match event::read()? {
+            // windows also sends releases
+            Event::Key(key) if key.kind == KeyEventKind::Press => key_command(key),
+            Event::Mouse(mouse) => tui.mouse(mouse),
+            _ => None,
+        };
+        match command {
+            Some(Command::Quit) => return Ok(()),
+            Some(command) => tui.apply(command),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GamePhase;
+
+    fn tui() -> Tui {
+        Tui::new(
+            GameConfig {
+                width: 9,
+                length: 9,
+                mines: 10,
+                shape: None,
+                ..Default::default()
+            },
+            Some(3),
+        )
+    }
+
+    #[test]
+    fn keys_play_by_the_same_rules() {
+        let mut tui = tui();
+        // a seed from the command line deals the same first board every time
+        let mines = |tui: &Tui| -> Vec<bool> {
+            tui.game_state
+                .board()
+                .iter()
+                .map(|tile| tile.has_mine())
+                .collect()
+        };
+        assert_eq!(mines(&tui), mines(&self::tui()));
+        tui.apply(Command::Flag);
+        assert_eq!(tui.game_state.flag_count(), 1);
+        tui.apply(Command::Flag);
+        // the first reveal is never a mine, like in the window
+        tui.apply(Command::Reveal);
+        assert_ne!(tui.game_state.phase(), GamePhase::Lost);
+        let index = tui.cursor_tile().unwrap();
+        assert!(tui.game_state.board()[index].clicked);
+
+        tui.apply(Command::Move(-100, 100));
+        assert_eq!(tui.cursor, (0, 8));
+        assert_eq!(
+            key_command(KeyEvent::from(KeyCode::Char('k'))),
+            Some(Command::Move(0, 1))
+        );
+    }
+
+    #[test]
+    fn clicks_land_on_the_cell_drawn_there() {
+        let mut tui = tui();
+        // the top row is drawn first
+        assert_eq!(tui.cell_at(0, BOARD_TOP), Some((0, 8)));
+        assert_eq!(tui.cell_at(5, BOARD_TOP + 8), Some((2, 0)));
+        assert_eq!(tui.cell_at(0, 0), None);
+        assert_eq!(tui.cell_at(40, BOARD_TOP), None);
+
+        tui.cursor = (8, 0);
+        tui.follow_cursor(4, 4);
+        assert_eq!(tui.scroll, (5, 5));
+        assert_eq!(tui.cell_at(0, BOARD_TOP), Some((5, 3)));
+    }
+}
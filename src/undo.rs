@@ -0,0 +1,323 @@
+use crate::{GamePhase, GameState, MineActiveTrait, Mines};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// Undo steps kept per game, the oldest ones are dropped after this.
+pub const MAX_UNDO: usize = 1000;
+/// Undos a game gets with the charges policy.
+pub const UNDO_CHARGES: u32 = 3;
+
+// a tile's marks packed into a byte
+const CLICKED: u8 = 1;
+const FLAGGED: u8 = 2;
+const QUESTIONED: u8 = 4;
+
+/// How much undo a game allows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UndoPolicy {
+    // ranked games, every click counts
+    Off,
+    // a few undos per game, like an item
+    Charges(u32),
+    // practice games
+    Unlimited,
+}
+
+impl UndoPolicy {
+    pub const ALL: [UndoPolicy; 3] = [
+        UndoPolicy::Off,
+        UndoPolicy::Charges(UNDO_CHARGES),
+        UndoPolicy::Unlimited,
+    ];
+
+    pub fn label(&self) -> String {
+        match self {
+            UndoPolicy::Off => String::from("OFF"),
+            UndoPolicy::Charges(charges) => format!("{} CHARGES", charges),
+            UndoPolicy::Unlimited => String::from("UNLIMITED"),
+        }
+    }
+}
+
+// the parts of a game an action can change besides the tiles
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Counters {
+    phase: GamePhase,
+    flag_count: u32,
+    revealed_count: u32,
+    clicks: u32,
+}
+
+impl Counters {
+    fn of(game_state: &GameState) -> Counters {
+        Self {
+            phase: game_state.phase,
+            flag_count: game_state.flag_count,
+            revealed_count: game_state.revealed_count,
+            clicks: game_state.clicks,
+        }
+    }
+}
+
+/// The board just before an action, taken with `History::before`.
+pub struct Snapshot {
+    marks: Vec<u8>,
+    counters: Counters,
+}
+
+// what one action changed, tiles are (index, before, after)
+struct Change {
+    tiles: Vec<(usize, u8, u8)>,
+    before: Counters,
+    after: Counters,
+}
+
+/// Undo and redo stacks of one game. Only what an action changed is kept, so big
+/// boards don't need a copy of the whole game per step.
+pub struct History {
+    policy: UndoPolicy,
+    // None when the policy has no charges to count
+    charges: Option<u32>,
+    // undos taken this game, kept with its stats
+    used: u32,
+    undo: VecDeque<Change>,
+    redo: Vec<Change>,
+}
+
+impl History {
+    pub fn new(policy: UndoPolicy) -> History {
+        Self {
+            policy,
+            charges: match policy {
+                UndoPolicy::Charges(charges) => Some(charges),
+                _ => None,
+            },
+            used: 0,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    /// Undos left with the charges policy.
+    pub fn charges(&self) -> Option<u32> {
+        self.charges
+    }
+
+    /// Takes the board before an action. None when the action can't be undone:
+    /// undo is off, or it's the first click, which may move mines around.
+    pub fn before(&self, game_state: &GameState) -> Option<Snapshot> {
+        if self.policy == UndoPolicy::Off || game_state.phase == GamePhase::Ready {
+            return None;
+        }
+        Some(Snapshot {
+            marks: game_state.board.iter().map(marks).collect(),
+            counters: Counters::of(game_state),
+        })
+    }
+
+    /// Stores what the action since `before` changed. Any redo steps are gone now.
+    pub fn push(&mut self, before: Snapshot, game_state: &GameState) {
+        let tiles = game_state
+            .board
+            .iter()
+            .zip(before.marks)
+            .enumerate()
+            .filter_map(|(index, (tile, old))| {
+                let new = marks(tile);
+                (new != old).then_some((index, old, new))
+            })
+            .collect();
+        self.redo.clear();
+        self.undo.push_back(Change {
+            tiles,
+            before: before.counters,
+            after: Counters::of(game_state),
+        });
+        if self.undo.len() > MAX_UNDO {
+            self.undo.pop_front();
+        }
+    }
+
+    pub fn used(&self) -> u32 {
+        self.used
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() && self.charges != Some(0)
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Puts back the last action, using up a charge. Returns the tiles it changed.
+    pub fn undo(&mut self, game_state: &mut GameState) -> Option<Vec<usize>> {
+        if !self.can_undo() {
+            return None;
+        }
+        let change = self.undo.pop_back()?;
+        if let Some(charges) = self.charges.as_mut() {
+            *charges -= 1;
+        }
+        self.used += 1;
+        let tiles = restore(game_state, &change, false);
+        self.redo.push(change);
+        Some(tiles)
+    }
+
+    /// Does the last undone action again, redoing doesn't cost a charge.
+    pub fn redo(&mut self, game_state: &mut GameState) -> Option<Vec<usize>> {
+        let change = self.redo.pop()?;
+        let tiles = restore(game_state, &change, true);
+        self.undo.push_back(change);
+        Some(tiles)
+    }
+}
+
+fn marks(tile: &crate::Tiles) -> u8 {
+    let mut marks = 0;
+    if tile.clicked {
+        marks |= CLICKED;
+    }
+    if tile.flagged {
+        marks |= FLAGGED;
+    }
+    if tile.questioned {
+        marks |= QUESTIONED;
+    }
+    marks
+}
+
+// sets the tiles and counters to one side of a change
+fn restore(game_state: &mut GameState, change: &Change, forward: bool) -> Vec<usize> {
+    let counters = if forward { change.after } else { change.before };
+    for (index, before, after) in &change.tiles {
+        let marks = if forward { *after } else { *before };
+        let tile = &mut game_state.board[*index];
+        tile.clicked = marks & CLICKED != 0;
+        tile.flagged = marks & FLAGGED != 0;
+        tile.questioned = marks & QUESTIONED != 0;
+        // a mine is only active once it's been clicked
+        if let Some(Mines::Default(state)) = tile.mine.as_mut() {
+            state.set_active(tile.clicked);
+        }
+    }
+    let now = Instant::now();
+    match (game_state.phase.is_over(), counters.phase.is_over()) {
+        (false, true) => game_state.ended_at = Some(now),
+        (true, false) => {
+            // the time spent looking at the results doesn't count
+            if let (Some(started), Some(ended)) =
+                (game_state.started_at.as_mut(), game_state.ended_at)
+            {
+                *started += now - ended;
+            }
+            game_state.ended_at = None;
+            // that time is skipped already, the pause starts over from here
+            if game_state.paused_at.is_some() {
+                game_state.paused_at = Some(now);
+            }
+        }
+        _ => {}
+    }
+    game_state.phase = counters.phase;
+    game_state.flag_count = counters.flag_count;
+    game_state.revealed_count = counters.revealed_count;
+    game_state.clicks = counters.clicks;
+    change.tiles.iter().map(|(index, _, _)| *index).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GameConfig;
+    use crate::game_objects::create_minefield;
+    use std::time::Duration;
+
+    fn game() -> GameState {
+        let config = GameConfig {
+            width: 9,
+            length: 9,
+            mines: 10,
+            shape: None,
+            ..Default::default()
+        };
+        let mut game_state = create_minefield(&config, None, 7);
+        let safe = (0..game_state.board.len())
+            .find(|index| !game_state.board[*index].has_mine())
+            .unwrap();
+        game_state.open(safe);
+        game_state
+    }
+
+    #[test]
+    fn undo_puts_back_a_loss_and_redo_loses_again() {
+        let mut game_state = game();
+        let mut history = History::new(UndoPolicy::Unlimited);
+        let mine = (0..game_state.board.len())
+            .find(|index| game_state.board[*index].has_mine())
+            .unwrap();
+        let revealed = game_state.revealed_count;
+
+        let before = history.before(&game_state).unwrap();
+        game_state.open(mine);
+        history.push(before, &game_state);
+        assert_eq!(game_state.phase(), GamePhase::Lost);
+
+        assert_eq!(history.undo(&mut game_state), Some(vec![mine]));
+        assert_eq!(game_state.phase(), GamePhase::Playing);
+        assert_eq!(game_state.revealed_count, revealed);
+        assert!(!game_state.board[mine].clicked);
+        assert!(game_state.ended_at.is_none());
+
+        history.redo(&mut game_state);
+        assert_eq!(game_state.phase(), GamePhase::Lost);
+        assert!(game_state.board[mine].clicked);
+    }
+
+    #[test]
+    fn undo_on_the_results_skips_their_time_once() {
+        let mut game_state = game();
+        let mut history = History::new(UndoPolicy::Unlimited);
+        let mine = (0..game_state.board.len())
+            .find(|index| game_state.board[*index].has_mine())
+            .unwrap();
+        let before = history.before(&game_state).unwrap();
+        game_state.open(mine);
+        history.push(before, &game_state);
+
+        // 5 seconds of play, then 4 on the results screen
+        let now = Instant::now();
+        game_state.started_at = Some(now - Duration::from_secs(10));
+        game_state.ended_at = Some(now - Duration::from_secs(5));
+        game_state.paused_at = Some(now - Duration::from_secs(4));
+        history.undo(&mut game_state);
+        game_state.set_paused(false);
+
+        let elapsed = game_state.elapsed().as_secs_f32();
+        assert!((4.9..5.5).contains(&elapsed), "{}", elapsed);
+    }
+
+    #[test]
+    fn charges_run_out_and_off_keeps_nothing() {
+        let mut game_state = game();
+        let covered: Vec<usize> = (0..game_state.board.len())
+            .filter(|index| !game_state.board[*index].clicked)
+            .take(3)
+            .collect();
+        let mut history = History::new(UndoPolicy::Charges(1));
+        for index in &covered[..2] {
+            let before = history.before(&game_state).unwrap();
+            game_state.toggle_flag(*index);
+            history.push(before, &game_state);
+        }
+        assert!(history.undo(&mut game_state).is_some());
+        assert_eq!(game_state.flag_count(), 1);
+        assert_eq!(history.charges(), Some(0));
+        assert!(history.undo(&mut game_state).is_none());
+
+        let off = History::new(UndoPolicy::Off);
+        assert!(off.before(&game_state).is_none());
+    }
+}